@@ -3,16 +3,73 @@
 in VS_OUTPUT
 {
 	flat vec3 Color;
+	vec3 SmoothColor;
 	vec2 TexCoord;
+	vec3 Normal;
+	vec3 ViewPosition;
 } IN;
 
 out vec4 Color;
 
 uniform sampler2D myTexture;
 
+// see model::color::ColorMode for the values
+uniform int colorMode;
+// vertex colors came with the file (scans), so they are blended instead of one per face
+uniform bool smoothColors;
+uniform vec3 palette[16];
+// index of the first triangle of the current draw in the whole mesh
+uniform int firstPrimitive;
+// color of the group/material being drawn
+uniform vec3 submeshColor;
+// blend weights, see model::color::Transition. 0 is all color, 1 all texture
+uniform float textureMix;
+// 0 is color/texture, 1 all shaded
+uniform float shadeMix;
+
+// scrambles the triangle index, so neighbouring faces get unrelated colors
+uint hash(uint x)
+{
+	x ^= x >> 16;
+	x *= 0x7feb352dU;
+	x ^= x >> 15;
+	x *= 0x846ca68bU;
+	x ^= x >> 16;
+	return x;
+}
+
+vec3 faceColor()
+{
+	uint face = hash(uint(firstPrimitive + gl_PrimitiveID));
+
+	switch (colorMode)
+	{
+		case 0:
+			return smoothColors ? IN.SmoothColor : IN.Color;
+		case 1:
+			return vec3(0.2 + 0.8 * float(face & 0xffU) / 255.0);
+		case 2:
+		case 3:
+			return submeshColor;
+		case 4:
+			return normalize(IN.Normal) * 0.5 + 0.5;
+		default:
+			return palette[face % 16U];
+	}
+}
+
+// light gray lit from the camera, with the normal of the triangle so every face is flat
+vec3 shaded()
+{
+	vec3 normal = cross(dFdx(IN.ViewPosition), dFdy(IN.ViewPosition));
+	// lines and points have no surface, they are drawn fully lit
+	float light = length(normal) > 0.0 ? abs(dot(normalize(normal), normalize(IN.ViewPosition))) : 1.0;
+	return vec3(0.8) * (0.2 + 0.8 * light);
+}
+
 void main()
 {
-	// Color = vec4(IN.Color, 1.0f);
+	vec3 textured = texture(myTexture, IN.TexCoord).rgb;
 	// Color = texture(myTexture, IN.TexCoord) * vec4(IN.Color, 1.0);
-	Color = texture(myTexture, IN.TexCoord);
-}
\ No newline at end of file
+	Color = vec4(mix(mix(faceColor(), textured, textureMix), shaded(), shadeMix), 1.0);
+}
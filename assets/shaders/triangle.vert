@@ -8,7 +8,13 @@ layout (location = 3) in vec3 Color;
 out VS_OUTPUT
 {
 	flat vec3 Color;
+	// the same, but blended across the triangle
+	vec3 SmoothColor;
 	vec2 TexCoord;
+	// object space, so the colors stay on the model while it rotates
+	vec3 Normal;
+	// for the per-face lighting of the shaded mode
+	vec3 ViewPosition;
 } OUT;
 
 // uniform mat4 transform;
@@ -20,7 +26,11 @@ void main()
 {
 	// gl_Position = vec4(Position, 1.0);
 	// gl_Position = transform * vec4(Position, 1.0);
-	gl_Position = projection * view * model * vec4(Position, 1.0);
+	vec4 viewPosition = view * model * vec4(Position, 1.0);
+	gl_Position = projection * viewPosition;
+	OUT.ViewPosition = viewPosition.xyz;
 	OUT.Color = Color;
+	OUT.SmoothColor = Color;
 	OUT.TexCoord = TexCoord;
-}
\ No newline at end of file
+	OUT.Normal = Normal;
+}
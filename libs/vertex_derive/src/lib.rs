@@ -0,0 +1,93 @@
+// #[derive(VertexLayout)] for vertex structs of the viewer, see render_gl::VertexLayout.
+//
+// Every field becomes an attribute, at the location after the previous one (starting at 0).
+// Fields can be tweaked with #[vertex(...)]:
+//   location = N  put it at location N, the fields after it continue from N + 1
+//   normalized    integers are read as 0..1 (or -1..1) floats, for colors stored in bytes
+//   skip          not an attribute, only padding or data for the cpu
+// The generated code refers to crate::render_gl, so it only works inside the viewer crate.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitInt};
+
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream
+{
+	let input = parse_macro_input!(input as DeriveInput);
+	match expand(&input)
+	{
+		Ok(tokens) => tokens.into(),
+		Err(e) => e.to_compile_error().into()
+	}
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream>
+{
+	let fields = match &input.data
+	{
+		Data::Struct(data) => match &data.fields
+		{
+			Fields::Named(fields) => &fields.named,
+			_ => return Err(syn::Error::new_spanned(input, "VertexLayout needs named fields"))
+		},
+		_ => return Err(syn::Error::new_spanned(input, "VertexLayout can only be derived for structs"))
+	};
+
+	let name = &input.ident;
+	let mut attributes = Vec::new();
+	let mut location: u32 = 0;
+	for field in fields
+	{
+		let mut skip = false;
+		let mut normalized = false;
+		for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("vertex"))
+		{
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("location")
+				{
+					let value: LitInt = meta.value()?.parse()?;
+					location = value.base10_parse()?;
+				}
+				else if meta.path.is_ident("normalized")
+				{
+					normalized = true;
+				}
+				else if meta.path.is_ident("skip")
+				{
+					skip = true;
+				}
+				else
+				{
+					return Err(meta.error("expected `location = N`, `normalized` or `skip`"));
+				}
+				Ok(())
+			})?;
+		}
+		if skip
+		{
+			continue;
+		}
+
+		let ident = field.ident.as_ref().unwrap();
+		let ty = &field.ty;
+		attributes.push(quote! {
+			crate::render_gl::VertexAttribute {
+				location: #location,
+				components: <#ty as crate::render_gl::AttributeType>::COMPONENTS,
+				kind: <#ty as crate::render_gl::AttributeType>::KIND,
+				normalized: #normalized,
+				offset: ::std::mem::offset_of!(Self, #ident)
+			}
+		});
+		location += 1;
+	}
+
+	let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+	Ok(quote! {
+		impl #impl_generics crate::render_gl::VertexLayout for #name #type_generics #where_clause
+		{
+			const ATTRIBUTES: &'static [crate::render_gl::VertexAttribute] = &[#(#attributes),*];
+		}
+	})
+}
@@ -34,6 +34,11 @@ impl Camera
 		&self.view
 	}
 
+	pub fn position(&self) -> math::vector::Vector3
+	{
+		self.camera_pos
+	}
+
 	pub fn update_camera(&mut self, event: &sdl2::event::Event)
 	{
 		match event
@@ -14,7 +14,17 @@ use sdl2::keyboard::Keycode;
 
 fn main()
 {
-	let res = Resources::from_relative_exe_path(Path::new("assets")).unwrap();
+	// debug builds read the shaders straight from the source tree, so edits to them are reloaded while running
+	// (the copy next to the executable is only refreshed by cargo build)
+	let source_assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+	let res = if cfg!(debug_assertions) && source_assets.is_dir()
+	{
+		Resources::from_path(&source_assets)
+	}
+	else
+	{
+		Resources::from_relative_exe_path(Path::new("assets")).unwrap()
+	};
     let sdl = sdl2::init().unwrap();
     let video_subsystem = sdl.video().unwrap();
 	let gl_attr = video_subsystem.gl_attr();
@@ -41,8 +51,29 @@ fn main()
 	// i fucked up and need to have a seperate copy for each model or it won't compile (fix it pls, future me)
 	let program_2 = self::render_gl::Program::from_res(&res, "shaders/triangle").unwrap();
 
+	// model to open can be passed as an argument (.obj, .ply, .stl, .gltf, .glb or .bpt),
+	// or the name of a primitive (see model::primitives::NAMES)
+	let (model_path, options) = parse_args(std::env::args().skip(1));
+
 	// let mesh_42 = model::Mesh::from_file("assets/models/42.obj", program);
-	let mesh_teapot = model::Mesh::from_file("assets/models/teapot2.obj", program_2, "assets/textures/wall.jpg");
+	let mesh_teapot = match model::primitives::by_name(&model_path)
+	{
+		Some(data) => {
+			let mut mesh = model::Mesh::from_data(data, program_2, "assets/textures/wall.jpg");
+			mesh.set_conversion(options.conversion);
+			mesh
+		},
+		None => match model::Mesh::from_file_with_options(&model_path, program_2, "assets/textures/wall.jpg", &options)
+		{
+			Ok(mesh) => mesh,
+			Err(e) => {
+				// show a cube instead, so it's obvious something is wrong without having to restart
+				println!("failed to load {}: {:?}", model_path, e);
+				let program = self::render_gl::Program::from_res(&res, "shaders/triangle").unwrap();
+				model::Mesh::from_data(model::primitives::cube(1.0, 1), program, "assets/textures/wall.jpg")
+			}
+		}
+	};
 
     unsafe
 	{
@@ -63,6 +94,9 @@ fn main()
 
 	let mut scene = scene::Scene::new(vec![mesh_teapot], camera);
 
+	// animations run on the time between frames, so they don't depend on the frame rate
+	let mut last_frame = std::time::Instant::now();
+
     'main: loop
     {
         for event in event_pump.poll_iter()
@@ -92,6 +126,10 @@ fn main()
             }
         }
 
+		let now = std::time::Instant::now();
+		scene.update((now - last_frame).as_secs_f32());
+		last_frame = now;
+
         unsafe
         {
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
@@ -102,3 +140,32 @@ fn main()
         window.gl_swap_window();
     }
 }
+
+// [model] [--up y|z] [--flip-handedness] [--unit mm|cm|m|in], anything it doesn't understand is reported and skipped
+fn parse_args(mut args: impl Iterator<Item = String>) -> (String, model::LoadOptions)
+{
+	let mut model_path = "assets/models/teapot2.obj".to_string();
+	let mut options = model::LoadOptions::default();
+
+	while let Some(arg) = args.next()
+	{
+		match arg.as_str()
+		{
+			"--up" => match args.next().as_deref().and_then(model::coords::UpAxis::from_name)
+			{
+				Some(up) => options.conversion.up = up,
+				None => println!("--up takes y or z")
+			},
+			"--unit" => match args.next().as_deref().and_then(model::coords::Unit::from_name)
+			{
+				Some(unit) => options.conversion.unit = unit,
+				None => println!("--unit takes one of {}", model::coords::Unit::NAMES.join(", "))
+			},
+			"--flip-handedness" => options.conversion.flip_handedness = true,
+			_ if arg.starts_with("--") => println!("ignoring unknown option {}", arg),
+			_ => model_path = arg
+		}
+	}
+
+	(model_path, options)
+}
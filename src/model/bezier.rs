@@ -0,0 +1,369 @@
+// Bézier patches, from .bpt files (the format the Utah teapot is usually shared in) or obj
+// `cstype bezier` surfaces, and their tessellation into triangles.
+//
+// .bpt layout: the number of patches, then for each patch its degree in u and v followed by
+// (u degree + 1) * (v degree + 1) control points, one "x y z" per line, u varying fastest.
+//
+// Normals come from the derivatives of the surface, not from the triangles, so they stay smooth
+// at any resolution. Where a patch collapses to a point (the top of the teapot's lid) the derivative
+// is zero, and the normal is taken a tiny step inside the patch instead.
+
+use math;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+use crate::model::{self, MeshData, SubMesh, Vertex};
+
+// quads per patch side a patch mesh starts with
+pub const DEFAULT_RESOLUTION: usize = 8;
+pub const MAX_RESOLUTION: usize = 64;
+// far above anything modeled by hand (the teapot is bicubic), it bounds what a file can make the loaders allocate
+pub const MAX_DEGREE: usize = 32;
+
+// how far inside the patch the normal is taken when the surface is degenerate
+const DEGENERATE_STEP: f32 = 1e-3;
+
+#[derive(Debug)]
+pub enum Error
+{
+	Io(io::Error),
+	Parse {
+		line: usize,
+		message: String
+	}
+}
+
+impl From<io::Error> for Error
+{
+	fn from(other: io::Error) -> Self
+	{
+		Error::Io(other)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patch
+{
+	// in u and v
+	pub degree: [usize; 2],
+	// (degree[0] + 1) * (degree[1] + 1) control points, u varies fastest
+	pub points: Vec<math::vector::Vector3>,
+	// texture coordinates at (u, v) = (0, 0) and (1, 1)
+	pub uv_range: [math::vector::Vector2; 2]
+}
+
+impl Patch
+{
+	pub fn new(degree: [usize; 2], points: Vec<math::vector::Vector3>) -> Self
+	{
+		assert_eq!(points.len(), (degree[0] + 1) * (degree[1] + 1), "wrong number of control points for the degree");
+
+		Patch { degree, points, uv_range: [(0.0, 0.0).into(), (1.0, 1.0).into()] }
+	}
+
+	// Point at (u, v) and the derivatives along u and v there
+	pub fn evaluate(&self, u: f32, v: f32) -> (math::vector::Vector3, math::vector::Vector3, math::vector::Vector3)
+	{
+		let (bu, du) = bernstein(self.degree[0], u);
+		let (bv, dv) = bernstein(self.degree[1], v);
+		let zero = math::vector::Vector3::new(0.0, 0.0, 0.0);
+		let (mut point, mut along_u, mut along_v) = (zero, zero, zero);
+
+		for j in 0..=self.degree[1]
+		{
+			for i in 0..=self.degree[0]
+			{
+				let p = self.points[j * (self.degree[0] + 1) + i];
+				point = point + p * (bu[i] * bv[j]);
+				along_u = along_u + p * (du[i] * bv[j]);
+				along_v = along_v + p * (bu[i] * dv[j]);
+			}
+		}
+
+		(point, along_u, along_v)
+	}
+
+	// Unit normal at (u, v), facing the side from which u and v go counter-clockwise
+	pub fn normal(&self, u: f32, v: f32) -> math::vector::Vector3
+	{
+		let (_, along_u, along_v) = self.evaluate(u, v);
+		let normal = model::cross(along_u, along_v);
+		let length = normal.length();
+		if length > 1e-6
+		{
+			return normal / length;
+		}
+
+		// degenerate edge or corner, step towards the middle of the patch
+		let toward = |t: f32| t + (0.5 - t).signum() * DEGENERATE_STEP;
+		let (_, along_u, along_v) = self.evaluate(toward(u), toward(v));
+		let normal = model::cross(along_u, along_v);
+		let length = normal.length();
+		if length > 0.0 { normal / length } else { normal }
+	}
+}
+
+// Bernstein polynomials of `degree` at t, and their derivatives
+fn bernstein(degree: usize, t: f32) -> (Vec<f32>, Vec<f32>)
+{
+	let binomial = |n: usize, k: usize| (0..k).fold(1.0f32, |acc, i| acc * (n - i) as f32 / (i + 1) as f32);
+	let basis = |n: usize, i: usize| binomial(n, i) * t.powi(i as i32) * (1.0 - t).powi((n - i) as i32);
+
+	let values = (0..=degree).map(|i| basis(degree, i)).collect();
+	let derivatives = (0..=degree)
+		.map(|i| {
+			if degree == 0
+			{
+				return 0.0;
+			}
+			let lower = |i: usize| if i < degree { basis(degree - 1, i) } else { 0.0 };
+			let previous = if i > 0 { basis(degree - 1, i - 1) } else { 0.0 };
+			degree as f32 * (previous - lower(i))
+		})
+		.collect();
+
+	(values, derivatives)
+}
+
+pub fn load<T>(filename: T) -> Result<Vec<Patch>, Error>
+where T: AsRef<Path>
+{
+	from_bytes(&fs::read(filename)?)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<Vec<Patch>, Error>
+{
+	let text = str::from_utf8(bytes).map_err(|_| Error::Parse { line: 0, message: "invalid utf-8".into() })?;
+	let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line)).filter(|(_, line)| !line.trim().is_empty());
+	let mut last_line = 0;
+
+	let mut next_numbers = |count: usize| -> Result<(usize, Vec<f32>), Error> {
+		let (line, text) = lines.next().ok_or(Error::Parse { line: last_line, message: "unexpected end of file".into() })?;
+		last_line = line;
+
+		let numbers = text
+			.split_whitespace()
+			.map(|token| token.parse::<f32>().map_err(|_| Error::Parse { line, message: format!("invalid number '{}'", token) }))
+			.collect::<Result<Vec<f32>, Error>>()?;
+		if numbers.len() != count
+		{
+			return Err(Error::Parse { line, message: format!("expected {} values, found {}", count, numbers.len()) });
+		}
+
+		Ok((line, numbers))
+	};
+
+	let (line, count) = next_numbers(1)?;
+	let count = to_count(count[0], line)?;
+
+	let mut patches = Vec::with_capacity(count.min(bytes.len()));
+	for _ in 0..count
+	{
+		let (line, degree) = next_numbers(2)?;
+		let degree = [to_count(degree[0], line)?, to_count(degree[1], line)?];
+		if degree[0] == 0 || degree[1] == 0
+		{
+			return Err(Error::Parse { line, message: "patch degree has to be at least 1".into() });
+		}
+		if degree[0] > MAX_DEGREE || degree[1] > MAX_DEGREE
+		{
+			return Err(Error::Parse { line, message: format!("patch degree can be at most {}", MAX_DEGREE) });
+		}
+
+		let point_count = (degree[0] + 1) * (degree[1] + 1);
+		// each point is a line of at least "0 0 0"
+		let mut points = Vec::with_capacity(point_count.min(bytes.len() / 6));
+		for _ in 0..point_count
+		{
+			let (_, p) = next_numbers(3)?;
+			points.push((p[0], p[1], p[2]).into());
+		}
+		patches.push(Patch::new(degree, points));
+	}
+
+	Ok(patches)
+}
+
+// above 2^24 an f32 can't tell whole numbers apart anyway
+fn to_count(value: f32, line: usize) -> Result<usize, Error>
+{
+	if (0.0..=16_777_216.0).contains(&value) && value.fract() == 0.0
+	{
+		Ok(value as usize)
+	}
+	else
+	{
+		Err(Error::Parse { line, message: format!("expected a whole number, found {}", value) })
+	}
+}
+
+// The polygons of `data` plus its patches cut into resolution * resolution quads each, in a submesh of their own.
+// Texture coordinates are the patch parameters
+pub fn tessellate(data: &MeshData, resolution: usize) -> MeshData
+{
+	let resolution = resolution.clamp(1, MAX_RESOLUTION);
+	let side = resolution + 1;
+
+	let mut vertices = data.vertices.clone();
+	let mut indices = data.indices.clone();
+	vertices.reserve(data.patches.len() * side * side);
+	indices.reserve(data.patches.len() * resolution * resolution * 6);
+
+	let first = indices.len();
+	let mut rng = model::color::vertex_rng();
+	for patch in &data.patches
+	{
+		let base = vertices.len() as u32;
+		let [uv_min, uv_max] = patch.uv_range;
+		for j in 0..side
+		{
+			for i in 0..side
+			{
+				let (u, v) = (i as f32 / resolution as f32, j as f32 / resolution as f32);
+				let texcoord = (uv_min.x() + (uv_max.x() - uv_min.x()) * u, uv_min.y() + (uv_max.y() - uv_min.y()) * v);
+
+				let mut vertex = Vertex::new(patch.evaluate(u, v).0, model::random_color(&mut rng), texcoord.into());
+				vertex.normal = patch.normal(u, v);
+				vertices.push(vertex);
+			}
+		}
+
+		for j in 0..resolution as u32
+		{
+			for i in 0..resolution as u32
+			{
+				let corner = base + j * side as u32 + i;
+				let above = corner + side as u32;
+				indices.extend_from_slice(&[corner, corner + 1, above + 1, corner, above + 1, above]);
+			}
+		}
+	}
+
+	let mut result = MeshData::new(vertices, indices);
+	// the patch vertices go after the ones that were there, so these still point at the same vertices
+	result.lines = data.lines.clone();
+	result.points = data.points.clone();
+	result.submeshes = data.submeshes.clone();
+	result.material_libs = data.material_libs.clone();
+	if !data.patches.is_empty()
+	{
+		result.submeshes.push(SubMesh { name: "patches".into(), material: None, first: first as u32, count: (result.indices.len() - first) as u32 });
+	}
+	// polygons that aren't in any submesh yet (a file with only patches has a single empty one)
+	result.submeshes.retain(|submesh| submesh.count > 0);
+
+	result
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::model::validate::{self, Kind};
+
+	// quarter of a cylinder of radius 1 around the y axis, as a bicubic patch
+	fn quarter_cylinder() -> Patch
+	{
+		// circle arc control points for 90 degrees
+		let k = 0.5523;
+		let arc = [(1.0, 0.0), (1.0, k), (k, 1.0), (0.0, 1.0)];
+		let points = (0..4)
+			.flat_map(|j| arc.iter().map(move |&(x, z)| (x, j as f32 / 3.0, z).into()))
+			.collect();
+
+		Patch::new([3, 3], points)
+	}
+
+	#[test]
+	fn evaluates_corners_and_normals()
+	{
+		let patch = quarter_cylinder();
+		let (p, _, _) = patch.evaluate(0.0, 0.0);
+		assert_eq!(p, (1.0, 0.0, 0.0).into());
+		let (p, _, _) = patch.evaluate(1.0, 1.0);
+		assert_eq!(p, (0.0, 1.0, 1.0).into());
+
+		// u goes around the y axis, v up it, so the normal points at the axis
+		for (u, v) in [(0.0, 0.0), (0.5, 0.5), (0.3, 1.0)]
+		{
+			let (p, _, _) = patch.evaluate(u, v);
+			let radial = math::vector::Vector3::new(p.x(), 0.0, p.z()).normalized();
+			assert!(patch.normal(u, v).dot(&radial) < -0.99, "normal at ({}, {})", u, v);
+		}
+	}
+
+	#[test]
+	fn degenerate_corner_has_a_normal()
+	{
+		// cone with all of its top row in one point
+		let mut patch = quarter_cylinder();
+		for i in 12..16
+		{
+			patch.points[i] = (0.0, 1.0, 0.0).into();
+		}
+
+		let normal = patch.normal(0.5, 1.0);
+		assert!((normal.length() - 1.0).abs() < 1e-4);
+		assert!(normal.y() < 0.0);
+	}
+
+	#[test]
+	fn parses_bpt()
+	{
+		let mut text = String::from("1\n3 3\n");
+		for j in 0..4
+		{
+			for i in 0..4
+			{
+				text += &format!("{} {} 0\n", i, j);
+			}
+		}
+		let patches = from_bytes(text.as_bytes()).unwrap();
+		assert_eq!(patches.len(), 1);
+		assert_eq!(patches[0].points[5], (1.0, 1.0, 0.0).into());
+
+		match from_bytes(b"2\n3 3\n0 0 0\n")
+		{
+			Err(Error::Parse { line: 3, .. }) => {},
+			other => panic!("unexpected {:?}", other)
+		}
+		assert!(matches!(from_bytes(b"1\n3 3\n0 0\n"), Err(Error::Parse { line: 3, .. })));
+
+		// rejected before anything is allocated for them
+		assert!(matches!(from_bytes(b"1\n100000 100000\n0 0 0\n"), Err(Error::Parse { line: 2, .. })));
+		assert!(matches!(from_bytes(b"1\n1e30 1\n0 0 0\n"), Err(Error::Parse { line: 2, .. })));
+		assert!(matches!(from_bytes(b"1e30\n1 1\n0 0 0\n"), Err(Error::Parse { line: 1, .. })));
+	}
+
+	#[test]
+	fn teapot()
+	{
+		let patches = from_bytes(include_bytes!("../../assets/models/teapot.bpt")).unwrap();
+		assert_eq!(patches.len(), 32);
+
+		let mut data = MeshData::new(Vec::new(), Vec::new());
+		data.patches = patches;
+		let mesh = tessellate(&data, 4);
+		assert_eq!(mesh.vertices.len(), 32 * 25);
+		assert_eq!(mesh.indices.len(), 32 * 16 * 6);
+		assert_eq!(mesh.submeshes.len(), 1);
+		assert!(validate::validate(&mesh).count(Kind::IndexOutOfRange) == 0);
+
+		// same size as teapot.obj, y up
+		let (min, max) = (mesh.bounds.min(), mesh.bounds.max());
+		assert!((min.y() - 0.0).abs() < 1e-4 && (max.y() - 3.15).abs() < 1e-4);
+		assert!((min.x() + 3.0).abs() < 0.1 && (max.x() - 3.43).abs() < 0.1);
+
+		// the body (patches 4 to 11, after the rim) has its normals pointing away from its axis
+		for patch in &data.patches[4..12]
+		{
+			for (u, v) in [(0.1, 0.1), (0.5, 0.5), (0.9, 0.7)]
+			{
+				let (p, _, _) = patch.evaluate(u, v);
+				let radial = math::vector::Vector3::new(p.x(), 0.0, p.z()).normalized();
+				assert!(patch.normal(u, v).dot(&radial) > 0.5, "normal at {:?}", p);
+			}
+		}
+	}
+}
@@ -0,0 +1,488 @@
+// Binary cache of processed meshes, keyed by a hash of the source file, so a big model
+// only has to be parsed, triangulated and have its normals generated the first time it is opened.
+//
+// Layout (little endian):
+//   header     "SCOPMESH", version u32, source hash u64, vertex count u32, index count u32,
+//              submesh count u32, material lib count u32, bounds min xyz, bounds max xyz (f32)
+//   vertices   vertex count * size_of::<Vertex>(), exactly as they are uploaded to the gpu
+//   padding    up to a multiple of 4 bytes
+//   indices    index count * u32
+//   submeshes  first u32, count u32, name, material (strings are a u32 length followed by utf-8 bytes,
+//              a length of u32::MAX means no material)
+//   materials  material lib file names as strings
+//   lods       lod count u32, then for each: error f32, index count u32, indices, submesh count u32, submeshes
+//   patches    patch count u32, then for each: u and v degree u32, control points xyz, uv range (f32)
+//   elements   line index count u32, line indices, point index count u32, point indices
+//   flags      u32, bit 0 set if the vertex colors came from the file
+//
+// The file is memory-mapped, but a hit still copies the vertices and indices out of it: the Mesh owns
+// its MeshData (it is exported, re-optimized and subdivided later) and uploads to the gpu from that copy.
+// Uploading straight from the mapping would save one memcpy, not the parsing this cache is there to skip
+
+use math;
+use memmap2::Mmap;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::PathBuf;
+use crate::model::{Bounds, MeshData, SubMesh, Vertex};
+use crate::model::bezier::Patch;
+use crate::model::simplify::Lod;
+
+const MAGIC: &[u8; 8] = b"SCOPMESH";
+// bump whenever the layout or the way meshes are processed changes, old files are then ignored
+const VERSION: u32 = 8;
+const HEADER_SIZE: usize = 8 + 4 + 8 + 4 * 4 + 6 * 4;
+const NO_MATERIAL: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub enum Error
+{
+	Io(io::Error),
+	Corrupt(String),
+}
+
+impl From<io::Error> for Error
+{
+	fn from(other: io::Error) -> Self
+	{
+		Error::Io(other)
+	}
+}
+
+// 64-bit FNV-1a, unlike std's DefaultHasher it is guaranteed to stay the same between builds
+pub fn source_hash(bytes: &[u8]) -> u64
+{
+	let mut hash: u64 = 0xcbf29ce484222325;
+	for &b in bytes
+	{
+		hash ^= b as u64;
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+
+	hash
+}
+
+pub fn cache_dir() -> PathBuf
+{
+	std::env::temp_dir().join("scop-cache")
+}
+
+fn cache_path(source_hash: u64) -> PathBuf
+{
+	cache_dir().join(format!("{:016x}.mesh", source_hash))
+}
+
+// A cache file mapped into memory, until into_mesh_data copies the vertices and indices out of it
+pub struct CachedMesh
+{
+	map: Mmap,
+	vertex_count: usize,
+	index_count: usize,
+	index_offset: usize,
+	submeshes: Vec<SubMesh>,
+	material_libs: Vec<String>,
+	lods: Vec<Lod>,
+	patches: Vec<Patch>,
+	lines: Vec<u32>,
+	points: Vec<u32>,
+	vertex_colors: bool,
+	bounds: Bounds
+}
+
+impl CachedMesh
+{
+	pub fn into_mesh_data(self) -> MeshData
+	{
+		// Vertex is packed (alignment 1) and open() checked the file is long enough.
+		// The mapping starts on a page boundary and index_offset is a multiple of 4
+		let (vertices, indices) = unsafe
+		{
+			(
+				std::slice::from_raw_parts(self.map.as_ptr().add(HEADER_SIZE) as *const Vertex, self.vertex_count),
+				std::slice::from_raw_parts(self.map.as_ptr().add(self.index_offset) as *const u32, self.index_count)
+			)
+		};
+
+		MeshData {
+			vertices: vertices.to_vec(),
+			indices: indices.to_vec(),
+			submeshes: self.submeshes,
+			material_libs: self.material_libs,
+			bounds: self.bounds,
+			lods: self.lods,
+			patches: self.patches,
+			lines: self.lines,
+			points: self.points,
+			vertex_colors: self.vertex_colors
+		}
+	}
+}
+
+// Returns None if there is no usable cache file for this source (missing, or from another version)
+pub fn open(source_hash: u64) -> Result<Option<CachedMesh>, Error>
+{
+	// the vertices and indices are copied as they are, which assumes the file has the same byte order as the machine
+	if cfg!(target_endian = "big")
+	{
+		return Ok(None);
+	}
+
+	let file = match fs::File::open(cache_path(source_hash))
+	{
+		Ok(file) => file,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+		Err(e) => return Err(e.into())
+	};
+
+	// the cache file could be changed by another process while it is mapped, in which case
+	// we'd render garbage. It's our own temp file, so that's accepted
+	let map = unsafe { Mmap::map(&file)? };
+
+	let mut reader = Reader { bytes: &map, pos: 0 };
+	if reader.take(8)? != MAGIC || reader.u32()? != VERSION || reader.u64()? != source_hash
+	{
+		return Ok(None);
+	}
+
+	let vertex_count = reader.u32()? as usize;
+	let index_count = reader.u32()? as usize;
+	let submesh_count = reader.u32()? as usize;
+	let material_lib_count = reader.u32()? as usize;
+	let min = reader.vector3()?;
+	let max = reader.vector3()?;
+
+	reader.take(vertex_count * mem::size_of::<Vertex>())?;
+	reader.align(4)?;
+	let index_offset = reader.pos;
+	reader.take(index_count * mem::size_of::<u32>())?;
+
+	let submeshes = reader.submeshes(submesh_count)?;
+
+	let mut material_libs = Vec::with_capacity(material_lib_count.min(reader.remaining()));
+	for _ in 0..material_lib_count
+	{
+		material_libs.push(reader.string()?.unwrap_or_default());
+	}
+
+	let lod_count = reader.u32()? as usize;
+	let mut lods = Vec::with_capacity(lod_count.min(reader.remaining()));
+	for _ in 0..lod_count
+	{
+		let error = reader.f32()?;
+		let index_count = reader.u32()? as usize;
+		let indices = reader.indices(index_count)?;
+		let submesh_count = reader.u32()? as usize;
+		let submeshes = reader.submeshes(submesh_count)?;
+		lods.push(Lod { indices, submeshes, error });
+	}
+
+	let patch_count = reader.u32()? as usize;
+	let mut patches = Vec::with_capacity(patch_count.min(reader.remaining()));
+	for _ in 0..patch_count
+	{
+		let degree = [reader.u32()? as usize, reader.u32()? as usize];
+		let point_count = (degree[0] + 1).checked_mul(degree[1] + 1).filter(|&n| n <= reader.remaining())
+			.ok_or_else(|| Error::Corrupt(format!("patch degree {:?} is too big", degree)))?;
+		let mut points = Vec::with_capacity(point_count);
+		for _ in 0..point_count
+		{
+			points.push(reader.vector3()?);
+		}
+		let mut patch = Patch::new(degree, points);
+		patch.uv_range = [(reader.f32()?, reader.f32()?).into(), (reader.f32()?, reader.f32()?).into()];
+		patches.push(patch);
+	}
+
+	let line_count = reader.u32()? as usize;
+	let lines = reader.indices(line_count)?;
+	let point_count = reader.u32()? as usize;
+	let points = reader.indices(point_count)?;
+
+	let flags = reader.u32()?;
+
+	Ok(Some(CachedMesh {
+		vertex_count,
+		index_count,
+		index_offset,
+		submeshes,
+		material_libs,
+		lods,
+		patches,
+		lines,
+		points,
+		vertex_colors: flags & 1 != 0,
+		bounds: Bounds::new(min, max),
+		map
+	}))
+}
+
+pub fn write(source_hash: u64, data: &MeshData) -> Result<(), Error>
+{
+	fs::create_dir_all(cache_dir())?;
+
+	// write to a temporary file first, so a crash never leaves a half written cache behind
+	let path = cache_path(source_hash);
+	let temp_path = path.with_extension("tmp");
+	let mut out = BufWriter::new(fs::File::create(&temp_path)?);
+
+	out.write_all(MAGIC)?;
+	out.write_all(&VERSION.to_le_bytes())?;
+	out.write_all(&source_hash.to_le_bytes())?;
+	for count in [data.vertices.len(), data.indices.len(), data.submeshes.len(), data.material_libs.len()]
+	{
+		out.write_all(&(count as u32).to_le_bytes())?;
+	}
+	for v in [data.bounds.min(), data.bounds.max()]
+	{
+		for value in [v.x(), v.y(), v.z()]
+		{
+			out.write_all(&value.to_le_bytes())?;
+		}
+	}
+
+	// Vertex is plain packed f32s, so its bytes are the file contents
+	let vertex_bytes = unsafe {
+		std::slice::from_raw_parts(data.vertices.as_ptr() as *const u8, data.vertices.len() * mem::size_of::<Vertex>())
+	};
+	out.write_all(vertex_bytes)?;
+	let padding = (4 - (HEADER_SIZE + vertex_bytes.len()) % 4) % 4;
+	out.write_all(&[0; 4][..padding])?;
+
+	for index in &data.indices
+	{
+		out.write_all(&index.to_le_bytes())?;
+	}
+
+	write_submeshes(&mut out, &data.submeshes)?;
+
+	for lib in &data.material_libs
+	{
+		write_string(&mut out, Some(lib))?;
+	}
+
+	out.write_all(&(data.lods.len() as u32).to_le_bytes())?;
+	for lod in &data.lods
+	{
+		out.write_all(&lod.error.to_le_bytes())?;
+		out.write_all(&(lod.indices.len() as u32).to_le_bytes())?;
+		for index in &lod.indices
+		{
+			out.write_all(&index.to_le_bytes())?;
+		}
+		out.write_all(&(lod.submeshes.len() as u32).to_le_bytes())?;
+		write_submeshes(&mut out, &lod.submeshes)?;
+	}
+
+	out.write_all(&(data.patches.len() as u32).to_le_bytes())?;
+	for patch in &data.patches
+	{
+		for degree in patch.degree
+		{
+			out.write_all(&(degree as u32).to_le_bytes())?;
+		}
+		for point in &patch.points
+		{
+			for value in [point.x(), point.y(), point.z()]
+			{
+				out.write_all(&value.to_le_bytes())?;
+			}
+		}
+		for value in [patch.uv_range[0].x(), patch.uv_range[0].y(), patch.uv_range[1].x(), patch.uv_range[1].y()]
+		{
+			out.write_all(&value.to_le_bytes())?;
+		}
+	}
+
+	for elements in [&data.lines, &data.points]
+	{
+		out.write_all(&(elements.len() as u32).to_le_bytes())?;
+		for index in elements
+		{
+			out.write_all(&index.to_le_bytes())?;
+		}
+	}
+
+	let flags = data.vertex_colors as u32;
+	out.write_all(&flags.to_le_bytes())?;
+
+	out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+	fs::rename(temp_path, path)?;
+
+	Ok(())
+}
+
+fn write_submeshes(out: &mut impl Write, submeshes: &[SubMesh]) -> io::Result<()>
+{
+	for submesh in submeshes
+	{
+		out.write_all(&submesh.first.to_le_bytes())?;
+		out.write_all(&submesh.count.to_le_bytes())?;
+		write_string(out, Some(&submesh.name))?;
+		write_string(out, submesh.material.as_deref())?;
+	}
+
+	Ok(())
+}
+
+fn write_string(out: &mut impl Write, string: Option<&str>) -> io::Result<()>
+{
+	match string
+	{
+		Some(string) => {
+			out.write_all(&(string.len() as u32).to_le_bytes())?;
+			out.write_all(string.as_bytes())
+		},
+		None => out.write_all(&NO_MATERIAL.to_le_bytes())
+	}
+}
+
+// Bounds checked cursor over the mapped file
+struct Reader<'a>
+{
+	bytes: &'a [u8],
+	pos: usize
+}
+
+impl<'a> Reader<'a>
+{
+	fn take(&mut self, len: usize) -> Result<&'a [u8], Error>
+	{
+		if len > self.remaining()
+		{
+			return Err(Error::Corrupt(format!("file ends at {} bytes, expected at least {}", self.bytes.len(), self.pos + len)));
+		}
+
+		let slice = &self.bytes[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(slice)
+	}
+
+	fn remaining(&self) -> usize
+	{
+		self.bytes.len() - self.pos
+	}
+
+	fn align(&mut self, alignment: usize) -> Result<(), Error>
+	{
+		let padding = (alignment - self.pos % alignment) % alignment;
+		self.take(padding).map(|_| ())
+	}
+
+	fn u32(&mut self) -> Result<u32, Error>
+	{
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	fn u64(&mut self) -> Result<u64, Error>
+	{
+		Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+	}
+
+	fn f32(&mut self) -> Result<f32, Error>
+	{
+		Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	fn vector3(&mut self) -> Result<math::vector::Vector3, Error>
+	{
+		Ok(math::vector::Vector3::new(self.f32()?, self.f32()?, self.f32()?))
+	}
+
+	fn indices(&mut self, count: usize) -> Result<Vec<u32>, Error>
+	{
+		Ok(self.take(count * mem::size_of::<u32>())?
+			.chunks_exact(4)
+			.map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+			.collect())
+	}
+
+	fn submeshes(&mut self, count: usize) -> Result<Vec<SubMesh>, Error>
+	{
+		let mut submeshes = Vec::with_capacity(count.min(self.remaining()));
+		for _ in 0..count
+		{
+			let first = self.u32()?;
+			let count = self.u32()?;
+			let name = self.string()?.unwrap_or_default();
+			let material = self.string()?;
+			submeshes.push(SubMesh { name, material, first, count });
+		}
+
+		Ok(submeshes)
+	}
+
+	fn string(&mut self) -> Result<Option<String>, Error>
+	{
+		let len = self.u32()?;
+		if len == NO_MATERIAL
+		{
+			return Ok(None);
+		}
+
+		let bytes = self.take(len as usize)?;
+		String::from_utf8(bytes.to_vec())
+			.map(Some)
+			.map_err(|_| Error::Corrupt("invalid utf-8 in string".into()))
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn round_trip()
+	{
+		let vertices = vec![
+			Vertex::new((0.0, 0.0, 0.0).into(), (1.0, 0.0, 0.0).into(), (0.0, 0.0).into()),
+			Vertex::new((1.0, 0.0, 0.0).into(), (0.0, 1.0, 0.0).into(), (1.0, 0.0).into()),
+			Vertex::new((0.0, 1.0, 0.5).into(), (0.0, 0.0, 1.0).into(), (0.0, 1.0).into())
+		];
+		let mut data = MeshData::new(vertices, vec![0, 1, 2, 2, 1, 0]);
+		data.generate_normals();
+		data.submeshes = vec![
+			SubMesh { name: "front".into(), material: Some("red".into()), first: 0, count: 3 },
+			SubMesh { name: "back".into(), material: None, first: 3, count: 3 }
+		];
+		data.material_libs = vec!["test.mtl".into()];
+		data.lods = vec![Lod {
+			indices: vec![0, 1, 2],
+			submeshes: vec![SubMesh { name: "front".into(), material: Some("red".into()), first: 0, count: 3 }],
+			error: 0.25
+		}];
+		let mut patch = Patch::new([1, 2], (0..6).map(|i| (i as f32, 0.5, -1.0).into()).collect());
+		patch.uv_range = [(0.25, 0.0).into(), (0.5, 1.0).into()];
+		data.patches = vec![patch];
+		data.vertex_colors = true;
+		data.lines = vec![0, 1, 1, 2];
+		data.points = vec![2];
+
+		// a hash no real file is going to have
+		let key = source_hash(b"cache round trip test") ^ 0x5c09;
+		write(key, &data).unwrap();
+		let cached = open(key).unwrap().expect("cache file should exist").into_mesh_data();
+
+		assert_eq!(cached.indices, data.indices);
+		for (a, b) in cached.vertices.iter().zip(&data.vertices)
+		{
+			assert_eq!(a.position(), b.position());
+			assert_eq!(a.normal(), b.normal());
+			assert_eq!(a.color(), b.color());
+		}
+		assert_eq!(cached.submeshes, data.submeshes);
+		assert_eq!(cached.material_libs, data.material_libs);
+		assert_eq!(cached.lods, data.lods);
+		assert_eq!(cached.patches, data.patches);
+		assert!(cached.vertex_colors);
+		assert_eq!(cached.lines, data.lines);
+		assert_eq!(cached.points, data.points);
+		assert_eq!(&cached.bounds, &data.bounds);
+
+		// a different source must not hit this file
+		assert!(open(key ^ 1).unwrap().is_none());
+
+		fs::remove_file(cache_path(key)).unwrap();
+	}
+}
@@ -0,0 +1,311 @@
+// How faces are colored when the model isn't drawn with its texture, and the animated blend between
+// the colors, the texture and plain shading. The colors are derived from fixed seeds,
+// so the same file looks the same on every run and screenshots can be compared.
+// The per-face modes are picked in the fragment shader from gl_PrimitiveID, because faces share
+// vertices and a vertex color can't tell its faces apart.
+
+use math::vector::Vector3;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::model::SubMesh;
+
+// must match the size of the palette array in triangle.frag
+pub const PALETTE_SIZE: usize = 16;
+pub const DEFAULT_SEED: u64 = 42;
+// seconds a switch between color, texture and shaded takes
+pub const DEFAULT_TRANSITION: f32 = 0.5;
+
+// The values are what the shader gets in its colorMode uniform
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorMode
+{
+	// colors that came with the file, or the seeded random ones for files without any
+	Vertex = 0,
+	// a different shade of gray for each triangle
+	FaceShade = 1,
+	// one palette color per group/object
+	Group = 2,
+	// one palette color per material, groups with the same material look the same
+	Material = 3,
+	// object space normal mapped to rgb, x is red, y green and z blue
+	Normal = 4,
+	// a palette color for each triangle
+	Palette = 5,
+}
+
+impl ColorMode
+{
+	pub const ALL: [ColorMode; 6] = [
+		ColorMode::Vertex,
+		ColorMode::FaceShade,
+		ColorMode::Group,
+		ColorMode::Material,
+		ColorMode::Normal,
+		ColorMode::Palette
+	];
+
+	// the one after this, wrapping around to Vertex
+	pub fn next(self) -> Self
+	{
+		let index = ColorMode::ALL.iter().position(|&mode| mode == self).unwrap();
+		ColorMode::ALL[(index + 1) % ColorMode::ALL.len()]
+	}
+
+	// the colors come from the submesh a triangle is in, so each one has to be drawn on its own
+	pub fn per_submesh(self) -> bool
+	{
+		matches!(self, ColorMode::Group | ColorMode::Material)
+	}
+}
+
+// What the model is drawn with
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Appearance
+{
+	// the ColorMode colors
+	Color,
+	Texture,
+	// light gray, lit per face from the camera, to look at the bare geometry
+	Shaded,
+}
+
+impl Appearance
+{
+	// Texture -> Color -> Shaded -> Texture
+	pub fn next(self) -> Self
+	{
+		match self
+		{
+			Appearance::Texture => Appearance::Color,
+			Appearance::Color => Appearance::Shaded,
+			Appearance::Shaded => Appearance::Texture
+		}
+	}
+}
+
+// Fades between appearances over `duration` seconds. The shader gets the two weights in its
+// textureMix and shadeMix uniforms, and draws mix(mix(color, texture, textureMix), shaded, shadeMix)
+#[derive(Copy, Clone, Debug)]
+pub struct Transition
+{
+	target: Appearance,
+	texture: f32,
+	shade: f32,
+	pub duration: f32
+}
+
+impl Transition
+{
+	// starts out fully showing appearance
+	pub fn new(appearance: Appearance, duration: f32) -> Self
+	{
+		let mut transition = Transition { target: appearance, texture: 0.0, shade: 0.0, duration: 0.0 };
+		transition.update(0.0);
+		transition.duration = duration;
+		transition
+	}
+
+	pub fn target(&self) -> Appearance
+	{
+		self.target
+	}
+
+	// fades from wherever the blend is now, so switching again halfway through turns around smoothly
+	pub fn set_target(&mut self, appearance: Appearance)
+	{
+		self.target = appearance;
+	}
+
+	// moves the weights towards the target by dt seconds worth, any dt gives the same speed
+	pub fn update(&mut self, dt: f32)
+	{
+		let (texture, shade) = match self.target
+		{
+			Appearance::Color => (0.0, 0.0),
+			Appearance::Texture => (1.0, 0.0),
+			// keeps fading out of whatever was shown before
+			Appearance::Shaded => (self.texture, 1.0)
+		};
+		let step = if self.duration > 0.0 { dt / self.duration } else { 1.0 };
+		self.texture = approach(self.texture, texture, step);
+		self.shade = approach(self.shade, shade, step);
+	}
+
+	// (textureMix, shadeMix)
+	pub fn weights(&self) -> (f32, f32)
+	{
+		(self.texture, self.shade)
+	}
+}
+
+fn approach(from: f32, to: f32, step: f32) -> f32
+{
+	if from < to { (from + step).min(to) } else { (from - step).max(to) }
+}
+
+// rng for vertex colors of files that don't have any, the same for every load
+pub fn vertex_rng() -> StdRng
+{
+	StdRng::seed_from_u64(DEFAULT_SEED)
+}
+
+// PALETTE_SIZE bright, saturated colors with random hues
+pub fn palette(seed: u64) -> Vec<Vector3>
+{
+	let mut rng = StdRng::seed_from_u64(seed);
+	(0..PALETTE_SIZE)
+		.map(|_| {
+			let hue = rng.gen_range(0.0..1.0);
+			let saturation = rng.gen_range(0.5..0.9);
+			let value = rng.gen_range(0.6..1.0);
+			hsv(hue, saturation, value)
+		})
+		.collect()
+}
+
+// Color for each submesh in Group or Material mode. Names are numbered in the order they first show up,
+// so the first PALETTE_SIZE different groups (or materials) all get different colors
+pub fn submesh_colors(submeshes: &[SubMesh], mode: ColorMode, palette: &[Vector3]) -> Vec<Vector3>
+{
+	let mut seen: Vec<Option<&str>> = Vec::new();
+	submeshes
+		.iter()
+		.map(|submesh| {
+			let key = match mode
+			{
+				ColorMode::Material => submesh.material.as_deref(),
+				_ => Some(submesh.name.as_str())
+			};
+			let index = match seen.iter().position(|&k| k == key)
+			{
+				Some(index) => index,
+				None => {
+					seen.push(key);
+					seen.len() - 1
+				}
+			};
+			palette[index % palette.len()]
+		})
+		.collect()
+}
+
+// all three in 0..1
+fn hsv(hue: f32, saturation: f32, value: f32) -> Vector3
+{
+	let h = hue * 6.0;
+	let c = value * saturation;
+	let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+	let (r, g, b) = match h as u32
+	{
+		0 => (c, x, 0.0),
+		1 => (x, c, 0.0),
+		2 => (0.0, c, x),
+		3 => (0.0, x, c),
+		4 => (x, 0.0, c),
+		_ => (c, 0.0, x)
+	};
+	let m = value - c;
+
+	(r + m, g + m, b + m).into()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn submesh(name: &str, material: Option<&str>) -> SubMesh
+	{
+		SubMesh { name: name.to_string(), material: material.map(|m| m.to_string()), first: 0, count: 3 }
+	}
+
+	#[test]
+	fn palette_is_reproducible()
+	{
+		assert_eq!(palette(DEFAULT_SEED), palette(DEFAULT_SEED));
+		assert_ne!(palette(DEFAULT_SEED), palette(DEFAULT_SEED + 1));
+		for color in palette(7)
+		{
+			for channel in [color.x(), color.y(), color.z()]
+			{
+				assert!((0.0..=1.0).contains(&channel));
+			}
+			// bright enough to tell apart from the background
+			assert!(color.x().max(color.y()).max(color.z()) >= 0.6);
+		}
+
+		let (mut a, mut b) = (vertex_rng(), vertex_rng());
+		assert_eq!(a.gen::<u64>(), b.gen::<u64>());
+	}
+
+	#[test]
+	fn groups_and_materials()
+	{
+		let palette = palette(DEFAULT_SEED);
+		let submeshes = [
+			submesh("body", Some("metal")),
+			submesh("lid", Some("metal")),
+			submesh("handle", Some("wood")),
+			submesh("body", None)
+		];
+
+		let groups = submesh_colors(&submeshes, ColorMode::Group, &palette);
+		assert_eq!(groups, vec![palette[0], palette[1], palette[2], palette[0]]);
+
+		let materials = submesh_colors(&submeshes, ColorMode::Material, &palette);
+		assert_eq!(materials, vec![palette[0], palette[0], palette[1], palette[2]]);
+	}
+
+	#[test]
+	fn cycling_visits_every_mode()
+	{
+		let mut mode = ColorMode::Vertex;
+		for expected in ColorMode::ALL.iter().skip(1)
+		{
+			mode = mode.next();
+			assert_eq!(mode, *expected);
+		}
+		assert_eq!(mode.next(), ColorMode::Vertex);
+		assert_eq!(Appearance::Texture.next().next().next(), Appearance::Texture);
+		assert_eq!(hsv(0.0, 1.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+	}
+
+	#[test]
+	fn transition_follows_time_not_frames()
+	{
+		let mut transition = Transition::new(Appearance::Texture, 0.5);
+		assert_eq!(transition.weights(), (1.0, 0.0));
+
+		transition.set_target(Appearance::Color);
+		// ten short frames move as far as one long one
+		let mut long = transition;
+		long.update(0.25);
+		for _ in 0..10
+		{
+			transition.update(0.025);
+		}
+		assert!((transition.weights().0 - 0.5).abs() < 1e-5);
+		assert!((long.weights().0 - 0.5).abs() < 1e-5);
+
+		// turns around from halfway, and stops at the end
+		transition.set_target(Appearance::Texture);
+		transition.update(0.1);
+		assert!((transition.weights().0 - 0.7).abs() < 1e-5);
+		transition.update(10.0);
+		assert_eq!(transition.weights(), (1.0, 0.0));
+
+		// shading fades in over the texture, and back out to where it was
+		transition.set_target(Appearance::Shaded);
+		transition.update(0.5);
+		assert_eq!(transition.weights(), (1.0, 1.0));
+		transition.set_target(Appearance::Color);
+		transition.update(0.25);
+		assert!((transition.weights().0 - 0.5).abs() < 1e-5);
+		assert!((transition.weights().1 - 0.5).abs() < 1e-5);
+
+		let mut instant = Transition::new(Appearance::Color, 0.0);
+		instant.set_target(Appearance::Texture);
+		instant.update(0.0);
+		assert_eq!(instant.weights(), (1.0, 0.0));
+	}
+}
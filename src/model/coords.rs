@@ -0,0 +1,219 @@
+// Conversion from the coordinate system and units a file was written in to the viewer's:
+// y up, right-handed, in meters. Models from CAD and 3ds Max are usually z up, and parts
+// modelled in millimeters are a thousand times too large for the camera.
+
+use math::matrix::Matrix4;
+use math::vector::{Vector3, Vector4};
+use crate::model::Bounds;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UpAxis
+{
+	Y,
+	Z,
+}
+
+impl UpAxis
+{
+	// for the command line, "y" or "z"
+	pub fn from_name(name: &str) -> Option<Self>
+	{
+		match name.to_ascii_lowercase().as_str()
+		{
+			"y" => Some(UpAxis::Y),
+			"z" => Some(UpAxis::Z),
+			_ => None
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Unit
+{
+	Millimeter,
+	Centimeter,
+	Meter,
+	Inch,
+}
+
+impl Unit
+{
+	// names accepted by from_name
+	pub const NAMES: [&'static str; 4] = ["mm", "cm", "m", "in"];
+
+	pub fn from_name(name: &str) -> Option<Self>
+	{
+		match name.to_ascii_lowercase().as_str()
+		{
+			"mm" => Some(Unit::Millimeter),
+			"cm" => Some(Unit::Centimeter),
+			"m" => Some(Unit::Meter),
+			"in" | "inch" => Some(Unit::Inch),
+			_ => None
+		}
+	}
+
+	// length of one unit in meters
+	pub fn meters(self) -> f32
+	{
+		match self
+		{
+			Unit::Millimeter => 0.001,
+			Unit::Centimeter => 0.01,
+			Unit::Meter => 1.0,
+			Unit::Inch => 0.0254
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Conversion
+{
+	// the axis that points up in the file
+	pub up: UpAxis,
+	// the file is left-handed, z is mirrored (which also turns the triangles around)
+	pub flip_handedness: bool,
+	pub unit: Unit
+}
+
+impl Conversion
+{
+	// file coordinates -> viewer coordinates
+	pub fn matrix(&self) -> Matrix4
+	{
+		let s = self.unit.meters();
+		let z = if self.flip_handedness { -s } else { s };
+		match self.up
+		{
+			UpAxis::Y => Matrix4::new(
+				(s, 0.0, 0.0, 0.0).into(),
+				(0.0, s, 0.0, 0.0).into(),
+				(0.0, 0.0, z, 0.0).into(),
+				(0.0, 0.0, 0.0, 1.0).into()
+			),
+			// a quarter turn about x, what was up (+z) ends up at +y and +y goes to -z
+			UpAxis::Z => Matrix4::new(
+				(s, 0.0, 0.0, 0.0).into(),
+				(0.0, 0.0, s, 0.0).into(),
+				(0.0, -z, 0.0, 0.0).into(),
+				(0.0, 0.0, 0.0, 1.0).into()
+			)
+		}
+	}
+
+	// the conversion is a mirror image, counter-clockwise triangles become clockwise
+	pub fn mirrors(&self) -> bool
+	{
+		self.flip_handedness
+	}
+
+	// bounds of the converted model, from the bounds in file coordinates
+	pub fn bounds(&self, bounds: &Bounds) -> Bounds
+	{
+		transform_bounds(&self.matrix(), bounds)
+	}
+}
+
+impl Default for Conversion
+{
+	// no conversion, the file is already y up and in meters
+	fn default() -> Self
+	{
+		Conversion { up: UpAxis::Y, flip_handedness: false, unit: Unit::Meter }
+	}
+}
+
+// Whether `transform` turns the winding of triangles around (its 3x3 part has a negative determinant),
+// exporters write the corners in the other order so the faces keep pointing outwards
+pub fn mirrors(transform: &Matrix4) -> bool
+{
+	let column = |x, y, z| {
+		let c: Vector4 = *transform * Vector4::new(x, y, z, 0.0);
+		Vector3::new(c.x(), c.y(), c.z())
+	};
+	let (x, y, z) = (column(1.0, 0.0, 0.0), column(0.0, 1.0, 0.0), column(0.0, 0.0, 1.0));
+
+	x.dot(&crate::model::cross(y, z)) < 0.0
+}
+
+// smallest box around the transformed corners of bounds
+pub fn transform_bounds(transform: &Matrix4, bounds: &Bounds) -> Bounds
+{
+	let corners = (0..8).map(|i| {
+		let pick = |bit, min: f32, max: f32| if i & bit == 0 { min } else { max };
+		let (min, max) = (bounds.min(), bounds.max());
+		let corner = Vector4::new(pick(1, min.x(), max.x()), pick(2, min.y(), max.y()), pick(4, min.z(), max.z()), 1.0);
+		let p: Vector4 = *transform * corner;
+		Vector3::new(p.x(), p.y(), p.z())
+	});
+
+	let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+	let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+	for p in corners
+	{
+		min = Vector3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z()));
+		max = Vector3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z()));
+	}
+
+	Bounds::new(min, max)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn apply(conversion: &Conversion, p: Vector3) -> Vector3
+	{
+		let p: Vector4 = conversion.matrix() * Vector4::from((p, 1.0));
+		Vector3::new(p.x(), p.y(), p.z())
+	}
+
+	#[test]
+	fn z_up_stands_upright()
+	{
+		let conversion = Conversion { up: UpAxis::Z, ..Conversion::default() };
+		assert_eq!(apply(&conversion, Vector3::new(0.0, 0.0, 1.0)), Vector3::new(0.0, 1.0, 0.0));
+		assert_eq!(apply(&conversion, Vector3::new(0.0, 1.0, 0.0)), Vector3::new(0.0, 0.0, -1.0));
+		assert_eq!(apply(&conversion, Vector3::new(1.0, 0.0, 0.0)), Vector3::new(1.0, 0.0, 0.0));
+		// a rotation, the triangles keep their winding
+		assert!(!mirrors(&conversion.matrix()));
+		assert!(!mirrors(&Conversion::default().matrix()));
+	}
+
+	#[test]
+	fn handedness_mirrors()
+	{
+		for up in [UpAxis::Y, UpAxis::Z]
+		{
+			let conversion = Conversion { up, flip_handedness: true, unit: Unit::Meter };
+			assert!(conversion.mirrors());
+			assert!(mirrors(&conversion.matrix()));
+		}
+
+		let conversion = Conversion { flip_handedness: true, ..Conversion::default() };
+		assert_eq!(apply(&conversion, Vector3::new(1.0, 2.0, 3.0)), Vector3::new(1.0, 2.0, -3.0));
+		let conversion = Conversion { up: UpAxis::Z, flip_handedness: true, unit: Unit::Meter };
+		// still stands upright, mirrored front to back
+		assert_eq!(apply(&conversion, Vector3::new(1.0, 2.0, 3.0)), Vector3::new(1.0, 3.0, 2.0));
+	}
+
+	#[test]
+	fn units_scale_to_meters()
+	{
+		let part = Bounds::new(Vector3::new(-50.0, 0.0, -10.0), Vector3::new(50.0, 200.0, 10.0));
+		let conversion = Conversion { up: UpAxis::Z, unit: Unit::Millimeter, ..Conversion::default() };
+		let bounds = conversion.bounds(&part);
+		assert!((bounds.min() - Vector3::new(-0.05, -0.01, -0.2)).length() < 1e-6, "{:?}", bounds);
+		assert!((bounds.max() - Vector3::new(0.05, 0.01, 0.0)).length() < 1e-6, "{:?}", bounds);
+
+		assert_eq!(Unit::from_name("IN"), Some(Unit::Inch));
+		assert_eq!(UpAxis::from_name("z"), Some(UpAxis::Z));
+		assert_eq!(Unit::from_name("furlong"), None);
+		for name in Unit::NAMES
+		{
+			assert!(Unit::from_name(name).is_some());
+		}
+		assert!((Unit::Inch.meters() * 12.0 - 0.3048).abs() < 1e-6);
+	}
+}
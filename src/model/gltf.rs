@@ -0,0 +1,983 @@
+use math;
+use base64::Engine;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::model::{self, MeshData, SubMesh, Vertex};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const CHUNK_JSON: u32 = 0x4E4F534A;
+const CHUNK_BIN: u32 = 0x004E4942;
+
+const MODE_TRIANGLES: u64 = 4;
+const MODE_TRIANGLE_STRIP: u64 = 5;
+const MODE_TRIANGLE_FAN: u64 = 6;
+
+#[derive(Debug)]
+pub enum Error
+{
+	Io(io::Error),
+	Json(serde_json::Error),
+	Image(image::ImageError),
+	Invalid(String),
+}
+
+impl From<io::Error> for Error
+{
+	fn from(other: io::Error) -> Self
+	{
+		Error::Io(other)
+	}
+}
+
+impl From<serde_json::Error> for Error
+{
+	fn from(other: serde_json::Error) -> Self
+	{
+		Error::Json(other)
+	}
+}
+
+impl From<image::ImageError> for Error
+{
+	fn from(other: image::ImageError) -> Self
+	{
+		Error::Image(other)
+	}
+}
+
+fn invalid<T>(message: impl Into<String>) -> Result<T, Error>
+{
+	Err(Error::Invalid(message.into()))
+}
+
+// PBR metallic-roughness material, texture fields are indices into GltfData::images
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material
+{
+	pub name: String,
+	pub base_color_factor: [f32; 4],
+	pub base_color_texture: Option<usize>,
+	pub metallic_factor: f32,
+	pub roughness_factor: f32,
+	pub metallic_roughness_texture: Option<usize>,
+	pub normal_texture: Option<usize>,
+	pub occlusion_texture: Option<usize>,
+	pub emissive_factor: [f32; 3],
+	pub emissive_texture: Option<usize>,
+	pub double_sided: bool
+}
+
+impl Default for Material
+{
+	// defaults from the spec
+	fn default() -> Self
+	{
+		Material {
+			name: String::new(),
+			base_color_factor: [1.0; 4],
+			base_color_texture: None,
+			metallic_factor: 1.0,
+			roughness_factor: 1.0,
+			metallic_roughness_texture: None,
+			normal_texture: None,
+			occlusion_texture: None,
+			emissive_factor: [0.0; 3],
+			emissive_texture: None,
+			double_sided: false
+		}
+	}
+}
+
+// Everything in the default scene, with node transforms baked into the vertices.
+// Every primitive becomes a submesh whose material is the name of one of `materials`
+pub struct GltfData
+{
+	pub mesh: MeshData,
+	pub materials: Vec<Material>,
+	pub images: Vec<image::RgbaImage>
+}
+
+impl GltfData
+{
+	// image to use for each submesh, None if its material has no base color texture
+	pub fn submesh_images(&self) -> Vec<Option<usize>>
+	{
+		self.mesh.submeshes
+			.iter()
+			.map(|submesh| {
+				self.materials
+					.iter()
+					.find(|m| Some(&m.name) == submesh.material.as_ref())
+					.and_then(|m| m.base_color_texture)
+			})
+			.collect()
+	}
+}
+
+// Loads .gltf (buffers/images next to it or embedded as data uris) and .glb files
+pub fn load<T>(filename: T) -> Result<GltfData, Error>
+where T: AsRef<Path>
+{
+	let path = filename.as_ref();
+	let bytes = fs::read(path)?;
+	let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+	from_bytes(&bytes, Some(&base_dir))
+}
+
+// `base_dir` is where relative uris are looked up, without it only embedded data can be used
+pub fn from_bytes(bytes: &[u8], base_dir: Option<&Path>) -> Result<GltfData, Error>
+{
+	let (json, bin) = if bytes.starts_with(GLB_MAGIC) { split_glb(bytes)? } else { (bytes, None) };
+	let document: Value = serde_json::from_slice(json)?;
+
+	let version = document["asset"]["version"].as_str().unwrap_or("");
+	if !version.starts_with("2.")
+	{
+		return invalid(format!("unsupported gltf version '{}'", version));
+	}
+
+	let loader = Loader {
+		buffers: load_buffers(&document, bin, base_dir)?,
+		document: &document
+	};
+
+	let images = loader.images(base_dir)?;
+	let materials = loader.materials()?;
+	let mesh = loader.scene(&materials)?;
+
+	Ok(GltfData { mesh, materials, images })
+}
+
+// Returns the json chunk and the binary chunk of a .glb file
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), Error>
+{
+	let read_u32 = |pos: usize| -> Result<u32, Error> {
+		match bytes.get(pos..pos + 4)
+		{
+			Some(b) => Ok(u32::from_le_bytes(b.try_into().unwrap())),
+			None => invalid("glb file is truncated")
+		}
+	};
+
+	if read_u32(4)? != 2
+	{
+		return invalid("unsupported glb container version");
+	}
+	let length = (read_u32(8)? as usize).min(bytes.len());
+
+	let mut json = None;
+	let mut bin = None;
+	let mut pos = 12;
+	while pos + 8 <= length
+	{
+		let chunk_length = read_u32(pos)? as usize;
+		let chunk_type = read_u32(pos + 4)?;
+		let data = match bytes.get(pos + 8..pos + 8 + chunk_length)
+		{
+			Some(data) => data,
+			None => return invalid("glb chunk goes past the end of the file")
+		};
+
+		match chunk_type
+		{
+			CHUNK_JSON if json.is_none() => json = Some(data),
+			CHUNK_BIN if bin.is_none() => bin = Some(data),
+			// unknown chunks must be ignored
+			_ => {}
+		}
+
+		// chunks are padded to 4 bytes
+		pos += 8 + chunk_length.next_multiple_of(4);
+	}
+
+	match json
+	{
+		Some(json) => Ok((json, bin)),
+		None => invalid("glb file has no json chunk")
+	}
+}
+
+fn load_buffers(document: &Value, bin: Option<&[u8]>, base_dir: Option<&Path>) -> Result<Vec<Vec<u8>>, Error>
+{
+	let mut buffers = Vec::new();
+
+	for (i, buffer) in array(&document["buffers"]).iter().enumerate()
+	{
+		let data = match buffer["uri"].as_str()
+		{
+			Some(uri) => read_uri(uri, base_dir)?,
+			// only the first buffer of a glb may leave out the uri, it's the binary chunk
+			None if i == 0 => match bin
+			{
+				Some(bin) => bin.to_vec(),
+				None => return invalid("buffer 0 has no uri and there is no binary chunk")
+			},
+			None => return invalid(format!("buffer {} has no uri", i))
+		};
+
+		let length = buffer["byteLength"].as_u64().unwrap_or(0) as usize;
+		if data.len() < length
+		{
+			return invalid(format!("buffer {} is {} bytes, expected {}", i, data.len(), length));
+		}
+
+		buffers.push(data);
+	}
+
+	Ok(buffers)
+}
+
+fn read_uri(uri: &str, base_dir: Option<&Path>) -> Result<Vec<u8>, Error>
+{
+	if let Some(data) = uri.strip_prefix("data:")
+	{
+		let encoded = match data.split_once(";base64,")
+		{
+			Some((_, encoded)) => encoded,
+			None => return invalid("only base64 data uris are supported")
+		};
+
+		return base64::engine::general_purpose::STANDARD
+			.decode(encoded)
+			.or_else(|_| invalid("invalid base64 in data uri"));
+	}
+
+	let base_dir = match base_dir
+	{
+		Some(dir) => dir,
+		None => return invalid(format!("can't resolve external file '{}'", uri))
+	};
+
+	Ok(fs::read(base_dir.join(PathBuf::from(percent_decode(uri))))?)
+}
+
+// uris in gltf are percent encoded ("my%20texture.png")
+fn percent_decode(uri: &str) -> String
+{
+	let bytes = uri.as_bytes();
+	let mut out = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+
+	while i < bytes.len()
+	{
+		let hex = bytes.get(i + 1..i + 3)
+			.and_then(|h| std::str::from_utf8(h).ok())
+			.and_then(|h| u8::from_str_radix(h, 16).ok());
+
+		match hex
+		{
+			Some(value) if bytes[i] == b'%' => {
+				out.push(value);
+				i += 3;
+			},
+			_ => {
+				out.push(bytes[i]);
+				i += 1;
+			}
+		}
+	}
+
+	String::from_utf8_lossy(&out).into_owned()
+}
+
+fn array(value: &Value) -> &[Value]
+{
+	value.as_array().map(Vec::as_slice).unwrap_or(&[])
+}
+
+fn index(value: &Value) -> Option<usize>
+{
+	value.as_u64().map(|i| i as usize)
+}
+
+fn floats<const N: usize>(value: &Value, default: [f32; N]) -> [f32; N]
+{
+	let mut result = default;
+	for (out, v) in result.iter_mut().zip(array(value))
+	{
+		if let Some(v) = v.as_f64()
+		{
+			*out = v as f32;
+		}
+	}
+
+	result
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ComponentType
+{
+	I8,
+	U8,
+	I16,
+	U16,
+	U32,
+	F32
+}
+
+impl ComponentType
+{
+	fn from_code(code: u64) -> Result<Self, Error>
+	{
+		match code
+		{
+			5120 => Ok(ComponentType::I8),
+			5121 => Ok(ComponentType::U8),
+			5122 => Ok(ComponentType::I16),
+			5123 => Ok(ComponentType::U16),
+			5125 => Ok(ComponentType::U32),
+			5126 => Ok(ComponentType::F32),
+			_ => invalid(format!("unknown component type {}", code))
+		}
+	}
+
+	fn size(&self) -> usize
+	{
+		match self
+		{
+			ComponentType::I8 | ComponentType::U8 => 1,
+			ComponentType::I16 | ComponentType::U16 => 2,
+			ComponentType::U32 | ComponentType::F32 => 4
+		}
+	}
+
+	// `normalized` maps integers to 0..1 (or -1..1 for signed types)
+	fn read(&self, bytes: &[u8], normalized: bool) -> f64
+	{
+		let (value, max) = match self
+		{
+			ComponentType::I8 => (bytes[0] as i8 as f64, i8::MAX as f64),
+			ComponentType::U8 => (bytes[0] as f64, u8::MAX as f64),
+			ComponentType::I16 => (i16::from_le_bytes([bytes[0], bytes[1]]) as f64, i16::MAX as f64),
+			ComponentType::U16 => (u16::from_le_bytes([bytes[0], bytes[1]]) as f64, u16::MAX as f64),
+			ComponentType::U32 => (u32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64, u32::MAX as f64),
+			ComponentType::F32 => return f32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64
+		};
+
+		if normalized { (value / max).max(-1.0) } else { value }
+	}
+}
+
+fn component_count(ty: &str) -> Result<usize, Error>
+{
+	match ty
+	{
+		"SCALAR" => Ok(1),
+		"VEC2" => Ok(2),
+		"VEC3" => Ok(3),
+		"VEC4" | "MAT2" => Ok(4),
+		"MAT3" => Ok(9),
+		"MAT4" => Ok(16),
+		_ => invalid(format!("unknown accessor type '{}'", ty))
+	}
+}
+
+struct Loader<'a>
+{
+	document: &'a Value,
+	buffers: Vec<Vec<u8>>
+}
+
+impl<'a> Loader<'a>
+{
+	// Bytes of a buffer view starting at `offset`, and the distance between elements
+	fn view(&self, view: usize, offset: usize, element_size: usize, count: usize) -> Result<(&[u8], usize), Error>
+	{
+		let view_json = &self.document["bufferViews"][view];
+		let buffer = index(&view_json["buffer"])
+			.and_then(|b| self.buffers.get(b))
+			.ok_or_else(|| Error::Invalid(format!("buffer view {} has no valid buffer", view)))?;
+		let view_offset = index(&view_json["byteOffset"]).unwrap_or(0);
+		let view_length = index(&view_json["byteLength"]).unwrap_or(0);
+		let stride = index(&view_json["byteStride"]).unwrap_or(element_size);
+
+		let data = match view_offset.checked_add(view_length).and_then(|end| buffer.get(view_offset..end))
+		{
+			Some(data) => data,
+			None => return invalid(format!("buffer view {} is out of its buffer's range", view))
+		};
+
+		// offset + stride * (count - 1) + element_size, the counts come from the file so they can be anything
+		let needed = match count
+		{
+			0 => Some(0),
+			_ => stride.checked_mul(count - 1).and_then(|n| n.checked_add(offset)).and_then(|n| n.checked_add(element_size))
+		};
+		match needed
+		{
+			Some(needed) if needed <= data.len() => Ok((&data[offset.min(data.len())..], stride)),
+			_ => invalid(format!("buffer view {} is too short for its accessor", view))
+		}
+	}
+
+	// Returns `count * components` values, with sparse substitution applied
+	fn read_accessor(&self, accessor: usize) -> Result<(Vec<f64>, usize), Error>
+	{
+		let json = &self.document["accessors"][accessor];
+		if json.is_null()
+		{
+			return invalid(format!("accessor {} does not exist", accessor));
+		}
+
+		let component_type = ComponentType::from_code(json["componentType"].as_u64().unwrap_or(0))?;
+		let normalized = json["normalized"].as_bool().unwrap_or(false);
+		let count = index(&json["count"]).unwrap_or(0);
+		let components = component_count(json["type"].as_str().unwrap_or(""))?;
+		let element_size = component_type.size() * components;
+		let too_large = || Error::Invalid(format!("accessor {} has too many elements ({})", accessor, count));
+		let value_count = count.checked_mul(components).ok_or_else(too_large)?;
+
+		// the data is checked before anything is allocated for it, so a made up count can't exhaust memory
+		let view = match index(&json["bufferView"])
+		{
+			Some(view) => Some(self.view(view, index(&json["byteOffset"]).unwrap_or(0), element_size, count)?),
+			None => {
+				// all zeros (only useful with sparse values), still no larger than the data in the file
+				let total: usize = self.buffers.iter().map(|b| b.len()).sum();
+				match count.checked_mul(element_size)
+				{
+					Some(size) if size <= total => None,
+					_ => return Err(too_large())
+				}
+			}
+		};
+
+		let mut values = vec![0.0; value_count];
+		if let Some((data, stride)) = view
+		{
+			for i in 0..count
+			{
+				for c in 0..components
+				{
+					let start = i * stride + c * component_type.size();
+					values[i * components + c] = component_type.read(&data[start..], normalized);
+				}
+			}
+		}
+
+		let sparse = &json["sparse"];
+		if !sparse.is_null()
+		{
+			let sparse_count = index(&sparse["count"]).unwrap_or(0);
+
+			let indices = &sparse["indices"];
+			let index_type = ComponentType::from_code(indices["componentType"].as_u64().unwrap_or(0))?;
+			let index_view = index(&indices["bufferView"]).ok_or(Error::Invalid("sparse indices need a buffer view".into()))?;
+			let (index_data, _) = self.view(index_view, index(&indices["byteOffset"]).unwrap_or(0), index_type.size(), sparse_count)?;
+
+			let sparse_values = &sparse["values"];
+			let value_view = index(&sparse_values["bufferView"]).ok_or(Error::Invalid("sparse values need a buffer view".into()))?;
+			let (value_data, _) = self.view(value_view, index(&sparse_values["byteOffset"]).unwrap_or(0), element_size, sparse_count)?;
+
+			// view() checked them with the view's stride, sparse data is always tightly packed
+			let packed = |size: usize, data: &[u8]| sparse_count.checked_mul(size).is_some_and(|needed| needed <= data.len());
+			if !packed(index_type.size(), index_data) || !packed(element_size, value_data)
+			{
+				return invalid(format!("sparse data of accessor {} is out of range", accessor));
+			}
+
+			for i in 0..sparse_count
+			{
+				// sparse data is always tightly packed
+				let target = index_type.read(&index_data[i * index_type.size()..], false) as usize;
+				if target >= count
+				{
+					return invalid(format!("sparse index {} out of range in accessor {}", target, accessor));
+				}
+				for c in 0..components
+				{
+					let start = i * element_size + c * component_type.size();
+					values[target * components + c] = component_type.read(&value_data[start..], normalized);
+				}
+			}
+		}
+
+		Ok((values, components))
+	}
+
+	fn images(&self, base_dir: Option<&Path>) -> Result<Vec<image::RgbaImage>, Error>
+	{
+		let mut images = Vec::new();
+
+		for (i, json) in array(&self.document["images"]).iter().enumerate()
+		{
+			let bytes = if let Some(uri) = json["uri"].as_str()
+			{
+				read_uri(uri, base_dir)?
+			}
+			else if let Some(view) = index(&json["bufferView"])
+			{
+				let length = index(&self.document["bufferViews"][view]["byteLength"]).unwrap_or(0);
+				self.view(view, 0, length, 1)?.0[..length].to_vec()
+			}
+			else
+			{
+				return invalid(format!("image {} has neither a uri nor a buffer view", i));
+			};
+
+			images.push(image::load_from_memory(&bytes)?.into_rgba8());
+		}
+
+		Ok(images)
+	}
+
+	fn materials(&self) -> Result<Vec<Material>, Error>
+	{
+		// gltf textures point to images, materials point to textures
+		let texture_image = |info: &Value| -> Option<usize> {
+			let texture = index(&info["index"])?;
+			index(&self.document["textures"][texture]["source"])
+		};
+
+		let mut materials = Vec::new();
+		for (i, json) in array(&self.document["materials"]).iter().enumerate()
+		{
+			let pbr = &json["pbrMetallicRoughness"];
+			let defaults = Material::default();
+
+			materials.push(Material {
+				// submeshes refer to materials by name, so every material needs a unique one
+				name: match json["name"].as_str()
+				{
+					Some(name) if !materials.iter().any(|m: &Material| m.name == name) => name.to_string(),
+					_ => format!("material_{}", i)
+				},
+				base_color_factor: floats(&pbr["baseColorFactor"], defaults.base_color_factor),
+				base_color_texture: texture_image(&pbr["baseColorTexture"]),
+				metallic_factor: pbr["metallicFactor"].as_f64().map(|f| f as f32).unwrap_or(defaults.metallic_factor),
+				roughness_factor: pbr["roughnessFactor"].as_f64().map(|f| f as f32).unwrap_or(defaults.roughness_factor),
+				metallic_roughness_texture: texture_image(&pbr["metallicRoughnessTexture"]),
+				normal_texture: texture_image(&json["normalTexture"]),
+				occlusion_texture: texture_image(&json["occlusionTexture"]),
+				emissive_factor: floats(&json["emissiveFactor"], defaults.emissive_factor),
+				emissive_texture: texture_image(&json["emissiveTexture"]),
+				double_sided: json["doubleSided"].as_bool().unwrap_or(false)
+			});
+		}
+
+		Ok(materials)
+	}
+
+	// Walks the node hierarchy of the default scene (or the first one) and collects every primitive
+	fn scene(&self, materials: &[Material]) -> Result<MeshData, Error>
+	{
+		let mut mesh = MeshData::new(Vec::new(), Vec::new());
+		mesh.submeshes.clear();
+		let identity = math::matrix::Matrix4::new_identity();
+
+		let scene = index(&self.document["scene"]).or(if array(&self.document["scenes"]).is_empty() { None } else { Some(0) });
+		let roots: Vec<usize> = match scene
+		{
+			Some(scene) => array(&self.document["scenes"][scene]["nodes"]).iter().filter_map(index).collect(),
+			None => {
+				// no scenes at all: show every mesh untransformed
+				for m in 0..array(&self.document["meshes"]).len()
+				{
+					self.add_mesh(&mut mesh, m, &identity, materials)?;
+				}
+				Vec::new()
+			}
+		};
+
+		// a valid file is a tree with every node in it once, a node reached twice means a cycle or a node shared
+		// between parents (which could repeat a subtree exponentially often)
+		let mut visited = vec![false; array(&self.document["nodes"]).len()];
+		let mut stack: Vec<(usize, math::matrix::Matrix4)> = roots.into_iter().map(|n| (n, identity)).collect();
+		while let Some((node, parent)) = stack.pop()
+		{
+			match visited.get_mut(node)
+			{
+				None => return invalid(format!("node {} does not exist", node)),
+				Some(true) => return invalid(format!("node {} is in the hierarchy more than once", node)),
+				Some(seen) => *seen = true
+			}
+
+			let json = &self.document["nodes"][node];
+
+			let transform = parent * node_transform(json);
+			if let Some(mesh_index) = index(&json["mesh"])
+			{
+				self.add_mesh(&mut mesh, mesh_index, &transform, materials)?;
+			}
+			for child in array(&json["children"]).iter().filter_map(index)
+			{
+				stack.push((child, transform));
+			}
+		}
+
+		mesh.bounds = model::Bounds::from_vertices(&mesh.vertices);
+		Ok(mesh)
+	}
+
+	fn add_mesh(&self, mesh: &mut MeshData, mesh_index: usize, transform: &math::matrix::Matrix4, materials: &[Material]) -> Result<(), Error>
+	{
+		let json = &self.document["meshes"][mesh_index];
+		if json.is_null()
+		{
+			return invalid(format!("mesh {} does not exist", mesh_index));
+		}
+
+		let normal_matrix = NormalMatrix::new(transform);
+		let mut rng = model::color::vertex_rng();
+
+		for (p, primitive) in array(&json["primitives"]).iter().enumerate()
+		{
+			let mode = primitive["mode"].as_u64().unwrap_or(MODE_TRIANGLES);
+			if mode != MODE_TRIANGLES && mode != MODE_TRIANGLE_STRIP && mode != MODE_TRIANGLE_FAN
+			{
+				// points and lines have no surface to draw
+				continue;
+			}
+
+			let attributes = &primitive["attributes"];
+			let positions = match index(&attributes["POSITION"])
+			{
+				Some(accessor) => self.read_accessor(accessor)?,
+				None => continue
+			};
+			let normals = index(&attributes["NORMAL"]).map(|a| self.read_accessor(a)).transpose()?;
+			let texcoords = index(&attributes["TEXCOORD_0"]).map(|a| self.read_accessor(a)).transpose()?;
+			let colors = index(&attributes["COLOR_0"]).map(|a| self.read_accessor(a)).transpose()?;
+			mesh.vertex_colors |= colors.is_some();
+
+			let material = index(&primitive["material"]).and_then(|m| materials.get(m));
+			let base_color = material.map(|m| m.base_color_factor).unwrap_or([1.0; 4]);
+
+			let first_vertex = mesh.vertices.len();
+			let count = positions.0.len() / positions.1.max(1);
+			for i in 0..count
+			{
+				let get = |data: &(Vec<f64>, usize), c: usize| data.0.get(i * data.1 + c).copied().unwrap_or(0.0) as f32;
+
+				let position = math::vector::Vector3::new(get(&positions, 0), get(&positions, 1), get(&positions, 2));
+				let world: math::vector::Vector4 = *transform * math::vector::Vector4::from((position, 1.0));
+
+				let color = match &colors
+				{
+					Some(colors) => (get(colors, 0) * base_color[0], get(colors, 1) * base_color[1], get(colors, 2) * base_color[2]).into(),
+					None if material.is_some() => (base_color[0], base_color[1], base_color[2]).into(),
+					None => model::random_color(&mut rng)
+				};
+				let texcoord = match &texcoords
+				{
+					Some(texcoords) => (get(texcoords, 0), get(texcoords, 1)).into(),
+					None => (position.x(), position.y()).into()
+				};
+
+				let mut vertex = Vertex::new((world.x(), world.y(), world.z()).into(), color, texcoord);
+				if let Some(normals) = &normals
+				{
+					vertex.normal = normal_matrix.apply((get(normals, 0), get(normals, 1), get(normals, 2)).into());
+				}
+				mesh.vertices.push(vertex);
+			}
+
+			let local: Vec<u32> = match index(&primitive["indices"])
+			{
+				Some(accessor) => self.read_accessor(accessor)?.0.into_iter().map(|i| i as u32).collect(),
+				None => (0..count as u32).collect()
+			};
+			if let Some(bad) = local.iter().find(|&&i| i as usize >= count)
+			{
+				return invalid(format!("mesh {} primitive {} has index {} but only {} vertices", mesh_index, p, bad, count));
+			}
+
+			let mut triangles = triangulate(&local, mode);
+			// a mirroring transform turns the winding inside out
+			if normal_matrix.mirrored
+			{
+				for triangle in triangles.chunks_exact_mut(3)
+				{
+					triangle.swap(1, 2);
+				}
+			}
+
+			let first = mesh.indices.len() as u32;
+			mesh.indices.extend(triangles.iter().map(|&i| i + first_vertex as u32));
+			mesh.submeshes.push(SubMesh {
+				name: json["name"].as_str().map(str::to_string).unwrap_or_else(|| format!("mesh_{}", mesh_index)),
+				material: material.map(|m| m.name.clone()),
+				first,
+				count: mesh.indices.len() as u32 - first
+			});
+
+			if normals.is_none()
+			{
+				generate_normals(mesh, first_vertex, first as usize);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+// Normals of a primitive that came without any, computed from the transformed positions
+fn generate_normals(mesh: &mut MeshData, first_vertex: usize, first_index: usize)
+{
+	let mut part = MeshData::new(
+		mesh.vertices[first_vertex..].to_vec(),
+		mesh.indices[first_index..].iter().map(|&i| i - first_vertex as u32).collect()
+	);
+	part.generate_normals();
+
+	for (vertex, generated) in mesh.vertices[first_vertex..].iter_mut().zip(part.vertices)
+	{
+		vertex.normal = generated.normal;
+	}
+}
+
+fn triangulate(indices: &[u32], mode: u64) -> Vec<u32>
+{
+	match mode
+	{
+		MODE_TRIANGLE_STRIP => (2..indices.len())
+			.flat_map(|i| {
+				// every other triangle of a strip is wound the other way
+				if i % 2 == 0 { [indices[i - 2], indices[i - 1], indices[i]] } else { [indices[i - 1], indices[i - 2], indices[i]] }
+			})
+			.collect(),
+		MODE_TRIANGLE_FAN => (2..indices.len())
+			.flat_map(|i| [indices[0], indices[i - 1], indices[i]])
+			.collect(),
+		_ => indices[..indices.len() / 3 * 3].to_vec()
+	}
+}
+
+// Local transform of a node, either given as a matrix or as translation * rotation * scale
+fn node_transform(node: &Value) -> math::matrix::Matrix4
+{
+	if node["matrix"].is_array()
+	{
+		// column major
+		let m = floats(&node["matrix"], [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+		return math::matrix::Matrix4::new(
+			(m[0], m[4], m[8], m[12]).into(),
+			(m[1], m[5], m[9], m[13]).into(),
+			(m[2], m[6], m[10], m[14]).into(),
+			(m[3], m[7], m[11], m[15]).into()
+		);
+	}
+
+	let [tx, ty, tz] = floats(&node["translation"], [0.0; 3]);
+	let [x, y, z, w] = floats(&node["rotation"], [0.0, 0.0, 0.0, 1.0]);
+	let [sx, sy, sz] = floats(&node["scale"], [1.0; 3]);
+
+	math::matrix::Matrix4::new(
+		((1.0 - 2.0 * (y * y + z * z)) * sx, (2.0 * (x * y - z * w)) * sy, (2.0 * (x * z + y * w)) * sz, tx).into(),
+		((2.0 * (x * y + z * w)) * sx, (1.0 - 2.0 * (x * x + z * z)) * sy, (2.0 * (y * z - x * w)) * sz, ty).into(),
+		((2.0 * (x * z - y * w)) * sx, (2.0 * (y * z + x * w)) * sy, (1.0 - 2.0 * (x * x + y * y)) * sz, tz).into(),
+		(0.0, 0.0, 0.0, 1.0).into()
+	)
+}
+
+// Transforms normals with the inverse transpose of the upper 3x3 of a matrix, so non-uniform scaling works
+struct NormalMatrix
+{
+	// columns of the cofactor matrix (determinant * inverse transpose)
+	columns: [math::vector::Vector3; 3],
+	mirrored: bool
+}
+
+impl NormalMatrix
+{
+	fn new(m: &math::matrix::Matrix4) -> Self
+	{
+		let column = |v: (f32, f32, f32)| {
+			let c: math::vector::Vector4 = *m * math::vector::Vector4::from((math::vector::Vector3::from(v), 0.0));
+			math::vector::Vector3::new(c.x(), c.y(), c.z())
+		};
+		let (c0, c1, c2) = (column((1.0, 0.0, 0.0)), column((0.0, 1.0, 0.0)), column((0.0, 0.0, 1.0)));
+		let determinant = c0.dot(&model::cross(c1, c2));
+
+		NormalMatrix {
+			columns: [model::cross(c1, c2), model::cross(c2, c0), model::cross(c0, c1)],
+			mirrored: determinant < 0.0
+		}
+	}
+
+	fn apply(&self, normal: math::vector::Vector3) -> math::vector::Vector3
+	{
+		let mut n = self.columns[0] * normal.x() + self.columns[1] * normal.y() + self.columns[2] * normal.z();
+		if self.mirrored
+		{
+			n = -n;
+		}
+
+		let length = n.length();
+		if length > 0.0 { n / length } else { n }
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// triangle with positions, normals and texcoords, indexed, plus a sparse accessor that moves vertex 2
+	fn triangle_buffer() -> Vec<u8>
+	{
+		let mut bytes = Vec::new();
+		let floats = [
+			0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, // positions
+			0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, // normals
+			0.0, 0.0, 1.0, 0.0, 0.0, 1.0 // texcoords
+		];
+		for f in floats
+		{
+			bytes.extend_from_slice(&f.to_le_bytes());
+		}
+		for i in [0u16, 1, 2, 0]
+		{
+			// last one is padding
+			bytes.extend_from_slice(&i.to_le_bytes());
+		}
+		// sparse: index 2 -> (0, 2, 0)
+		bytes.extend_from_slice(&2u32.to_le_bytes());
+		for f in [0.0f32, 2.0, 0.0]
+		{
+			bytes.extend_from_slice(&f.to_le_bytes());
+		}
+		bytes
+	}
+
+	fn triangle_json(buffer_uri: Option<String>, length: usize) -> String
+	{
+		let uri = buffer_uri.map(|u| format!("\"uri\": \"{}\",", u)).unwrap_or_default();
+		format!(r#"{{
+			"asset": {{ "version": "2.0" }},
+			"scene": 0,
+			"scenes": [{{ "nodes": [0] }}],
+			"nodes": [
+				{{ "translation": [10, 0, 0], "children": [1] }},
+				{{ "mesh": 0, "scale": [2, 2, 2] }}
+			],
+			"meshes": [{{ "name": "tri", "primitives": [{{
+				"attributes": {{ "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }},
+				"indices": 3,
+				"material": 0
+			}}] }}],
+			"materials": [{{
+				"name": "red",
+				"pbrMetallicRoughness": {{ "baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0.5 }}
+			}}],
+			"buffers": [{{ {} "byteLength": {} }}],
+			"bufferViews": [
+				{{ "buffer": 0, "byteOffset": 0, "byteLength": 96 }},
+				{{ "buffer": 0, "byteOffset": 96, "byteLength": 6 }},
+				{{ "buffer": 0, "byteOffset": 104, "byteLength": 4 }},
+				{{ "buffer": 0, "byteOffset": 108, "byteLength": 12 }}
+			],
+			"accessors": [
+				{{ "bufferView": 0, "byteOffset": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+				   "sparse": {{ "count": 1, "indices": {{ "bufferView": 2, "componentType": 5125 }}, "values": {{ "bufferView": 3 }} }} }},
+				{{ "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3" }},
+				{{ "bufferView": 0, "byteOffset": 72, "componentType": 5126, "count": 3, "type": "VEC2" }},
+				{{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+			]
+		}}"#, uri, length)
+	}
+
+	fn check_triangle(data: &GltfData)
+	{
+		let mesh = &data.mesh;
+		assert_eq!(mesh.indices, vec![0, 1, 2]);
+		// scaled by 2, then moved by 10 on x
+		assert_eq!(mesh.vertices[0].position(), (10.0, 0.0, 0.0).into());
+		assert_eq!(mesh.vertices[1].position(), (12.0, 0.0, 0.0).into());
+		// sparse value replaced (0, 1, 0)
+		assert_eq!(mesh.vertices[2].position(), (10.0, 4.0, 0.0).into());
+		assert_eq!(mesh.vertices[1].normal(), (0.0, 0.0, 1.0).into());
+		assert_eq!(mesh.vertices[2].texcoord(), (0.0, 1.0).into());
+		assert_eq!(mesh.vertices[0].color(), (1.0, 0.0, 0.0).into());
+
+		assert_eq!(mesh.submeshes.len(), 1);
+		assert_eq!(mesh.submeshes[0].material.as_deref(), Some("red"));
+		assert_eq!(data.materials[0].metallic_factor, 0.5);
+		assert_eq!(data.materials[0].roughness_factor, 1.0);
+	}
+
+	#[test]
+	fn gltf_with_data_uri()
+	{
+		let buffer = triangle_buffer();
+		let uri = format!("data:application/octet-stream;base64,{}", base64::engine::general_purpose::STANDARD.encode(&buffer));
+		let json = triangle_json(Some(uri), buffer.len());
+
+		check_triangle(&from_bytes(json.as_bytes(), None).unwrap());
+	}
+
+	#[test]
+	fn glb()
+	{
+		let mut json = triangle_json(None, triangle_buffer().len()).into_bytes();
+		json.resize(json.len().next_multiple_of(4), b' ');
+		let buffer = triangle_buffer();
+
+		let mut glb = Vec::new();
+		glb.extend_from_slice(GLB_MAGIC);
+		glb.extend_from_slice(&2u32.to_le_bytes());
+		glb.extend_from_slice(&((12 + 8 + json.len() + 8 + buffer.len()) as u32).to_le_bytes());
+		glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+		glb.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+		glb.extend_from_slice(&json);
+		glb.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+		glb.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+		glb.extend_from_slice(&buffer);
+
+		check_triangle(&from_bytes(&glb, None).unwrap());
+	}
+
+	#[test]
+	fn mirrored_node_flips_winding()
+	{
+		let buffer = triangle_buffer();
+		let uri = format!("data:application/octet-stream;base64,{}", base64::engine::general_purpose::STANDARD.encode(&buffer));
+		let json = triangle_json(Some(uri), buffer.len()).replace("\"scale\": [2, 2, 2]", "\"scale\": [-1, 1, 1]");
+
+		let data = from_bytes(json.as_bytes(), None).unwrap();
+		assert_eq!(data.mesh.indices, vec![0, 2, 1]);
+		assert_eq!(data.mesh.vertices[0].normal(), (0.0, 0.0, 1.0).into());
+	}
+
+	fn triangle_with(edit: impl Fn(String) -> String) -> Result<GltfData, Error>
+	{
+		let buffer = triangle_buffer();
+		let uri = format!("data:application/octet-stream;base64,{}", base64::engine::general_purpose::STANDARD.encode(&buffer));
+		from_bytes(edit(triangle_json(Some(uri), buffer.len())).as_bytes(), None)
+	}
+
+	#[test]
+	fn rejects_out_of_range_sizes()
+	{
+		let invalid = |result: Result<GltfData, Error>| matches!(result, Err(Error::Invalid(_)));
+
+		// nothing is allocated for a count the data can't hold
+		assert!(invalid(triangle_with(|json| json.replace("\"count\": 3, \"type\": \"SCALAR\"", "\"count\": 4611686018427387904, \"type\": \"SCALAR\""))));
+		assert!(invalid(triangle_with(|json| json.replace("\"bufferView\": 1, \"componentType\": 5123, \"count\": 3", "\"componentType\": 5123, \"count\": 1000000000000"))));
+		assert!(invalid(triangle_with(|json| json.replace("\"byteOffset\": 96, \"byteLength\": 6", "\"byteOffset\": 18446744073709551615, \"byteLength\": 6"))));
+		assert!(invalid(triangle_with(|json| json.replace("\"byteOffset\": 0, \"byteLength\": 96", "\"byteOffset\": 0, \"byteLength\": 96, \"byteStride\": 9223372036854775807"))));
+		assert!(invalid(triangle_with(|json| json.replace("\"sparse\": { \"count\": 1", "\"sparse\": { \"count\": 2"))));
+	}
+
+	#[test]
+	fn rejects_shared_and_cyclic_nodes()
+	{
+		let invalid = |result: Result<GltfData, Error>| matches!(result, Err(Error::Invalid(_)));
+
+		assert!(triangle_with(|json| json).is_ok());
+		// the same child twice would otherwise double the instances at every level
+		assert!(invalid(triangle_with(|json| json.replace("\"children\": [1]", "\"children\": [1, 1]"))));
+		assert!(invalid(triangle_with(|json| json.replace("{ \"mesh\": 0,", "{ \"children\": [0], \"mesh\": 0,"))));
+		assert!(invalid(triangle_with(|json| json.replace("\"children\": [1]", "\"children\": [5]"))));
+	}
+
+	#[test]
+	fn strips_and_fans()
+	{
+		assert_eq!(triangulate(&[0, 1, 2, 3], MODE_TRIANGLE_STRIP), vec![0, 1, 2, 2, 1, 3]);
+		assert_eq!(triangulate(&[0, 1, 2, 3], MODE_TRIANGLE_FAN), vec![0, 1, 2, 0, 2, 3]);
+	}
+}
@@ -0,0 +1,647 @@
+// Half-edge representation of a triangle mesh, for walking its topology.
+//
+// Every triangle owns 3 consecutive half-edges (face f has 3f, 3f + 1, 3f + 2, going around it in
+// index order), so `next`, `prev` and `face` are arithmetic and only the twins are stored.
+// Topology follows the indices: two vertices at the same position are different vertices, so
+// uv seams and hard edges look like boundaries. Weld the mesh first if they shouldn't.
+
+use std::collections::HashMap;
+use crate::model::{MeshData, SubMesh, Vertex};
+
+// no half-edge: the twin of a boundary half-edge, or the outgoing half-edge of an unused vertex
+pub const NONE: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HalfEdge
+{
+	// vertex it starts from
+	pub vertex: u32,
+	// same edge in the neighbouring triangle, going the other way
+	pub twin: u32
+}
+
+#[derive(Clone, Debug)]
+pub struct HalfEdgeMesh
+{
+	pub vertices: Vec<Vertex>,
+	half_edges: Vec<HalfEdge>,
+	// one half-edge starting at each vertex, a boundary one if the vertex is on a boundary
+	outgoing: Vec<u32>,
+	// index into `submeshes` for every face
+	face_groups: Vec<u32>,
+	// names and materials of the groups, their ranges are rebuilt by to_mesh_data
+	submeshes: Vec<SubMesh>,
+	material_libs: Vec<String>,
+	// edges that already had two triangles when another one used them, or were used twice in the same direction
+	non_manifold_edges: usize
+}
+
+impl HalfEdgeMesh
+{
+	// Triangles with out of range or repeated indices are left out
+	pub fn from_mesh_data(data: &MeshData) -> Self
+	{
+		let mut mesh = HalfEdgeMesh {
+			vertices: data.vertices.clone(),
+			half_edges: Vec::with_capacity(data.indices.len()),
+			outgoing: vec![NONE; data.vertices.len()],
+			face_groups: Vec::with_capacity(data.indices.len() / 3),
+			submeshes: data.submeshes.clone(),
+			material_libs: data.material_libs.clone(),
+			non_manifold_edges: 0
+		};
+
+		for (group, submesh) in data.submeshes.iter().enumerate()
+		{
+			let first = (submesh.first as usize).min(data.indices.len());
+			let end = (first + submesh.count as usize).min(data.indices.len());
+			for t in data.indices[first..end].chunks_exact(3)
+			{
+				let valid = t.iter().all(|&i| (i as usize) < data.vertices.len()) && t[0] != t[1] && t[1] != t[2] && t[0] != t[2];
+				if valid
+				{
+					mesh.half_edges.extend(t.iter().map(|&vertex| HalfEdge { vertex, twin: NONE }));
+					mesh.face_groups.push(group as u32);
+				}
+			}
+		}
+
+		let mut directed = HashMap::<(u32, u32), u32>::with_capacity(mesh.half_edges.len());
+		for h in 0..mesh.half_edges.len() as u32
+		{
+			let key = (mesh.origin(h), mesh.target(h));
+			if directed.insert(key, h).is_some()
+			{
+				// same direction twice, the faces disagree on which side is the front
+				mesh.non_manifold_edges += 1;
+			}
+		}
+
+		for h in 0..mesh.half_edges.len() as u32
+		{
+			if mesh.half_edges[h as usize].twin != NONE
+			{
+				continue;
+			}
+
+			match directed.get(&(mesh.target(h), mesh.origin(h)))
+			{
+				Some(&twin) if mesh.half_edges[twin as usize].twin == NONE && twin != h => mesh.link(h, twin),
+				Some(_) => mesh.non_manifold_edges += 1,
+				None => {}
+			}
+		}
+
+		for h in 0..mesh.half_edges.len() as u32
+		{
+			let vertex = mesh.origin(h) as usize;
+			if mesh.outgoing[vertex] == NONE || mesh.twin(h) == NONE
+			{
+				mesh.outgoing[vertex] = h;
+			}
+		}
+
+		mesh
+	}
+
+	pub fn to_mesh_data(&self) -> MeshData
+	{
+		let mut indices = Vec::with_capacity(self.half_edges.len());
+		let mut submeshes = self.submeshes.clone();
+
+		for (group, submesh) in submeshes.iter_mut().enumerate()
+		{
+			let first = indices.len();
+			for face in (0..self.face_count()).filter(|&f| self.face_groups[f] == group as u32)
+			{
+				indices.extend(self.half_edges[face * 3..face * 3 + 3].iter().map(|h| h.vertex));
+			}
+			submesh.first = first as u32;
+			submesh.count = (indices.len() - first) as u32;
+		}
+
+		let mut data = MeshData::new(self.vertices.clone(), indices);
+		data.submeshes = submeshes;
+		data.material_libs = self.material_libs.clone();
+		data
+	}
+
+	pub fn face_count(&self) -> usize
+	{
+		self.half_edges.len() / 3
+	}
+
+	pub fn half_edge_count(&self) -> usize
+	{
+		self.half_edges.len()
+	}
+
+	pub fn half_edge(&self, h: u32) -> HalfEdge
+	{
+		self.half_edges[h as usize]
+	}
+
+	pub fn next(&self, h: u32) -> u32
+	{
+		h - h % 3 + (h + 1) % 3
+	}
+
+	pub fn prev(&self, h: u32) -> u32
+	{
+		h - h % 3 + (h + 2) % 3
+	}
+
+	pub fn twin(&self, h: u32) -> u32
+	{
+		self.half_edges[h as usize].twin
+	}
+
+	pub fn face(&self, h: u32) -> usize
+	{
+		h as usize / 3
+	}
+
+	pub fn origin(&self, h: u32) -> u32
+	{
+		self.half_edges[h as usize].vertex
+	}
+
+	pub fn target(&self, h: u32) -> u32
+	{
+		self.origin(self.next(h))
+	}
+
+	pub fn face_vertices(&self, face: usize) -> [u32; 3]
+	{
+		[self.half_edges[face * 3].vertex, self.half_edges[face * 3 + 1].vertex, self.half_edges[face * 3 + 2].vertex]
+	}
+
+	// index of the submesh the face came from
+	pub fn face_group(&self, face: usize) -> u32
+	{
+		self.face_groups[face]
+	}
+
+	// NONE for vertices no face uses
+	pub fn outgoing(&self, vertex: u32) -> u32
+	{
+		self.outgoing[vertex as usize]
+	}
+
+	pub fn is_boundary_edge(&self, h: u32) -> bool
+	{
+		self.twin(h) == NONE
+	}
+
+	pub fn is_boundary_vertex(&self, vertex: u32) -> bool
+	{
+		let h = self.outgoing(vertex);
+		h != NONE && self.twin(h) == NONE
+	}
+
+	// Half-edges leaving `vertex`, counter-clockwise (for counter-clockwise faces).
+	// On a boundary this starts at the boundary half-edge; on a non-manifold vertex it only covers one fan
+	pub fn outgoing_half_edges(&self, vertex: u32) -> Outgoing<'_>
+	{
+		let start = self.outgoing(vertex);
+		Outgoing { mesh: self, start, current: start }
+	}
+
+	// Neighbouring vertices in order, including both ends of an open fan
+	pub fn one_ring(&self, vertex: u32) -> impl Iterator<Item = u32> + '_
+	{
+		let ring = self.outgoing_half_edges(vertex).map(|h| self.target(h));
+
+		// an open fan ends with an edge that only the last face has
+		let mut tail_done = false;
+		ring.chain(std::iter::from_fn(move || {
+			if tail_done
+			{
+				return None;
+			}
+			tail_done = true;
+
+			let start = self.outgoing(vertex);
+			if start == NONE || self.twin(start) != NONE
+			{
+				return None;
+			}
+			// walk to the last face of the fan
+			let mut h = start;
+			while self.twin(self.prev(h)) != NONE
+			{
+				h = self.twin(self.prev(h));
+				if h == start
+				{
+					return None;
+				}
+			}
+			Some(self.origin(self.prev(h)))
+		}))
+	}
+
+	// Every hole's outline, as vertices in the order of the boundary half-edges
+	pub fn boundary_loops(&self) -> Vec<Vec<u32>>
+	{
+		let mut visited = vec![false; self.half_edges.len()];
+		let mut loops = Vec::new();
+
+		for start in 0..self.half_edges.len() as u32
+		{
+			if visited[start as usize] || !self.is_boundary_edge(start)
+			{
+				continue;
+			}
+
+			let mut outline = Vec::new();
+			let mut h = start;
+			loop
+			{
+				visited[h as usize] = true;
+				outline.push(self.origin(h));
+
+				// the next boundary half-edge starts where this one ends, found by turning around that vertex
+				let mut next = self.next(h);
+				let mut steps = 0;
+				while self.twin(next) != NONE && steps < self.half_edges.len()
+				{
+					next = self.next(self.twin(next));
+					steps += 1;
+				}
+
+				if next == start || visited[next as usize] || self.twin(next) != NONE
+				{
+					break;
+				}
+				h = next;
+			}
+			loops.push(outline);
+		}
+
+		loops
+	}
+
+	// Every edge has one or two faces, faces agree on their winding, and the faces
+	// around each vertex form a single fan (no two cones touching at their tips)
+	pub fn is_manifold(&self) -> bool
+	{
+		if self.non_manifold_edges > 0
+		{
+			return false;
+		}
+
+		let mut around = vec![0usize; self.vertices.len()];
+		for h in &self.half_edges
+		{
+			around[h.vertex as usize] += 1;
+		}
+
+		(0..self.vertices.len() as u32).all(|v| self.outgoing_half_edges(v).count() == around[v as usize])
+	}
+
+	pub fn is_closed(&self) -> bool
+	{
+		self.half_edges.iter().all(|h| h.twin != NONE)
+	}
+
+	// Replaces the edge of `h` with the other diagonal of the two triangles next to it.
+	// Returns false (and does nothing) for boundary edges, or if the new edge already exists
+	pub fn flip_edge(&mut self, h: u32) -> bool
+	{
+		let t = self.twin(h);
+		if t == NONE
+		{
+			return false;
+		}
+
+		let (a, b) = (self.origin(h), self.target(h));
+		let c = self.origin(self.prev(h));
+		let d = self.origin(self.prev(t));
+		if c == d || self.one_ring(c).any(|v| v == d)
+		{
+			return false;
+		}
+
+		let outer_bc = self.twin(self.next(h));
+		let outer_ca = self.twin(self.prev(h));
+		let outer_ad = self.twin(self.next(t));
+		let outer_db = self.twin(self.prev(t));
+
+		// (a, b, c) + (b, a, d) -> (a, d, c) + (d, b, c)
+		let f1 = self.face(h) as u32;
+		let f2 = self.face(t) as u32;
+		self.set_face(f1, [a, d, c]);
+		self.set_face(f2, [d, b, c]);
+
+		self.link(f1 * 3, outer_ad);
+		self.link(f1 * 3 + 1, f2 * 3 + 2);
+		self.link(f1 * 3 + 2, outer_ca);
+		self.link(f2 * 3, outer_db);
+		self.link(f2 * 3 + 1, outer_bc);
+
+		self.fix_outgoing(a, f1 * 3);
+		self.fix_outgoing(b, f2 * 3 + 1);
+		self.fix_outgoing(c, f1 * 3 + 2);
+		self.fix_outgoing(d, f2 * 3);
+
+		true
+	}
+
+	// Adds a vertex in the middle of the edge of `h` (attributes are averaged), splitting the faces on both sides.
+	// Returns the new vertex
+	pub fn split_edge(&mut self, h: u32) -> u32
+	{
+		let t = self.twin(h);
+		let (a, b) = (self.origin(h), self.target(h));
+		let c = self.origin(self.prev(h));
+
+		let m = self.vertices.len() as u32;
+		self.vertices.push(midpoint(&self.vertices[a as usize], &self.vertices[b as usize]));
+		self.outgoing.push(NONE);
+
+		let outer_bc = self.twin(self.next(h));
+		let outer_ca = self.twin(self.prev(h));
+
+		// (a, b, c) -> (a, m, c) + (m, b, c)
+		let f1 = self.face(h) as u32;
+		let group = self.face_groups[f1 as usize];
+		self.set_face(f1, [a, m, c]);
+		let f3 = self.add_face([m, b, c], group);
+
+		self.link(f1 * 3 + 1, f3 * 3 + 2);
+		self.link(f1 * 3 + 2, outer_ca);
+		self.link(f3 * 3 + 1, outer_bc);
+
+		if t != NONE
+		{
+			let d = self.origin(self.prev(t));
+			let outer_ad = self.twin(self.next(t));
+			let outer_db = self.twin(self.prev(t));
+
+			// (b, a, d) -> (b, m, d) + (m, a, d)
+			let f2 = self.face(t) as u32;
+			let group = self.face_groups[f2 as usize];
+			self.set_face(f2, [b, m, d]);
+			let f4 = self.add_face([m, a, d], group);
+
+			self.link(f2 * 3 + 1, f4 * 3 + 2);
+			self.link(f2 * 3 + 2, outer_db);
+			self.link(f4 * 3 + 1, outer_ad);
+			self.link(f1 * 3, f4 * 3);
+			self.link(f3 * 3, f2 * 3);
+
+			self.fix_outgoing(d, f2 * 3 + 2);
+		}
+
+		self.fix_outgoing(a, f1 * 3);
+		self.fix_outgoing(b, f3 * 3 + 1);
+		self.fix_outgoing(c, f1 * 3 + 2);
+		self.fix_outgoing(m, f3 * 3);
+
+		m
+	}
+
+	// rewrites a face's corners, its twins have to be linked again afterwards
+	fn set_face(&mut self, face: u32, corners: [u32; 3])
+	{
+		for (i, vertex) in corners.into_iter().enumerate()
+		{
+			self.half_edges[face as usize * 3 + i] = HalfEdge { vertex, twin: NONE };
+		}
+	}
+
+	fn add_face(&mut self, corners: [u32; 3], group: u32) -> u32
+	{
+		let face = self.face_count() as u32;
+		self.half_edges.extend(corners.iter().map(|&vertex| HalfEdge { vertex, twin: NONE }));
+		self.face_groups.push(group);
+		face
+	}
+
+	fn link(&mut self, a: u32, b: u32)
+	{
+		self.half_edges[a as usize].twin = b;
+		if b != NONE
+		{
+			self.half_edges[b as usize].twin = a;
+		}
+	}
+
+	// points a vertex at `h`, or at the boundary half-edge of its fan if it has one
+	fn fix_outgoing(&mut self, vertex: u32, h: u32)
+	{
+		let mut current = h;
+		while self.twin(current) != NONE
+		{
+			current = self.next(self.twin(current));
+			if current == h
+			{
+				break;
+			}
+		}
+		self.outgoing[vertex as usize] = current;
+	}
+}
+
+pub struct Outgoing<'a>
+{
+	mesh: &'a HalfEdgeMesh,
+	start: u32,
+	current: u32
+}
+
+impl<'a> Iterator for Outgoing<'a>
+{
+	type Item = u32;
+
+	fn next(&mut self) -> Option<u32>
+	{
+		if self.current == NONE
+		{
+			return None;
+		}
+
+		let h = self.current;
+		// the edge coming into the vertex in this face, its twin leaves the vertex in the next face
+		let next = self.mesh.twin(self.mesh.prev(h));
+		self.current = if next == self.start { NONE } else { next };
+
+		Some(h)
+	}
+}
+
+fn midpoint(a: &Vertex, b: &Vertex) -> Vertex
+{
+	let normal = a.normal() + b.normal();
+	let length = normal.length();
+
+	let mut vertex = Vertex::new((a.position() + b.position()) / 2.0, (a.color() + b.color()) / 2.0, (a.texcoord() + b.texcoord()) / 2.0);
+	vertex.normal = if length > 0.0 { normal / length } else { normal };
+	vertex
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::model::test_meshes::{self, TETRAHEDRON};
+
+	fn mesh(positions: &[(f32, f32, f32)], indices: &[u32]) -> HalfEdgeMesh
+	{
+		HalfEdgeMesh::from_mesh_data(&test_meshes::mesh(positions, indices))
+	}
+
+	fn tetrahedron() -> HalfEdgeMesh
+	{
+		HalfEdgeMesh::from_mesh_data(&test_meshes::tetrahedron(&TETRAHEDRON))
+	}
+
+	// 0 1 2
+	// 3 4 5
+	// 6 7 8, counter-clockwise seen from +z
+	fn grid() -> HalfEdgeMesh
+	{
+		let mut positions = Vec::new();
+		for y in 0..3
+		{
+			for x in 0..3
+			{
+				positions.push((x as f32, -(y as f32), 0.0));
+			}
+		}
+		mesh(&positions, &[0, 3, 4, 0, 4, 1, 1, 4, 5, 1, 5, 2, 3, 6, 7, 3, 7, 4, 4, 7, 8, 4, 8, 5])
+	}
+
+	fn assert_consistent(mesh: &HalfEdgeMesh)
+	{
+		for h in 0..mesh.half_edge_count() as u32
+		{
+			let t = mesh.twin(h);
+			if t != NONE
+			{
+				assert_eq!(mesh.twin(t), h);
+				assert_eq!(mesh.origin(t), mesh.target(h));
+				assert_eq!(mesh.target(t), mesh.origin(h));
+			}
+		}
+		for v in 0..mesh.vertices.len() as u32
+		{
+			let h = mesh.outgoing(v);
+			if h != NONE
+			{
+				assert_eq!(mesh.origin(h), v);
+			}
+		}
+	}
+
+	fn sorted(mut v: Vec<u32>) -> Vec<u32>
+	{
+		v.sort_unstable();
+		v
+	}
+
+	#[test]
+	fn closed_mesh()
+	{
+		let mesh = tetrahedron();
+		assert_consistent(&mesh);
+		assert!(mesh.is_manifold());
+		assert!(mesh.is_closed());
+		assert!(mesh.boundary_loops().is_empty());
+		assert_eq!(sorted(mesh.one_ring(0).collect()), vec![1, 2, 3]);
+	}
+
+	#[test]
+	fn one_ring_and_boundary()
+	{
+		let mesh = grid();
+		assert_consistent(&mesh);
+		assert!(mesh.is_manifold());
+		assert!(!mesh.is_closed());
+
+		// interior vertex, counter-clockwise
+		let ring: Vec<u32> = mesh.one_ring(4).collect();
+		assert_eq!(sorted(ring.clone()), vec![0, 1, 3, 5, 7, 8]);
+		let start = ring.iter().position(|&v| v == 5).unwrap();
+		let rotated: Vec<u32> = ring[start..].iter().chain(&ring[..start]).copied().collect();
+		assert_eq!(rotated, vec![5, 1, 0, 3, 7, 8]);
+
+		// boundary vertices get both ends of their fan
+		assert_eq!(mesh.one_ring(1).collect::<Vec<_>>(), vec![0, 4, 5, 2]);
+		assert_eq!(mesh.one_ring(2).collect::<Vec<_>>(), vec![1, 5]);
+		assert!(mesh.is_boundary_vertex(2) && !mesh.is_boundary_vertex(4));
+
+		let loops = mesh.boundary_loops();
+		assert_eq!(loops.len(), 1);
+		assert_eq!(sorted(loops[0].clone()), vec![0, 1, 2, 3, 5, 6, 7, 8]);
+	}
+
+	#[test]
+	fn round_trip()
+	{
+		let mut data = grid().to_mesh_data();
+		data.submeshes = vec![
+			SubMesh { name: "top".into(), material: Some("a".into()), first: 0, count: 12 },
+			SubMesh { name: "bottom".into(), material: None, first: 12, count: 12 }
+		];
+
+		let back = HalfEdgeMesh::from_mesh_data(&data).to_mesh_data();
+		assert_eq!(back.indices, data.indices);
+		assert_eq!(back.submeshes, data.submeshes);
+	}
+
+	#[test]
+	fn flip()
+	{
+		let mut mesh = grid();
+		// edge 0-4, diagonal of the top left quad
+		let h = (0..mesh.half_edge_count() as u32).find(|&h| mesh.origin(h) == 0 && mesh.target(h) == 4).unwrap();
+		assert!(mesh.flip_edge(h));
+		assert_consistent(&mesh);
+		assert!(mesh.is_manifold());
+
+		assert!(!mesh.one_ring(0).any(|v| v == 4));
+		assert!(mesh.one_ring(1).any(|v| v == 3));
+		assert_eq!(sorted(mesh.one_ring(4).collect()), vec![1, 3, 5, 7, 8]);
+
+		// boundary edges can't flip
+		let boundary = (0..mesh.half_edge_count() as u32).find(|&h| mesh.is_boundary_edge(h)).unwrap();
+		assert!(!mesh.flip_edge(boundary));
+	}
+
+	#[test]
+	fn split()
+	{
+		let mut mesh = grid();
+		let h = (0..mesh.half_edge_count() as u32).find(|&h| mesh.origin(h) == 4 && mesh.target(h) == 5).unwrap();
+		let m = mesh.split_edge(h);
+
+		assert_consistent(&mesh);
+		assert!(mesh.is_manifold());
+		assert_eq!(mesh.face_count(), 10);
+		assert_eq!(mesh.vertices[m as usize].position(), (1.5, -1.0, 0.0).into());
+		assert_eq!(sorted(mesh.one_ring(m).collect()), vec![1, 4, 5, 8]);
+		assert!(!mesh.one_ring(4).any(|v| v == 5));
+
+		// on the boundary only one face is split, and the new vertex is on the boundary too
+		let h = (0..mesh.half_edge_count() as u32).find(|&h| mesh.origin(h) == 5 && mesh.target(h) == 2).unwrap();
+		let m = mesh.split_edge(h);
+		assert_consistent(&mesh);
+		assert_eq!(mesh.face_count(), 11);
+		assert!(mesh.is_boundary_vertex(m));
+		assert_eq!(mesh.boundary_loops()[0].len(), 9);
+	}
+
+	#[test]
+	fn non_manifold()
+	{
+		// three triangles on the 0-1 edge
+		let fin = mesh(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, -1.0, 0.0), (0.0, 0.0, 1.0)], &[0, 1, 2, 1, 0, 3, 0, 1, 4]);
+		assert!(!fin.is_manifold());
+
+		// two triangles touching at vertex 0 only
+		let bowtie = mesh(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (-1.0, 0.0, 0.0), (-1.0, -1.0, 0.0)], &[0, 1, 2, 0, 3, 4]);
+		assert_consistent(&bowtie);
+		assert!(!bowtie.is_manifold());
+	}
+}
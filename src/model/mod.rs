@@ -0,0 +1,1164 @@
+use math;
+use gl;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::vec::Vec;
+use rand::Rng;
+use crate::render_gl::{self, buffer, texture, VertexLayout};
+use sdl2::keyboard::Keycode;
+
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod cache;
+pub mod gltf;
+pub mod validate;
+pub mod optimize;
+pub mod simplify;
+pub mod halfedge;
+pub mod subdivide;
+pub mod bezier;
+pub mod primitives;
+pub mod color;
+pub mod spin;
+pub mod coords;
+#[cfg(test)]
+pub mod test_meshes;
+
+#[derive(Debug)]
+pub enum Error
+{
+	Io(io::Error),
+	Obj(obj::Error),
+	Ply(ply::Error),
+	Stl(stl::Error),
+	Gltf(gltf::Error),
+	Bezier(bezier::Error),
+	UnknownFormat(String),
+	// the mesh would make gl read past the end of its buffers
+	Invalid(validate::Report),
+}
+
+impl From<io::Error> for Error
+{
+	fn from(other: io::Error) -> Self
+	{
+		Error::Io(other)
+	}
+}
+
+impl From<obj::Error> for Error
+{
+	fn from(other: obj::Error) -> Self
+	{
+		Error::Obj(other)
+	}
+}
+
+impl From<ply::Error> for Error
+{
+	fn from(other: ply::Error) -> Self
+	{
+		Error::Ply(other)
+	}
+}
+
+impl From<stl::Error> for Error
+{
+	fn from(other: stl::Error) -> Self
+	{
+		Error::Stl(other)
+	}
+}
+
+impl From<gltf::Error> for Error
+{
+	fn from(other: gltf::Error) -> Self
+	{
+		Error::Gltf(other)
+	}
+}
+
+impl From<bezier::Error> for Error
+{
+	fn from(other: bezier::Error) -> Self
+	{
+		Error::Bezier(other)
+	}
+}
+
+// File formats Mesh::from_file can read, picked by file extension
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format
+{
+	Obj,
+	Ply,
+	Stl,
+	// .gltf and .glb
+	Gltf,
+	// .bpt Bézier patches
+	Bezier,
+}
+
+impl Format
+{
+	pub fn from_path(path: &Path) -> Result<Self, Error>
+	{
+		let extension = path.extension()
+			.and_then(|e| e.to_str())
+			.map(|e| e.to_ascii_lowercase())
+			.unwrap_or_default();
+
+		match extension.as_str()
+		{
+			"obj" => Ok(Format::Obj),
+			"ply" => Ok(Format::Ply),
+			"stl" => Ok(Format::Stl),
+			"gltf" | "glb" => Ok(Format::Gltf),
+			"bpt" => Ok(Format::Bezier),
+			_ => Err(Error::UnknownFormat(extension))
+		}
+	}
+
+	pub fn parse(&self, source: &[u8]) -> Result<MeshData, Error>
+	{
+		match self
+		{
+			Format::Obj => {
+				let obj = obj::from_bytes(source)?;
+				for warning in &obj.warnings
+				{
+					match warning.repeats
+					{
+						0 => println!("line {}: {}", warning.line, warning.message),
+						repeats => println!("line {}: {} (and {} more times)", warning.line, warning.message, repeats)
+					}
+				}
+				Ok(MeshData::from_obj(obj))
+			},
+			Format::Ply => Ok(ply::from_bytes(source)?),
+			Format::Stl => Ok(stl::from_bytes(source)?),
+			// only embedded buffers and images, use gltf::load for files that reference others
+			Format::Gltf => Ok(gltf::from_bytes(source, None)?.mesh),
+			Format::Bezier => {
+				let mut data = MeshData::new(Vec::new(), Vec::new());
+				data.patches = bezier::from_bytes(source)?;
+				Ok(data)
+			}
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, VertexLayout)]
+#[repr(C, packed)]
+pub struct Vertex
+{
+	position: math::vector::Vector3,
+	normal: math::vector::Vector3,
+	texcoord: math::vector::Vector2,
+	color: math::vector::Vector3,
+	// direction of increasing u, w is the sign of the bitangent (cross(normal, tangent) * w points along v)
+	tangent: math::vector::Vector4
+}
+
+impl Vertex
+{
+	pub fn new(position: math::vector::Vector3, color: math::vector::Vector3, texcoord: math::vector::Vector2) -> Self
+	{
+		Self {
+			position,
+			normal: (0.0, 0.0, 0.0).into(),
+			texcoord,
+			color,
+			tangent: math::vector::Vector4::new(0.0, 0.0, 0.0, 0.0)
+		}
+	}
+
+	pub fn position(&self) -> math::vector::Vector3
+	{
+		self.position
+	}
+
+	pub fn normal(&self) -> math::vector::Vector3
+	{
+		self.normal
+	}
+
+	pub fn texcoord(&self) -> math::vector::Vector2
+	{
+		self.texcoord
+	}
+
+	pub fn color(&self) -> math::vector::Vector3
+	{
+		self.color
+	}
+
+	pub fn tangent(&self) -> math::vector::Vector4
+	{
+		self.tangent
+	}
+}
+
+// Axis-aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds
+{
+	min: math::vector::Vector3,
+	max: math::vector::Vector3
+}
+
+impl Bounds
+{
+	pub fn new(min: math::vector::Vector3, max: math::vector::Vector3) -> Self
+	{
+		Bounds { min, max }
+	}
+
+	pub fn from_vertices(vertices: &[Vertex]) -> Self
+	{
+		if vertices.is_empty()
+		{
+			return Bounds { min: (0.0, 0.0, 0.0).into(), max: (0.0, 0.0, 0.0).into() };
+		}
+
+		let mut min = [f32::MAX; 3];
+		let mut max = [f32::MIN; 3];
+
+		for vertex in vertices
+		{
+			let p = vertex.position;
+			for (i, value) in [p.x(), p.y(), p.z()].into_iter().enumerate()
+			{
+				min[i] = min[i].min(value);
+				max[i] = max[i].max(value);
+			}
+		}
+
+		Bounds {
+			min: (min[0], min[1], min[2]).into(),
+			max: (max[0], max[1], max[2]).into()
+		}
+	}
+
+	pub fn min(&self) -> math::vector::Vector3
+	{
+		self.min
+	}
+
+	pub fn max(&self) -> math::vector::Vector3
+	{
+		self.max
+	}
+
+	pub fn center(&self) -> math::vector::Vector3
+	{
+		(self.min + self.max) / 2.0
+	}
+
+	pub fn size(&self) -> math::vector::Vector3
+	{
+		self.max - self.min
+	}
+
+	// length of the longest side of the box
+	pub fn largest_extent(&self) -> f32
+	{
+		let size = self.size();
+		size.x().max(size.y()).max(size.z())
+	}
+}
+
+// Transformations applied to the model when it is loaded
+#[derive(Copy, Clone, Debug)]
+pub struct LoadOptions
+{
+	// move the bounding box center to the origin, so the model rotates around its visual center
+	pub recenter: bool,
+	// uniformly scale the model so its largest side is 1.0
+	pub normalize: bool,
+	// subdivision level to start at, see Mesh::set_subdivision_level
+	pub subdivision: usize,
+	// up axis, handedness and unit of the file, applied before recentering and scaling
+	pub conversion: coords::Conversion
+}
+
+impl LoadOptions
+{
+	// Matrix that applies the options to vertices in file coordinates.
+	// The vertices themselves are never modified, so cached and exported data stays in file space
+	pub fn pivot(&self, bounds: &Bounds) -> math::matrix::Matrix4
+	{
+		let (offset, factor) = self.offset_and_factor(&self.conversion.bounds(bounds));
+		let identity = math::matrix::Matrix4::new_identity();
+
+		math::translate(&math::scale(&identity, &(factor, factor, factor).into()), &-offset) * self.conversion.matrix()
+	}
+
+	// bounds of the model as it is drawn, from the bounds in file coordinates
+	pub fn bounds(&self, bounds: &Bounds) -> Bounds
+	{
+		let converted = self.conversion.bounds(bounds);
+		let (offset, factor) = self.offset_and_factor(&converted);
+
+		Bounds::new((converted.min - offset) * factor, (converted.max - offset) * factor)
+	}
+
+	// bounds are after the conversion
+	fn offset_and_factor(&self, bounds: &Bounds) -> (math::vector::Vector3, f32)
+	{
+		let offset = if self.recenter { bounds.center() } else { (0.0, 0.0, 0.0).into() };
+		let extent = bounds.largest_extent();
+		let factor = if self.normalize && extent > 0.0 { 1.0 / extent } else { 1.0 };
+
+		(offset, factor)
+	}
+}
+
+impl Default for LoadOptions
+{
+	fn default() -> Self
+	{
+		LoadOptions {
+			recenter: true,
+			normalize: false,
+			subdivision: 0,
+			conversion: coords::Conversion::default()
+		}
+	}
+}
+
+// Range of indices that belongs to one group/object and material
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubMesh
+{
+	pub name: String,
+	pub material: Option<String>,
+	pub first: u32,
+	pub count: u32
+}
+
+// Processed mesh on the cpu side, what gets uploaded to the gpu and written to the mesh cache
+#[derive(Clone, Debug)]
+pub struct MeshData
+{
+	pub vertices: Vec<Vertex>,
+	pub indices: Vec<u32>,
+	// drawn on their own after the triangles, two indices per line segment and one per point
+	pub lines: Vec<u32>,
+	pub points: Vec<u32>,
+	pub submeshes: Vec<SubMesh>,
+	// mtllib files the material names refer to
+	pub material_libs: Vec<String>,
+	pub bounds: Bounds,
+	// simplified versions, each with fewer triangles than the one before
+	pub lods: Vec<simplify::Lod>,
+	// curved surfaces, turned into triangles by bezier::tessellate when the mesh is drawn
+	pub patches: Vec<bezier::Patch>,
+	// the vertex colors came from the file, instead of being made up
+	pub vertex_colors: bool
+}
+
+impl MeshData
+{
+	pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self
+	{
+		let bounds = Bounds::from_vertices(&vertices);
+		let submeshes = vec![SubMesh {
+			name: String::new(),
+			material: None,
+			first: 0,
+			count: indices.len() as u32
+		}];
+
+		MeshData {
+			vertices,
+			indices,
+			lines: Vec::new(),
+			points: Vec::new(),
+			submeshes,
+			material_libs: Vec::new(),
+			bounds,
+			lods: Vec::new(),
+			patches: Vec::new(),
+			vertex_colors: false
+		}
+	}
+
+	pub fn from_obj(data: obj::ObjData) -> Self
+	{
+		let indices: Vec<u32> = data.indices.iter().map(|i| i.position).collect();
+		let patches = data.surfaces.iter().flat_map(|surface| surface.patches(&data.positions)).collect();
+		// there is one vertex per position, so these index the vertices as they are
+		let (lines, points) = (data.lines, data.points);
+
+		let mut vertices = Vec::<Vertex>::with_capacity(data.positions.len());
+		let mut rng = color::vertex_rng();
+		let vertex_colors = data.colors.iter().any(|c| c.is_some());
+
+		for (i, color) in data.positions.into_iter().zip(data.colors)
+		{
+			// vertices without a color in a file that has some are white, rather than random
+			let color = match color
+			{
+				Some(color) => color,
+				None if vertex_colors => (1.0, 1.0, 1.0).into(),
+				None => random_color(&mut rng)
+			};
+			vertices.push(Vertex::new(i, color, (i.x(), i.y()).into()));
+		}
+
+		let mut mesh = MeshData::new(vertices, indices);
+		mesh.vertex_colors = vertex_colors;
+		if !data.groups.is_empty()
+		{
+			mesh.submeshes = data.groups
+				.into_iter()
+				.map(|group| SubMesh {
+					name: group.name,
+					material: group.material,
+					first: group.first as u32,
+					count: group.count as u32
+				})
+				.collect();
+		}
+		mesh.material_libs = data.material_libs;
+		mesh.patches = patches;
+		mesh.lines = lines;
+		mesh.points = points;
+		mesh.generate_normals();
+
+		mesh
+	}
+
+	// Smooth normals: every vertex gets the area weighted average of the faces around it
+	pub fn generate_normals(&mut self)
+	{
+		let mut normals = vec![math::vector::Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+
+		for triangle in self.indices.chunks_exact(3)
+		{
+			let (a, b, c) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+			if a >= self.vertices.len() || b >= self.vertices.len() || c >= self.vertices.len()
+			{
+				continue;
+			}
+
+			let p0 = self.vertices[a].position;
+			// not normalized, so bigger faces weigh more
+			let normal = cross(self.vertices[b].position - p0, self.vertices[c].position - p0);
+			for i in [a, b, c]
+			{
+				normals[i] = normals[i] + normal;
+			}
+		}
+
+		for (vertex, normal) in self.vertices.iter_mut().zip(normals)
+		{
+			let length = normal.length();
+			vertex.normal = if length > 0.0 { normal / length } else { normal };
+		}
+	}
+
+	// average of all vertex positions
+	pub fn centroid(&self) -> math::vector::Vector3
+	{
+		if self.vertices.is_empty()
+		{
+			return (0.0, 0.0, 0.0).into();
+		}
+
+		let mut sum = math::vector::Vector3::new(0.0, 0.0, 0.0);
+		for vertex in &self.vertices
+		{
+			sum = sum + vertex.position;
+		}
+
+		sum / self.vertices.len() as f32
+	}
+}
+
+// screen height fraction a model has to cover to be drawn at full detail
+const LOD_FULL_DETAIL_COVERAGE: f32 = 0.5;
+// every level has 4 times the triangles of the one before
+const MAX_SUBDIVISION_LEVEL: usize = 4;
+// in pixels
+const DEFAULT_LINE_WIDTH: f32 = 1.0;
+const DEFAULT_POINT_SIZE: f32 = 4.0;
+
+pub struct Mesh
+{
+	data: MeshData,
+	centroid: math::vector::Vector3,
+	// recenters/scales the model according to the load options, applied before model_mat
+	pivot: math::matrix::Matrix4,
+	// bounds after the pivot was applied
+	bounds: Bounds,
+	// kept so the pivot can be recomputed, see set_conversion
+	options: LoadOptions,
+	// default texture, for submeshes without one of their own
+	texture: texture::Texture,
+	// textures that came with the model file
+	textures: Vec<texture::Texture>,
+	// index into textures for each submesh
+	submesh_textures: Vec<Option<usize>>,
+	vao: buffer::VertexArray,
+	vbo: buffer::ArrayBuffer,
+	ebo: buffer::ElementArrayBuffer,
+	program: render_gl::Program,
+	model_mat: math::matrix::Matrix4,
+	// mesh as loaded, while a subdivided or tessellated version is drawn
+	cage: Option<MeshData>,
+	subdivision: usize,
+	// quads per side of each Bézier patch
+	tessellation: usize,
+	color_mode: color::ColorMode,
+	// interpolate vertex colors across faces, for colors from the file rather than random ones
+	smooth_colors: bool,
+	palette: Vec<math::vector::Vector3>,
+	// fades between the colors, the texture and shading
+	transition: color::Transition,
+	// auto-rotation about the bounding box center, applied between the pivot and model_mat
+	spin: spin::Spin,
+	// for the obj `l` and `p` elements, in pixels
+	line_width: f32,
+	point_size: f32
+}
+
+impl Mesh
+{
+	// basic constructor from raw data, see primitives for ready-made shapes
+	pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, program: render_gl::Program, tex_path: &str) -> Self
+	{
+		Mesh::from_data(MeshData::new(vertices, indices), program, tex_path)
+	}
+
+	pub fn from_data(data: MeshData, program: render_gl::Program, tex_path: &str) -> Self
+	{
+		let texture = texture::Texture::new();
+		texture.load(tex_path);
+		texture.set_filtering(gl::REPEAT);
+		texture.set_wrapping(gl::REPEAT);
+
+		let options = LoadOptions { recenter: false, normalize: false, ..LoadOptions::default() };
+		let mesh = Mesh::assemble(data, program, texture, &options);
+		mesh.setup_mesh(&mesh.data.vertices, &mesh.data.indices);
+
+		mesh
+	}
+
+	pub fn from_file<T>(filename: T, program: render_gl::Program, tex_path: &str) -> Result<Self, Error>
+	where T: AsRef<Path>
+	{
+		Mesh::from_file_with_options(filename, program, tex_path, &LoadOptions::default())
+	}
+
+	pub fn from_file_with_options<T>(filename: T, program: render_gl::Program, tex_path: &str, options: &LoadOptions) -> Result<Self, Error>
+	where T: AsRef<Path>
+	{
+		let mut mesh = Mesh::load(filename, program, tex_path, options)?;
+		mesh.set_subdivision_level(options.subdivision);
+
+		Ok(mesh)
+	}
+
+	fn load<T>(filename: T, program: render_gl::Program, tex_path: &str, options: &LoadOptions) -> Result<Self, Error>
+	where T: AsRef<Path>
+	{
+		let texture = texture::Texture::new();
+		texture.load(tex_path);
+		texture.set_filtering(gl::REPEAT);
+		texture.set_wrapping(gl::REPEAT);
+
+		let format = Format::from_path(filename.as_ref())?;
+		if format == Format::Gltf
+		{
+			// buffers and images can live in other files, which the cache key wouldn't cover
+			let mut gltf = gltf::load(&filename)?;
+			check(filename.as_ref(), &gltf.mesh)?;
+			prepare(filename.as_ref(), &mut gltf.mesh);
+			return Ok(Mesh::from_gltf(gltf, program, texture, options));
+		}
+
+		let source = fs::read(&filename)?;
+		let key = cache::source_hash(&source);
+
+		let cached = match cache::open(key)
+		{
+			Ok(cached) => cached,
+			Err(e) => {
+				println!("ignoring mesh cache: {:?}", e);
+				None
+			}
+		};
+
+		let data = match cached
+		{
+			// the mesh owns its data, see the cache module for why it isn't uploaded from the mapping
+			Some(cached) => cached.into_mesh_data(),
+			None => {
+				let mut data = format.parse(&source)?;
+				// only checked before it is cached, so a cached mesh is known to be valid
+				check(filename.as_ref(), &data)?;
+				prepare(filename.as_ref(), &mut data);
+				if let Err(e) = cache::write(key, &data)
+				{
+					println!("failed to write mesh cache: {:?}", e);
+				}
+				data
+			}
+		};
+
+		if !data.patches.is_empty()
+		{
+			return Ok(Mesh::from_patches(data, program, texture, options));
+		}
+
+		let mesh = Mesh::assemble(data, program, texture, options);
+		mesh.setup_mesh(&mesh.data.vertices, &mesh.data.indices);
+
+		Ok(mesh)
+	}
+
+	// the patches are kept, so they can be tessellated again at another resolution
+	fn from_patches(cage: MeshData, program: render_gl::Program, texture: texture::Texture, options: &LoadOptions) -> Self
+	{
+		let data = bezier::tessellate(&cage, bezier::DEFAULT_RESOLUTION);
+		let mut mesh = Mesh::assemble(data, program, texture, options);
+		mesh.cage = Some(cage);
+		mesh.setup_mesh(&mesh.data.vertices, &mesh.data.indices);
+
+		mesh
+	}
+
+	fn from_gltf(gltf: gltf::GltfData, program: render_gl::Program, texture: texture::Texture, options: &LoadOptions) -> Self
+	{
+		let submesh_textures = gltf.submesh_images();
+		let textures = gltf.images
+			.iter()
+			.map(|image| {
+				let texture = texture::Texture::new();
+				texture.load_image(image);
+				texture.set_filtering(gl::LINEAR);
+				texture.set_wrapping(gl::REPEAT);
+				texture
+			})
+			.collect();
+
+		let mut mesh = Mesh::assemble(gltf.mesh, program, texture, options);
+		mesh.textures = textures;
+		mesh.submesh_textures = submesh_textures;
+		mesh.setup_mesh(&mesh.data.vertices, &mesh.data.indices);
+
+		mesh
+	}
+
+	// creates the gl objects, data still has to be uploaded with setup_mesh
+	fn assemble(data: MeshData, program: render_gl::Program, texture: texture::Texture, options: &LoadOptions) -> Self
+	{
+		check_layout(&program);
+
+		let bounds = options.bounds(&data.bounds);
+		let smooth_colors = data.vertex_colors;
+		// scans and point clouds come with their own colors, show those instead of the default texture
+		let (color_mode, appearance) = if data.vertex_colors
+		{
+			(color::ColorMode::Vertex, color::Appearance::Color)
+		}
+		else
+		{
+			(color::ColorMode::Palette, color::Appearance::Texture)
+		};
+
+		Mesh {
+			centroid: data.centroid(),
+			pivot: options.pivot(&data.bounds),
+			bounds,
+			options: *options,
+			data,
+			texture,
+			textures: Vec::new(),
+			submesh_textures: Vec::new(),
+			vao: buffer::VertexArray::new(),
+			vbo: buffer::ArrayBuffer::new(),
+			ebo: buffer::ElementArrayBuffer::new(),
+			program,
+			model_mat: math::matrix::Matrix4::new_identity(),
+			cage: None,
+			subdivision: 0,
+			tessellation: bezier::DEFAULT_RESOLUTION,
+			color_mode,
+			smooth_colors,
+			palette: color::palette(color::DEFAULT_SEED),
+			transition: color::Transition::new(appearance, color::DEFAULT_TRANSITION),
+			spin: spin::Spin::default(),
+			line_width: DEFAULT_LINE_WIDTH,
+			point_size: DEFAULT_POINT_SIZE
+		}
+	}
+
+	pub fn data(&self) -> &MeshData
+	{
+		&self.data
+	}
+
+	// bounds of the model as it is drawn (after the load options were applied)
+	pub fn bounds(&self) -> &Bounds
+	{
+		&self.bounds
+	}
+
+	pub fn conversion(&self) -> coords::Conversion
+	{
+		self.options.conversion
+	}
+
+	// Changes the up axis, handedness or unit the file is read in, and recenters/rescales it like when it was loaded
+	pub fn set_conversion(&mut self, conversion: coords::Conversion)
+	{
+		self.options.conversion = conversion;
+		self.pivot = self.options.pivot(&self.data.bounds);
+		self.bounds = self.options.bounds(&self.data.bounds);
+	}
+
+	// bounds in file coordinates
+	pub fn original_bounds(&self) -> &Bounds
+	{
+		&self.data.bounds
+	}
+
+	// average of all vertex positions, in file coordinates
+	pub fn centroid(&self) -> math::vector::Vector3
+	{
+		self.centroid
+	}
+
+	// Writes the mesh as it is drawn (converted/recentered/scaled), without the interactive rotation
+	pub fn export_stl<T>(&self, filename: T) -> Result<(), Error>
+	where T: AsRef<Path>
+	{
+		Ok(stl::write_binary(filename, &self.data, &self.pivot)?)
+	}
+
+	// Same as export_stl, but keeps texture coordinates, normals, groups and materials (written to a .mtl next to it)
+	pub fn export_obj<T>(&self, filename: T, options: &obj::WriteOptions) -> Result<(), Error>
+	where T: AsRef<Path>
+	{
+		Ok(obj::write(filename, &self.data, &self.pivot, options)?)
+	}
+
+	fn setup_mesh(&self, vertices: &[Vertex], indices: &[u32])
+	{
+		self.vao.bind();
+		self.vbo.bind();
+
+		self.vbo.static_draw_data(vertices);
+
+		self.ebo.bind();
+		// the lods go after the full detail indices, in the same buffer, followed by the lines and points
+		let mut parts = vec![indices];
+		parts.extend(self.data.lods.iter().map(|lod| lod.indices.as_slice()));
+		parts.push(&self.data.lines);
+		parts.push(&self.data.points);
+		self.ebo.static_draw_parts(&parts);
+
+		// the attributes come from the VertexLayout derive on Vertex
+		self.vao.set_layout::<Vertex>(&self.vbo);
+		self.vao.unbind();
+	}
+
+	pub fn update_pos(&mut self, event: &sdl2::event::Event)
+	{
+		match event
+		{
+			sdl2::event::Event::KeyDown {
+				keycode: Some(key),
+				repeat,
+				..
+			} => {
+				match key
+				{
+					Keycode::A => {
+						self.model_mat = math::rotate(&self.model_mat, 3_f32.to_radians(), &(0.0, -1.0, 0.0).into())
+					},
+					Keycode::D => {
+						self.model_mat = math::rotate(&self.model_mat, 3_f32.to_radians(), &(0.0, 1.0, 0.0).into())
+					},
+					Keycode::W => {
+						self.model_mat = math::rotate(&self.model_mat, 3_f32.to_radians(), &(-1.0, 0.0, 0.0).into())
+					},
+					Keycode::S => {
+						self.model_mat = math::rotate(&self.model_mat, 3_f32.to_radians(), &(1.0, 0.0, 0.0).into())
+					},
+					Keycode::Equals | Keycode::KpPlus => {
+						self.set_subdivision_level(self.subdivision + 1)
+					},
+					Keycode::Minus | Keycode::KpMinus => {
+						self.set_subdivision_level(self.subdivision.saturating_sub(1))
+					},
+					Keycode::RightBracket => {
+						self.set_tessellation(self.tessellation * 2)
+					},
+					Keycode::LeftBracket => {
+						self.set_tessellation(self.tessellation / 2)
+					},
+					Keycode::C if !repeat => {
+						self.color_mode = self.color_mode.next();
+						println!("color mode {:?}", self.color_mode);
+					},
+					Keycode::T if !repeat => {
+						let appearance = self.transition.target().next();
+						self.transition.set_target(appearance);
+						println!("switching to {:?}", appearance);
+					},
+					Keycode::R if !repeat => {
+						self.spin.paused = !self.spin.paused
+					},
+					Keycode::X if !repeat => {
+						self.spin.set_axis(self.spin.axis.next());
+						println!("rotating about {:?}", self.spin.axis);
+					},
+					_ => {}
+				}
+			},
+			_ => {}
+		}
+	}
+
+	// advances everything that is animated by dt seconds
+	pub fn update(&mut self, dt: f32)
+	{
+		self.transition.update(dt);
+		self.spin.update(dt);
+	}
+
+	// Picks up edits to the shader files, a shader that doesn't build is reported and the old one kept drawing
+	pub fn reload_shader(&mut self)
+	{
+		match self.program.reload_if_changed()
+		{
+			Ok(true) => {
+				println!("reloaded shader");
+				check_layout(&self.program);
+			},
+			Ok(false) => {},
+			Err(e) => println!("{}\nkeeping the previous shader", e)
+		}
+	}
+
+	pub fn spin(&self) -> &spin::Spin
+	{
+		&self.spin
+	}
+
+	// speed, axis and pause of the auto-rotation
+	pub fn spin_mut(&mut self) -> &mut spin::Spin
+	{
+		&mut self.spin
+	}
+
+	pub fn subdivision_level(&self) -> usize
+	{
+		self.subdivision
+	}
+
+	// Replaces the drawn mesh with the loaded one subdivided `level` times (0 goes back to the loaded one)
+	// and uploads it again. Catmull-Clark is used for quad meshes, Loop for everything else
+	pub fn set_subdivision_level(&mut self, level: usize)
+	{
+		let level = level.min(MAX_SUBDIVISION_LEVEL);
+		if level != self.subdivision
+		{
+			self.subdivision = level;
+			self.rebuild();
+		}
+	}
+
+	pub fn color_mode(&self) -> color::ColorMode
+	{
+		self.color_mode
+	}
+
+	pub fn set_color_mode(&mut self, mode: color::ColorMode)
+	{
+		self.color_mode = mode;
+	}
+
+	pub fn appearance(&self) -> color::Appearance
+	{
+		self.transition.target()
+	}
+
+	// fades to appearance over the transition duration
+	pub fn set_appearance(&mut self, appearance: color::Appearance)
+	{
+		self.transition.set_target(appearance);
+	}
+
+	// seconds a switch of appearance takes, 0 switches immediately
+	pub fn set_transition_duration(&mut self, seconds: f32)
+	{
+		self.transition.duration = seconds.max(0.0);
+	}
+
+	pub fn set_line_width(&mut self, width: f32)
+	{
+		self.line_width = width.max(1.0);
+	}
+
+	pub fn set_point_size(&mut self, size: f32)
+	{
+		self.point_size = size.max(1.0);
+	}
+
+	pub fn tessellation(&self) -> usize
+	{
+		self.tessellation
+	}
+
+	// Tessellates the model's Bézier patches again with `resolution` quads per side, if it has any
+	pub fn set_tessellation(&mut self, resolution: usize)
+	{
+		let resolution = resolution.clamp(1, bezier::MAX_RESOLUTION);
+		let has_patches = self.cage.as_ref().is_some_and(|cage| !cage.patches.is_empty());
+		if has_patches && resolution != self.tessellation
+		{
+			self.tessellation = resolution;
+			self.rebuild();
+		}
+	}
+
+	// derives the drawn mesh from the loaded one again and uploads it
+	fn rebuild(&mut self)
+	{
+		let cage = self.cage.take().unwrap_or_else(|| std::mem::replace(&mut self.data, MeshData::new(Vec::new(), Vec::new())));
+		if self.subdivision == 0 && cage.patches.is_empty()
+		{
+			self.data = cage;
+		}
+		else
+		{
+			let tessellated = (!cage.patches.is_empty()).then(|| bezier::tessellate(&cage, self.tessellation));
+			let base = tessellated.as_ref().unwrap_or(&cage);
+			let scheme = subdivide::Scheme::for_mesh(base);
+			self.data = match tessellated
+			{
+				Some(tessellated) if self.subdivision == 0 => tessellated,
+				_ => subdivide::subdivide(base, scheme, self.subdivision)
+			};
+			self.cage = Some(cage);
+			println!("subdivision level {} ({:?}), tessellation {}: {} triangles", self.subdivision, scheme, self.tessellation, self.data.indices.len() / 3);
+		}
+
+		self.setup_mesh(&self.data.vertices, &self.data.indices);
+	}
+
+	// 1 for the full mesh, plus its simplified versions
+	pub fn lod_count(&self) -> usize
+	{
+		self.data.lods.len() + 1
+	}
+
+	// Level of detail to draw with, from how much of the screen height the model covers:
+	// full detail down to LOD_FULL_DETAIL_COVERAGE, then one level coarser every time that halves
+	pub fn lod_for(&self, camera_position: math::vector::Vector3, fov: f32) -> usize
+	{
+		let center: math::vector::Vector4 = self.model_mat * math::vector::Vector4::from((self.bounds.center(), 1.0));
+		let center = math::vector::Vector3::new(center.x(), center.y(), center.z());
+		let radius = self.bounds.size().length() / 2.0;
+		let distance = (camera_position - center).length();
+		if distance <= radius || radius == 0.0
+		{
+			return 0;
+		}
+
+		let coverage = radius / (distance * (fov / 2.0).tan());
+		let level = (LOD_FULL_DETAIL_COVERAGE / coverage).log2().floor().max(0.0) as usize;
+
+		level.min(self.data.lods.len())
+	}
+
+	pub fn render(&self, view: &math::matrix::Matrix4, projection: &math::matrix::Matrix4, lod: usize)
+	{
+		self.program.set_used();
+
+		let model = self.model_mat * self.spin.matrix(self.bounds.center()) * self.pivot;
+		self.program.set_mat4("model", &model);
+		self.program.set_mat4("view", view);
+		self.program.set_mat4("projection", projection);
+
+		self.program.set_i32("colorMode", self.color_mode as i32);
+		self.program.set_bool("smoothColors", self.smooth_colors);
+		self.program.set_vec3_array("palette", &self.palette);
+		self.program.set_i32("firstPrimitive", 0);
+
+		let (texture_mix, shade_mix) = self.transition.weights();
+		self.program.set_f32("textureMix", texture_mix);
+		self.program.set_f32("shadeMix", shade_mix);
+
+		self.vao.bind();
+
+		// a mirrored model has its triangles turned around, keep gl_FrontFacing (and culling) the same as unmirrored
+		let mirrored = self.options.conversion.mirrors();
+		if mirrored
+		{
+			unsafe
+			{
+				gl::FrontFace(gl::CW);
+			}
+		}
+
+		// lods are stored one after another in the index buffer, after the full mesh
+		let lod = lod.min(self.data.lods.len());
+		let offset: usize = self.data.indices.len() + self.data.lods[..lod.saturating_sub(1)].iter().map(|l| l.indices.len()).sum::<usize>();
+		let (first, count, submeshes) = match lod
+		{
+			0 => (0, self.data.indices.len(), &self.data.submeshes),
+			_ => (offset, self.data.lods[lod - 1].indices.len(), &self.data.lods[lod - 1].submeshes)
+		};
+
+		if self.textures.is_empty() && !self.color_mode.per_submesh()
+		{
+			self.texture.activate(gl::TEXTURE0);
+			unsafe
+			{
+				gl::DrawElements(
+					gl::TRIANGLES,
+					count as gl::types::GLint,
+					gl::UNSIGNED_INT,
+					(first * std::mem::size_of::<u32>()) as *const gl::types::GLvoid
+				);
+			}
+		}
+		else
+		{
+			// one draw per submesh, so each can use its own texture and color
+			let colors = color::submesh_colors(submeshes, self.color_mode, &self.palette);
+			for (i, (submesh, color)) in submeshes.iter().zip(colors).enumerate()
+			{
+				match self.submesh_textures.get(i).copied().flatten().and_then(|t| self.textures.get(t))
+				{
+					Some(texture) => texture.activate(gl::TEXTURE0),
+					None => self.texture.activate(gl::TEXTURE0)
+				}
+
+				// gl_PrimitiveID starts at 0 for every draw, this keeps the face colors the same as in a single draw
+				self.program.set_i32("firstPrimitive", (submesh.first / 3) as i32);
+				self.program.set_vec3("submeshColor", color);
+				unsafe
+				{
+					gl::DrawElements(
+						gl::TRIANGLES,
+						submesh.count as gl::types::GLint,
+						gl::UNSIGNED_INT,
+						((first + submesh.first as usize) * std::mem::size_of::<u32>()) as *const gl::types::GLvoid
+					);
+				}
+			}
+		}
+
+		// lines and points don't have lods, they are drawn the same at every level
+		let lines_first = self.data.indices.len() + self.data.lods.iter().map(|l| l.indices.len()).sum::<usize>();
+		let points_first = lines_first + self.data.lines.len();
+		self.program.set_i32("firstPrimitive", 0);
+		unsafe
+		{
+			if !self.data.lines.is_empty()
+			{
+				gl::LineWidth(self.line_width);
+				gl::DrawElements(
+					gl::LINES,
+					self.data.lines.len() as gl::types::GLint,
+					gl::UNSIGNED_INT,
+					(lines_first * std::mem::size_of::<u32>()) as *const gl::types::GLvoid
+				);
+			}
+			if !self.data.points.is_empty()
+			{
+				gl::PointSize(self.point_size);
+				gl::DrawElements(
+					gl::POINTS,
+					self.data.points.len() as gl::types::GLint,
+					gl::UNSIGNED_INT,
+					(points_first * std::mem::size_of::<u32>()) as *const gl::types::GLvoid
+				);
+			}
+		}
+
+		if mirrored
+		{
+			unsafe
+			{
+				gl::FrontFace(gl::CCW);
+			}
+		}
+
+		self.vao.unbind();
+	}
+}
+
+// a shader reading other locations than the buffer provides draws garbage without any gl error
+fn check_layout(program: &render_gl::Program)
+{
+	if let Err(errors) = program.check_layout(Vertex::ATTRIBUTES)
+	{
+		for error in errors
+		{
+			println!("vertex layout doesn't match the shader: {}", error);
+		}
+	}
+}
+
+// Prints what is wrong with a freshly loaded mesh, and refuses it if it can't be drawn safely
+fn check(path: &Path, data: &MeshData) -> Result<(), Error>
+{
+	let report = validate::validate(data);
+	if !report.is_clean()
+	{
+		println!("{}: {}", path.display(), report);
+	}
+
+	if report.has_errors() { Err(Error::Invalid(report)) } else { Ok(()) }
+}
+
+// Processing done once per file, before the mesh is cached
+fn prepare(path: &Path, data: &mut MeshData)
+{
+	if data.indices.is_empty()
+	{
+		// only Bézier patches, tessellated when drawn
+		return;
+	}
+
+	// files are in whatever order they were written in, which is rarely good for the vertex cache
+	let stats = optimize::optimize(data);
+	println!("{}: ACMR {:.3} -> {:.3}", path.display(), stats.acmr_before, stats.acmr_after);
+
+	data.lods = simplify::build_lods(data);
+	if !data.lods.is_empty()
+	{
+		let counts: Vec<String> = data.lods.iter().map(|lod| lod.triangle_count().to_string()).collect();
+		println!("{}: {} triangles, LODs {}", path.display(), data.indices.len() / 3, counts.join(", "));
+	}
+}
+
+// default vertex color for files that don't have any
+fn random_color(rng: &mut impl Rng) -> math::vector::Vector3
+{
+	let random_num1: f32 = rng.gen_range(0.0..1.0);
+	let random_num2: f32 = rng.gen_range(0.0..1.0);
+	let random_num3: f32 = rng.gen_range(0.0..1.0);
+
+	(random_num1, random_num2, random_num3).into()
+}
+
+// same as Vector3::cross, but doesn't panic for parallel vectors (degenerate triangles)
+fn cross(a: math::vector::Vector3, b: math::vector::Vector3) -> math::vector::Vector3
+{
+	math::vector::Vector3::new(
+		a.y() * b.z() - a.z() * b.y(),
+		a.z() * b.x() - a.x() * b.z(),
+		a.x() * b.y() - a.y() * b.x()
+	)
+}
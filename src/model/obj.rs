@@ -0,0 +1,1558 @@
+// Wavefront obj reader and writer.
+//
+// Lines can end in \n or \r\n, tokens are separated by any mix of spaces and tabs, a \ at the end of a
+// line continues the statement on the next one and everything from a # to the end of the line is a comment.
+// Statements this reader doesn't understand are skipped and reported in ObjData::warnings.
+
+use math;
+use std::fs;
+use std::collections::HashSet;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::thread;
+use crate::model::{bezier, coords, MeshData};
+
+// what the spec calls the group faces are in before any g statement
+const DEFAULT_GROUP: &str = "default";
+// Files smaller than this are parsed on the calling thread, spawning threads costs more than it saves
+const MIN_CHUNK_SIZE: usize = 1 << 20;
+// valid statements that don't change how the model looks here, skipped without a warning
+const IGNORED: [&[u8]; 1] = [
+	// smoothing groups, normals are generated smooth anyway
+	b"s"
+];
+// standard statements this loader has no support for, warned about as unsupported rather than unknown
+const UNSUPPORTED: [&[u8]; 24] = [
+	// free-form geometry other than the surfaces handled below
+	b"vp", b"bmat", b"step", b"curv", b"curv2", b"trim", b"hole", b"scrv", b"sp", b"con",
+	// grouping and display attributes
+	b"mg", b"lod", b"bevel", b"c_interp", b"d_interp", b"shadow_obj", b"trace_obj",
+	b"maplib", b"usemap", b"ctech", b"stech",
+	// general statements and older keywords
+	b"call", b"csh", b"bsp"
+];
+
+#[derive(Debug)]
+pub enum Error
+{
+	Io(io::Error),
+	Parse {
+		line: usize,
+		message: String
+	},
+	// mesh data that can't be written out
+	Export(String)
+}
+
+impl From<io::Error> for Error
+{
+	fn from(other: io::Error) -> Self
+	{
+		Error::Io(other)
+	}
+}
+
+// One corner of a triangle, all indices are 0-based
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ObjIndex
+{
+	pub position: u32,
+	pub texcoord: Option<u32>,
+	pub normal: Option<u32>
+}
+
+// Run of consecutive triangles sharing the same group/object name and material
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjGroup
+{
+	pub name: String,
+	pub material: Option<String>,
+	// range in ObjData::indices
+	pub first: usize,
+	pub count: usize
+}
+
+// Bézier surface from `cstype bezier`, `deg`, `surf`, `parm` and `end` statements
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjSurface
+{
+	pub degree: [usize; 2],
+	// where the spans start and end in u and v, a single patch is [0, 1]
+	pub parameters: [Vec<f32>; 2],
+	// position indices of the control points, u varies fastest
+	pub control: Vec<u32>
+}
+
+impl ObjSurface
+{
+	// control points in u and v: spans * degree + 1, neighbouring spans share their edge.
+	// None if that doesn't fit in a usize, the degree and parameters come from the file
+	pub fn control_size(&self) -> Option<[usize; 2]>
+	{
+		let side = |values: &Vec<f32>, degree: usize| values.len().checked_sub(1)?.checked_mul(degree)?.checked_add(1);
+		let width = side(&self.parameters[0], self.degree[0])?;
+		let height = side(&self.parameters[1], self.degree[1])?;
+		width.checked_mul(height)?;
+		Some([width, height])
+	}
+
+	// One patch per span, texture coordinates follow the parameters over the whole surface
+	pub fn patches(&self, positions: &[math::vector::Vector3]) -> Vec<bezier::Patch>
+	{
+		let [du, dv] = self.degree;
+		let [pu, pv] = &self.parameters;
+		// only surfaces that passed surfaces() get here, this is never None for them
+		let width = match self.control_size()
+		{
+			Some([width, _]) => width,
+			None => return Vec::new()
+		};
+		let normalize = |values: &[f32], i: usize| (values[i] - values[0]) / (values[values.len() - 1] - values[0]);
+
+		let mut patches = Vec::with_capacity((pu.len() - 1) * (pv.len() - 1));
+		for sv in 0..pv.len() - 1
+		{
+			for su in 0..pu.len() - 1
+			{
+				let points = (0..=dv)
+					.flat_map(|j| (0..=du).map(move |i| (sv * dv + j) * width + su * du + i))
+					.map(|c| positions[self.control[c] as usize])
+					.collect();
+
+				let mut patch = bezier::Patch::new(self.degree, points);
+				patch.uv_range = [(normalize(pu, su), normalize(pv, sv)).into(), (normalize(pu, su + 1), normalize(pv, sv + 1)).into()];
+				patches.push(patch);
+			}
+		}
+
+		patches
+	}
+}
+
+// Something in the file that was skipped, the same message is only kept once
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning
+{
+	// first line it came up on
+	pub line: usize,
+	pub message: String,
+	// how many more times it came up after that
+	pub repeats: usize
+}
+
+// Contents of an obj file, faces are already triangulated (3 entries in `indices` per triangle)
+#[derive(Debug, Default)]
+pub struct ObjData
+{
+	pub positions: Vec<math::vector::Vector3>,
+	// one per position, from the extra values of `v x y z r g b [a]` lines
+	pub colors: Vec<Option<math::vector::Vector3>>,
+	pub texcoords: Vec<math::vector::Vector2>,
+	pub normals: Vec<math::vector::Vector3>,
+	pub indices: Vec<ObjIndex>,
+	// position indices, two per segment of the `l` polylines
+	pub lines: Vec<u32>,
+	// position indices from `p` statements
+	pub points: Vec<u32>,
+	pub groups: Vec<ObjGroup>,
+	pub material_libs: Vec<String>,
+	pub surfaces: Vec<ObjSurface>,
+	// in the order of the line they first came up on
+	pub warnings: Vec<Warning>
+}
+
+pub fn load<T>(filename: T) -> Result<ObjData, Error>
+where T: AsRef<Path>
+{
+	from_bytes(&fs::read(filename)?)
+}
+
+// Parses a whole file, using as many threads as its size is worth
+pub fn from_bytes(bytes: &[u8]) -> Result<ObjData, Error>
+{
+	let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+	let chunks = (bytes.len() / MIN_CHUNK_SIZE).clamp(1, threads);
+
+	parse(bytes, chunks)
+}
+
+// Splits the file into `chunks` pieces on line boundaries and parses them in parallel
+pub fn parse(bytes: &[u8], chunks: usize) -> Result<ObjData, Error>
+{
+	let ranges = split_lines(bytes, chunks);
+
+	let results: Vec<Result<Chunk, ChunkError>> = if ranges.len() == 1
+	{
+		vec![parse_chunk(bytes)]
+	}
+	else
+	{
+		thread::scope(|scope| {
+			let handles: Vec<_> = ranges
+				.iter()
+				.map(|&(start, end)| scope.spawn(move || parse_chunk(&bytes[start..end])))
+				.collect();
+
+			handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+		})
+	};
+
+	// errors only know their offset, turn that into a line number in the file
+	let to_error = |e: ChunkError| Error::Parse { line: to_line(bytes, e.offset), message: e.message };
+
+	let mut parsed = Vec::with_capacity(results.len());
+	let mut warnings = Vec::<(usize, String, usize)>::new();
+	for (result, &(start, _)) in results.into_iter().zip(ranges.iter())
+	{
+		let mut chunk = result.map_err(|e| to_error(ChunkError { offset: start + e.offset, ..e }))?;
+		for (offset, _) in &mut chunk.freeform
+		{
+			*offset += start;
+		}
+		for (offset, message, repeats) in chunk.warnings.drain(..)
+		{
+			// chunks are in file order, so the first one to report something has its first line
+			match warnings.iter_mut().find(|w| w.1 == message)
+			{
+				Some(warning) => warning.2 += repeats + 1,
+				None => warnings.push((start + offset, message, repeats))
+			}
+		}
+		parsed.push(chunk);
+	}
+
+	let (mut data, freeform) = stitch(parsed);
+	data.surfaces = surfaces(freeform, data.positions.len()).map_err(to_error)?;
+	warnings.sort_by_key(|w| w.0);
+	data.warnings = warnings
+		.into_iter()
+		.map(|(offset, message, repeats)| Warning { line: to_line(bytes, offset), message, repeats })
+		.collect();
+
+	Ok(data)
+}
+
+fn to_line(bytes: &[u8], offset: usize) -> usize
+{
+	bytes[..offset].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+// Returns [start, end) byte ranges that each end right after a newline (or at the end of the file),
+// but never in the middle of a statement continued with \
+fn split_lines(bytes: &[u8], chunks: usize) -> Vec<(usize, usize)>
+{
+	let target = bytes.len() / chunks.max(1);
+	let mut ranges = Vec::with_capacity(chunks);
+	let mut start = 0;
+
+	while start < bytes.len()
+	{
+		let mut end = (start + target.max(1)).min(bytes.len());
+		if ranges.len() + 1 == chunks
+		{
+			end = bytes.len();
+		}
+
+		while end < bytes.len() && (bytes[end - 1] != b'\n' || continues(&bytes[..end - 1]))
+		{
+			end += 1;
+		}
+
+		ranges.push((start, end));
+		start = end;
+	}
+
+	if ranges.is_empty()
+	{
+		ranges.push((0, 0));
+	}
+
+	ranges
+}
+
+// `g`/`o` and `usemtl` statements, they apply to all faces after them until the next one
+#[derive(Clone, Debug)]
+enum GroupEvent
+{
+	Name(String),
+	// None after a usemtl without a name
+	Material(Option<String>)
+}
+
+// free-form geometry statements, only put together into surfaces once the whole file is parsed
+#[derive(Clone, Debug)]
+enum FreeForm
+{
+	CurveType(String),
+	Degree(Vec<usize>),
+	// control point position indices
+	Surface(Vec<Index>),
+	// true for u, false for v
+	Parameters(bool, Vec<f32>),
+	End
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Attribute
+{
+	Position,
+	Texcoord,
+	Normal
+}
+
+// `l` and `p` statements, they only use the positions
+#[derive(Copy, Clone, Debug)]
+enum Element
+{
+	Line,
+	Point
+}
+
+// Result of parsing one piece of the file.
+// Absolute indices are final already, relative (negative) ones depend on how many
+// vertices came before this chunk, so they are patched in `stitch`
+#[derive(Default)]
+struct Chunk
+{
+	data: ObjData,
+	// (position in data.indices, which index, index relative to the first vertex of this chunk)
+	relative: Vec<(usize, Attribute, i64)>,
+	// (line or point, position in data.lines or data.points, index relative to the first vertex of this chunk)
+	relative_elements: Vec<(Element, usize, i64)>,
+	// (position in data.indices, event), a chunk doesn't know which group was active when it started
+	events: Vec<(usize, GroupEvent)>,
+	// (byte offset, statement), relative indices are resolved in `stitch` like the face ones
+	freeform: Vec<(usize, FreeForm)>,
+	// (byte offset of the first one, message, how many more)
+	warnings: Vec<(usize, String, usize)>
+}
+
+struct ChunkError
+{
+	offset: usize,
+	message: String
+}
+
+fn parse_chunk(bytes: &[u8]) -> Result<Chunk, ChunkError>
+{
+	let mut chunk = Chunk::default();
+	let mut corners = Vec::<ObjIndex>::new();
+	let mut offset = 0;
+	// statements continued over several lines, put back together
+	let mut joined = Vec::<u8>::new();
+	let mut lines = bytes.split(|&b| b == b'\n');
+
+	while let Some(mut line) = lines.next()
+	{
+		let line_offset = offset;
+		offset += line.len() + 1;
+
+		if continues(line)
+		{
+			joined.clear();
+			while continues(line)
+			{
+				let content = trim_cr(line);
+				joined.extend_from_slice(&content[..content.len() - 1]);
+				joined.push(b' ');
+				line = lines.next().unwrap_or(b"");
+				offset += line.len() + 1;
+			}
+			joined.extend_from_slice(line);
+			line = &joined;
+		}
+
+		// everything after a # is a comment
+		let line = match line.iter().position(|&b| b == b'#')
+		{
+			Some(comment) => &line[..comment],
+			None => line
+		};
+
+		let mut tokens = line.split(|b| b.is_ascii_whitespace()).filter(|s| !s.is_empty());
+		let keyword = match tokens.next()
+		{
+			Some(keyword) => keyword,
+			None => continue
+		};
+
+		let result = match keyword
+		{
+			b"v" => parse_position(&mut tokens).map(|(position, color)| {
+				chunk.data.positions.push(position);
+				chunk.data.colors.push(color);
+			}),
+			b"vt" => parse_texcoord(&mut tokens)
+				.map(|texcoord| chunk.data.texcoords.push(texcoord)),
+			b"vn" => parse_floats::<3>(&mut tokens)
+				.map(|v| chunk.data.normals.push((v[0], v[1], v[2]).into())),
+			b"f" => {
+				corners.clear();
+				parse_face(&mut tokens, &mut chunk, &mut corners)
+			},
+			b"l" => parse_element(tokens, Element::Line, &mut chunk),
+			b"p" => parse_element(tokens, Element::Point, &mut chunk),
+			b"g" | b"o" => {
+				// `g` alone or `g default` goes back to the unnamed group
+				let name = join_tokens(tokens);
+				let name = if name == DEFAULT_GROUP { String::new() } else { name };
+				chunk.events.push((chunk.data.indices.len(), GroupEvent::Name(name)));
+				Ok(())
+			},
+			b"usemtl" => {
+				// `usemtl` alone goes back to no material
+				let name = join_tokens(tokens);
+				let material = if name.is_empty() { None } else { Some(name) };
+				chunk.events.push((chunk.data.indices.len(), GroupEvent::Material(material)));
+				Ok(())
+			},
+			b"mtllib" => {
+				chunk.data.material_libs.extend(tokens.map(|t| String::from_utf8_lossy(t).into_owned()));
+				Ok(())
+			},
+			b"cstype" => {
+				chunk.freeform.push((line_offset, FreeForm::CurveType(join_tokens(tokens))));
+				Ok(())
+			},
+			b"deg" => parse_counts(tokens)
+				.map(|degree| chunk.freeform.push((line_offset, FreeForm::Degree(degree)))),
+			b"surf" => parse_surface(tokens, chunk.data.positions.len())
+				.map(|control| chunk.freeform.push((line_offset, FreeForm::Surface(control)))),
+			b"parm" => parse_parameters(tokens)
+				.map(|(u, values)| chunk.freeform.push((line_offset, FreeForm::Parameters(u, values)))),
+			b"end" => {
+				chunk.freeform.push((line_offset, FreeForm::End));
+				Ok(())
+			},
+			keyword if IGNORED.contains(&keyword) => Ok(()),
+			keyword => {
+				let kind = if UNSUPPORTED.contains(&keyword) { "unsupported" } else { "unknown" };
+				let message = format!("skipped {} statement '{}'", kind, String::from_utf8_lossy(keyword));
+				match chunk.warnings.iter_mut().find(|w| w.1 == message)
+				{
+					Some(warning) => warning.2 += 1,
+					None => chunk.warnings.push((line_offset, message, 0))
+				}
+				Ok(())
+			}
+		};
+
+		result.map_err(|message| ChunkError { offset: line_offset, message })?;
+	}
+
+	Ok(chunk)
+}
+
+// the line (without its \n) ends in a \, so the statement goes on on the next one
+fn continues(line: &[u8]) -> bool
+{
+	trim_cr(line).last() == Some(&b'\\')
+}
+
+fn trim_cr(line: &[u8]) -> &[u8]
+{
+	line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn parse_floats<'a, const N: usize>(tokens: &mut impl Iterator<Item = &'a [u8]>) -> Result<[f32; N], String>
+{
+	let mut values = [0.0; N];
+	for value in values.iter_mut()
+	{
+		let token = tokens.next().ok_or_else(|| format!("expected {} values", N))?;
+		*value = to_str(token)?
+			.parse()
+			.map_err(|_| format!("invalid number '{}'", String::from_utf8_lossy(token)))?;
+	}
+
+	Ok(values)
+}
+
+// Up to N values into a fixed buffer, so lines with optional values don't allocate.
+// Returns how many were read, N + 1 if there are more than N
+fn parse_optional_floats<'a, const N: usize>(tokens: &mut impl Iterator<Item = &'a [u8]>) -> Result<([f32; N], usize), String>
+{
+	let mut values = [0.0; N];
+	let mut count = 0;
+	for token in tokens
+	{
+		if count == N
+		{
+			return Ok((values, N + 1));
+		}
+		values[count] = to_str(token)?
+			.parse()
+			.map_err(|_| format!("invalid number '{}'", String::from_utf8_lossy(token)))?;
+		count += 1;
+	}
+
+	Ok((values, count))
+}
+
+// `vt u [v [w]]`, v defaults to 0 and w (for 3d textures) is dropped
+fn parse_texcoord<'a>(tokens: &mut impl Iterator<Item = &'a [u8]>) -> Result<math::vector::Vector2, String>
+{
+	match parse_optional_floats::<3>(tokens)?
+	{
+		([u, v, _], 1..=3) => Ok((u, v).into()),
+		(_, 0) => Err("expected 1 to 3 values, found none".into()),
+		_ => Err("expected 1 to 3 values, found more".into())
+	}
+}
+
+// `v x y z`, optionally followed by w (ignored) or by a color: r g b, or r g b a with the alpha dropped.
+// Colors are 0..1, files that write 0..255 are recognized by a component above 1
+fn parse_position<'a>(tokens: &mut impl Iterator<Item = &'a [u8]>) -> Result<(math::vector::Vector3, Option<math::vector::Vector3>), String>
+{
+	let [x, y, z] = parse_floats::<3>(tokens)?;
+	let color = match parse_optional_floats::<4>(tokens)?
+	{
+		(_, 0 | 1) => None,
+		([r, g, b, _], 3 | 4) => {
+			let scale = if [r, g, b].iter().any(|&c| c > 1.0) { 1.0 / 255.0 } else { 1.0 };
+			Some((r * scale, g * scale, b * scale).into())
+		},
+		(_, 2) => return Err("expected 3 or 4 color values after the position, found 2".into()),
+		_ => return Err("expected 3 or 4 color values after the position, found more".into())
+	};
+
+	Ok(((x, y, z).into(), color))
+}
+
+fn parse_face<'a>(tokens: &mut impl Iterator<Item = &'a [u8]>, chunk: &mut Chunk, corners: &mut Vec<ObjIndex>) -> Result<(), String>
+{
+	// relative indices of the current face, by corner
+	let mut relative = Vec::<(usize, Attribute, i64)>::new();
+
+	for token in tokens
+	{
+		let corner = corners.len();
+		let mut parts = token.split(|&b| b == b'/');
+
+		let position = parse_index(parts.next(), chunk.data.positions.len())?
+			.ok_or("face is missing a vertex index")?;
+		let texcoord = parse_index(parts.next(), chunk.data.texcoords.len())?;
+		let normal = parse_index(parts.next(), chunk.data.normals.len())?;
+
+		let mut index = ObjIndex { position: 0, texcoord: None, normal: None };
+		match position
+		{
+			Index::Absolute(i) => index.position = i,
+			Index::Relative(i) => relative.push((corner, Attribute::Position, i))
+		}
+		match texcoord
+		{
+			Some(Index::Absolute(i)) => index.texcoord = Some(i),
+			Some(Index::Relative(i)) => { index.texcoord = Some(0); relative.push((corner, Attribute::Texcoord, i)) },
+			None => {}
+		}
+		match normal
+		{
+			Some(Index::Absolute(i)) => index.normal = Some(i),
+			Some(Index::Relative(i)) => { index.normal = Some(0); relative.push((corner, Attribute::Normal, i)) },
+			None => {}
+		}
+
+		corners.push(index);
+	}
+
+	if corners.len() < 3
+	{
+		return Err("face needs at least 3 vertices".into());
+	}
+
+	// fan triangulation: (0, 1, 2), (0, 2, 3), ...
+	for i in 1..corners.len() - 1
+	{
+		for corner in [0, i, i + 1]
+		{
+			for &(_, attribute, value) in relative.iter().filter(|r| r.0 == corner)
+			{
+				chunk.relative.push((chunk.data.indices.len(), attribute, value));
+			}
+			chunk.data.indices.push(corners[corner]);
+		}
+	}
+
+	Ok(())
+}
+
+// `l v1 v2 ...` is split into segments (v1 v2), (v2 v3), ..., `p v1 v2 ...` into single points.
+// Texture coordinate indices (`v/vt`) are allowed but not used
+fn parse_element<'a>(tokens: impl Iterator<Item = &'a [u8]>, element: Element, chunk: &mut Chunk) -> Result<(), String>
+{
+	let mut indices = Vec::new();
+	for token in tokens
+	{
+		let position = token.split(|&b| b == b'/').next();
+		indices.push(parse_index(position, chunk.data.positions.len())?.ok_or("element is missing a vertex index")?);
+	}
+
+	let order: Vec<Index> = match element
+	{
+		Element::Line if indices.len() < 2 => return Err("line needs at least 2 vertices".into()),
+		Element::Line => indices.windows(2).flat_map(|pair| [pair[0], pair[1]]).collect(),
+		Element::Point if indices.is_empty() => return Err("point needs a vertex".into()),
+		Element::Point => indices
+	};
+
+	let target = match element
+	{
+		Element::Line => &mut chunk.data.lines,
+		Element::Point => &mut chunk.data.points
+	};
+
+	for index in order
+	{
+		match index
+		{
+			Index::Absolute(i) => target.push(i),
+			Index::Relative(i) => {
+				chunk.relative_elements.push((element, target.len(), i));
+				target.push(0);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Index
+{
+	Absolute(u32),
+	// relative to the start of the chunk, can be negative if it points into a previous chunk
+	Relative(i64)
+}
+
+// obj indices start at 1, negative ones count backwards from the last vertex defined so far
+fn parse_index(token: Option<&[u8]>, defined: usize) -> Result<Option<Index>, String>
+{
+	let token = match token
+	{
+		Some(token) if !token.is_empty() => token,
+		_ => return Ok(None)
+	};
+
+	let value: i64 = to_str(token)?
+		.parse()
+		.map_err(|_| format!("invalid index '{}'", String::from_utf8_lossy(token)))?;
+
+	if value > 0 && value <= u32::MAX as i64
+	{
+		Ok(Some(Index::Absolute((value - 1) as u32)))
+	}
+	else if value < 0
+	{
+		Ok(Some(Index::Relative(defined as i64 + value)))
+	}
+	else
+	{
+		Err(format!("invalid index '{}'", value))
+	}
+}
+
+fn parse_counts<'a>(tokens: impl Iterator<Item = &'a [u8]>) -> Result<Vec<usize>, String>
+{
+	tokens
+		.map(|token| to_str(token)?.parse().map_err(|_| format!("invalid degree '{}'", String::from_utf8_lossy(token))))
+		.collect()
+}
+
+// `surf s0 s1 t0 t1 v1 v2 ...`, the parameter range is ignored and only the position of each control vertex is used
+fn parse_surface<'a>(mut tokens: impl Iterator<Item = &'a [u8]>, defined: usize) -> Result<Vec<Index>, String>
+{
+	parse_floats::<4>(&mut tokens)?;
+
+	let mut control = Vec::new();
+	for token in tokens
+	{
+		let position = token.split(|&b| b == b'/').next();
+		control.push(parse_index(position, defined)?.ok_or("surface is missing a control point index")?);
+	}
+
+	Ok(control)
+}
+
+fn parse_parameters<'a>(mut tokens: impl Iterator<Item = &'a [u8]>) -> Result<(bool, Vec<f32>), String>
+{
+	let u = match tokens.next()
+	{
+		Some(b"u") => true,
+		Some(b"v") => false,
+		_ => return Err("expected 'u' or 'v' after parm".into())
+	};
+
+	let values = tokens
+		.map(|token| to_str(token)?.parse().map_err(|_| format!("invalid number '{}'", String::from_utf8_lossy(token))))
+		.collect::<Result<Vec<f32>, String>>()?;
+
+	Ok((u, values))
+}
+
+// Puts the free-form statements together into surfaces, surfaces of other types than bezier are skipped
+fn surfaces(statements: Vec<(usize, FreeForm)>, position_count: usize) -> Result<Vec<ObjSurface>, ChunkError>
+{
+	let mut surfaces = Vec::new();
+	let mut bezier = false;
+	let mut degree = Vec::new();
+	let mut current: Option<(usize, ObjSurface)> = None;
+
+	let finish = |current: Option<(usize, ObjSurface)>, surfaces: &mut Vec<ObjSurface>| -> Result<(), ChunkError> {
+		let (offset, surface) = match current
+		{
+			Some(current) => current,
+			None => return Ok(())
+		};
+		let error = |message: String| ChunkError { offset, message };
+
+		for values in &surface.parameters
+		{
+			if values.len() < 2 || values.windows(2).any(|w| w[1] <= w[0])
+			{
+				return Err(error("surface parameters have to be at least 2 increasing values".into()));
+			}
+		}
+
+		let expected = match surface.control_size()
+		{
+			Some([width, height]) => width * height,
+			None => return Err(error("surface has too many control points".into()))
+		};
+		if surface.control.len() != expected
+		{
+			return Err(error(format!("surface needs {} control points, found {}", expected, surface.control.len())));
+		}
+		if let Some(&index) = surface.control.iter().find(|&&i| i as usize >= position_count)
+		{
+			return Err(error(format!("control point {} doesn't exist", index as u64 + 1)));
+		}
+
+		surfaces.push(surface);
+		Ok(())
+	};
+
+	for (offset, statement) in statements
+	{
+		match statement
+		{
+			FreeForm::CurveType(name) => bezier = name == "bezier",
+			FreeForm::Degree(values) => degree = values,
+			FreeForm::Surface(control) => {
+				finish(current.take(), &mut surfaces)?;
+				if !bezier
+				{
+					continue;
+				}
+
+				let degree = match degree.as_slice()
+				{
+					&[du, dv] if du > 0 && dv > 0 => [du, dv],
+					_ => return Err(ChunkError { offset, message: "bezier surface needs a 'deg' with two degrees above 0 first".into() })
+				};
+				if degree.iter().any(|&d| d > bezier::MAX_DEGREE)
+				{
+					return Err(ChunkError { offset, message: format!("bezier surface degree can be at most {}", bezier::MAX_DEGREE) });
+				}
+				let control = control
+					.into_iter()
+					.map(|index| match index
+					{
+						Index::Absolute(i) => i,
+						Index::Relative(_) => unreachable!("resolved in stitch")
+					})
+					.collect();
+				current = Some((offset, ObjSurface { degree, parameters: [vec![0.0, 1.0], vec![0.0, 1.0]], control }));
+			},
+			FreeForm::Parameters(u, values) => {
+				if let Some((_, surface)) = &mut current
+				{
+					surface.parameters[if u { 0 } else { 1 }] = values;
+				}
+			},
+			FreeForm::End => finish(current.take(), &mut surfaces)?
+		}
+	}
+	finish(current, &mut surfaces)?;
+
+	Ok(surfaces)
+}
+
+fn join_tokens<'a>(tokens: impl Iterator<Item = &'a [u8]>) -> String
+{
+	tokens.map(|t| String::from_utf8_lossy(t)).collect::<Vec<_>>().join(" ")
+}
+
+fn to_str(token: &[u8]) -> Result<&str, String>
+{
+	str::from_utf8(token).map_err(|_| "invalid utf-8".to_string())
+}
+
+// Concatenates the chunks and resolves relative indices now that the vertex counts before each chunk are known
+fn stitch(chunks: Vec<Chunk>) -> (ObjData, Vec<(usize, FreeForm)>)
+{
+	let mut data = ObjData::default();
+	let mut freeform = Vec::new();
+	let mut group = ObjGroup { name: String::new(), material: None, first: 0, count: 0 };
+	data.positions.reserve(chunks.iter().map(|c| c.data.positions.len()).sum());
+	data.colors.reserve(chunks.iter().map(|c| c.data.colors.len()).sum());
+	data.texcoords.reserve(chunks.iter().map(|c| c.data.texcoords.len()).sum());
+	data.normals.reserve(chunks.iter().map(|c| c.data.normals.len()).sum());
+	data.indices.reserve(chunks.iter().map(|c| c.data.indices.len()).sum());
+
+	for chunk in chunks
+	{
+		let first_index = data.indices.len();
+		let (first_line, first_point) = (data.lines.len(), data.points.len());
+		let offsets = [data.positions.len() as i64, data.texcoords.len() as i64, data.normals.len() as i64];
+
+		data.positions.extend_from_slice(&chunk.data.positions);
+		data.colors.extend_from_slice(&chunk.data.colors);
+		data.texcoords.extend_from_slice(&chunk.data.texcoords);
+		data.normals.extend_from_slice(&chunk.data.normals);
+		data.indices.extend_from_slice(&chunk.data.indices);
+		data.lines.extend_from_slice(&chunk.data.lines);
+		data.points.extend_from_slice(&chunk.data.points);
+		data.material_libs.extend(chunk.data.material_libs);
+
+		for (i, event) in chunk.events
+		{
+			let position = first_index + i;
+			if position > group.first
+			{
+				group.count = position - group.first;
+				data.groups.push(group.clone());
+			}
+			group.first = position;
+			match event
+			{
+				GroupEvent::Name(name) => group.name = name,
+				GroupEvent::Material(material) => group.material = material
+			}
+		}
+
+		for (i, attribute, value) in chunk.relative
+		{
+			let index = &mut data.indices[first_index + i];
+			// a relative index that points before the start of the file is clamped to u32::MAX, so it shows up as out of range
+			let resolve = |offset: i64| u32::try_from(offset + value).unwrap_or(u32::MAX);
+			match attribute
+			{
+				Attribute::Position => index.position = resolve(offsets[0]),
+				Attribute::Texcoord => index.texcoord = Some(resolve(offsets[1])),
+				Attribute::Normal => index.normal = Some(resolve(offsets[2]))
+			}
+		}
+
+		for (element, i, value) in chunk.relative_elements
+		{
+			let index = match element
+			{
+				Element::Line => &mut data.lines[first_line + i],
+				Element::Point => &mut data.points[first_point + i]
+			};
+			*index = u32::try_from(offsets[0] + value).unwrap_or(u32::MAX);
+		}
+
+		for (offset, mut statement) in chunk.freeform
+		{
+			if let FreeForm::Surface(control) = &mut statement
+			{
+				for index in control.iter_mut()
+				{
+					if let Index::Relative(value) = *index
+					{
+						*index = Index::Absolute(u32::try_from(offsets[0] + value).unwrap_or(u32::MAX));
+					}
+				}
+			}
+			freeform.push((offset, statement));
+		}
+	}
+
+	if data.indices.len() > group.first
+	{
+		group.count = data.indices.len() - group.first;
+		data.groups.push(group);
+	}
+
+	(data, freeform)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions
+{
+	// write faces with negative indices, counting back from the last vertex
+	pub relative_indices: bool,
+	// directory the mesh's material_libs are relative to (where it was loaded from),
+	// they are copied next to the exported file. None only references them
+	pub material_dir: Option<PathBuf>
+}
+
+// Writes the mesh to `filename` with `transform` applied.
+// The material libraries it was loaded with are referenced (and copied, see WriteOptions::material_dir),
+// a mesh with materials but no library gets a .mtl next to it with a plain white one for each.
+// Normals go through the same transform without the translation, so it should not shear
+pub fn write<T>(filename: T, data: &MeshData, transform: &math::matrix::Matrix4, options: &WriteOptions) -> Result<(), Error>
+where T: AsRef<Path>
+{
+	let path = filename.as_ref();
+	let out_dir = path.parent().unwrap_or(Path::new(""));
+
+	let mut libs = data.material_libs.clone();
+	let generated = libs.is_empty() && !material_names(data).is_empty();
+	let mtl_path = path.with_extension("mtl");
+	if generated
+	{
+		libs.extend(mtl_path.file_name().map(|name| name.to_string_lossy().into_owned()));
+	}
+
+	let mut out = BufWriter::new(fs::File::create(path)?);
+	write_to(&mut out, data, transform, &libs, options)?;
+	out.flush()?;
+
+	if generated
+	{
+		let mut out = BufWriter::new(fs::File::create(&mtl_path)?);
+		write_mtl_to(&mut out, data)?;
+		out.flush()?;
+	}
+	else if let Some(material_dir) = &options.material_dir
+	{
+		for lib in &libs
+		{
+			let (from, to) = (material_dir.join(lib), out_dir.join(lib));
+			// a library that is missing was already missing in the source, and exporting next to the source copies nothing
+			if from.is_file() && !same_file(&from, &to)
+			{
+				if let Some(parent) = to.parent()
+				{
+					fs::create_dir_all(parent)?;
+				}
+				fs::copy(&from, &to)?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool
+{
+	match (a.canonicalize(), b.canonicalize())
+	{
+		(Ok(a), Ok(b)) => a == b,
+		_ => false
+	}
+}
+
+// Every vertex is written as one v, vt and vn line (in that order), so faces use the same index for all three
+pub fn write_to(out: &mut impl Write, data: &MeshData, transform: &math::matrix::Matrix4, material_libs: &[String], options: &WriteOptions) -> Result<(), Error>
+{
+	if let Some(bad) = data.indices.iter().chain(&data.lines).chain(&data.points).find(|&&i| i as usize >= data.vertices.len())
+	{
+		return Err(Error::Export(format!("index {} out of range, mesh has {} vertices", bad, data.vertices.len())));
+	}
+
+	writeln!(out, "# exported by scop")?;
+	if !material_libs.is_empty()
+	{
+		writeln!(out, "mtllib {}", material_libs.join(" "))?;
+	}
+
+	for vertex in &data.vertices
+	{
+		let p: math::vector::Vector4 = *transform * math::vector::Vector4::from((vertex.position(), 1.0));
+		if data.vertex_colors
+		{
+			let c = vertex.color();
+			writeln!(out, "v {} {} {} {} {} {}", p.x(), p.y(), p.z(), c.x(), c.y(), c.z())?;
+		}
+		else
+		{
+			writeln!(out, "v {} {} {}", p.x(), p.y(), p.z())?;
+		}
+	}
+	for vertex in &data.vertices
+	{
+		let t = vertex.texcoord();
+		writeln!(out, "vt {} {}", t.x(), t.y())?;
+	}
+	for vertex in &data.vertices
+	{
+		let n: math::vector::Vector4 = *transform * math::vector::Vector4::from((vertex.normal(), 0.0));
+		let n = math::vector::Vector3::new(n.x(), n.y(), n.z());
+		let length = n.length();
+		let n = if length > 0.0 { n / length } else { n };
+		writeln!(out, "vn {} {} {}", n.x(), n.y(), n.z())?;
+	}
+
+	let count = data.vertices.len() as i64;
+	let corner = |index: u32| -> String {
+		let i = if options.relative_indices { index as i64 - count } else { index as i64 + 1 };
+		format!("{0}/{0}/{0}", i)
+	};
+
+	// faces keep pointing outwards through a mirroring transform
+	let mirrored = coords::mirrors(transform);
+	// the state a reader starts in, the unnamed group without a material
+	let mut name = "";
+	let mut material: Option<&str> = None;
+	for submesh in &data.submeshes
+	{
+		// both are written again when they go back to nothing, faces would stay in the previous group/material otherwise
+		if name != submesh.name
+		{
+			let written = if submesh.name.is_empty() { DEFAULT_GROUP } else { &submesh.name };
+			writeln!(out, "g {}", written)?;
+			name = &submesh.name;
+		}
+		if material != submesh.material.as_deref()
+		{
+			writeln!(out, "usemtl {}", submesh.material.as_deref().unwrap_or_default())?;
+			material = submesh.material.as_deref();
+		}
+
+		let first = (submesh.first as usize).min(data.indices.len());
+		let end = (first + submesh.count as usize).min(data.indices.len());
+		for triangle in data.indices[first..end].chunks_exact(3)
+		{
+			let (b, c) = if mirrored { (triangle[2], triangle[1]) } else { (triangle[1], triangle[2]) };
+			writeln!(out, "f {} {} {}", corner(triangle[0]), corner(b), corner(c))?;
+		}
+	}
+
+	let position = |index: u32| if options.relative_indices { index as i64 - count } else { index as i64 + 1 };
+	for segment in data.lines.chunks_exact(2)
+	{
+		writeln!(out, "l {} {}", position(segment[0]), position(segment[1]))?;
+	}
+	for &point in &data.points
+	{
+		writeln!(out, "p {}", position(point))?;
+	}
+
+	Ok(())
+}
+
+// One newmtl per material name, for meshes that came without a library.
+// Plain white, vertex colors are mostly the random ones and say nothing about the material
+pub fn write_mtl_to(out: &mut impl Write, data: &MeshData) -> Result<(), Error>
+{
+	writeln!(out, "# exported by scop")?;
+
+	for name in material_names(data)
+	{
+		writeln!(out)?;
+		writeln!(out, "newmtl {}", name)?;
+		writeln!(out, "Kd 1 1 1")?;
+	}
+
+	Ok(())
+}
+
+// distinct material names, in the order they are first used
+fn material_names(data: &MeshData) -> Vec<&str>
+{
+	let mut seen = HashSet::new();
+	data.submeshes
+		.iter()
+		.filter_map(|s| s.material.as_deref())
+		.filter(|name| seen.insert(*name))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use std::io::{BufRead, Write};
+	use std::time::Instant;
+	use crate::model::SubMesh;
+
+	const CUBE: &str = "v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+vt 0 0
+vn 0 0 -1
+mtllib cube.mtl
+o front
+usemtl red
+f 1/1/1 2/1/1 3/1/1 4/1/1
+f -4 -3 -2 -1
+g side
+f 1 5 6
+usemtl blue
+f 2//1 6//1 7//1
+";
+
+	#[test]
+	fn parses_and_triangulates()
+	{
+		let data = parse(CUBE.as_bytes(), 1).unwrap();
+
+		assert_eq!(data.positions.len(), 8);
+		assert_eq!(data.texcoords.len(), 1);
+		assert_eq!(data.normals.len(), 1);
+		// quad -> 2 triangles, quad -> 2 triangles, 2 triangles
+		assert_eq!(data.indices.len(), 18);
+
+		let positions: Vec<u32> = data.indices.iter().map(|i| i.position).collect();
+		assert_eq!(positions, vec![0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7, 0, 4, 5, 1, 5, 6]);
+		assert_eq!(data.indices[0], ObjIndex { position: 0, texcoord: Some(0), normal: Some(0) });
+		assert_eq!(data.indices[15], ObjIndex { position: 1, texcoord: None, normal: Some(0) });
+
+		assert_eq!(data.material_libs, vec!["cube.mtl".to_string()]);
+		assert_eq!(data.groups, vec![
+			ObjGroup { name: "front".into(), material: Some("red".into()), first: 0, count: 12 },
+			ObjGroup { name: "side".into(), material: Some("red".into()), first: 12, count: 3 },
+			ObjGroup { name: "side".into(), material: Some("blue".into()), first: 15, count: 3 }
+		]);
+	}
+
+	#[test]
+	fn chunked_matches_single_threaded()
+	{
+		let single = parse(CUBE.as_bytes(), 1).unwrap();
+
+		// enough chunks to put every line in its own chunk, so relative indices have to cross chunk boundaries
+		for chunks in 2..20
+		{
+			let chunked = parse(CUBE.as_bytes(), chunks).unwrap();
+			assert_eq!(chunked.positions, single.positions);
+			assert_eq!(chunked.colors, single.colors);
+			assert_eq!(chunked.indices, single.indices);
+			assert_eq!(chunked.groups, single.groups);
+		}
+	}
+
+	#[test]
+	fn write_round_trip()
+	{
+		let mut mesh = MeshData::from_obj(parse(CUBE.as_bytes(), 1).unwrap());
+		mesh.submeshes[1].name = "side with spaces".into();
+		let offset = math::translate(&math::matrix::Matrix4::new_identity(), &(0.0, 0.0, 2.0).into());
+
+		for relative_indices in [false, true]
+		{
+			let mut bytes = Vec::new();
+			let options = WriteOptions { relative_indices, ..Default::default() };
+			write_to(&mut bytes, &mesh, &offset, &["cube.mtl".to_string()], &options).unwrap();
+			let loaded = parse(&bytes, 3).unwrap();
+
+			assert_eq!(loaded.positions.len(), mesh.vertices.len());
+			for (i, vertex) in mesh.vertices.iter().enumerate()
+			{
+				assert_eq!(loaded.positions[i], vertex.position() + math::vector::Vector3::new(0.0, 0.0, 2.0));
+				assert_eq!(loaded.texcoords[i], vertex.texcoord());
+				assert!((loaded.normals[i] - vertex.normal()).length() < 1e-6);
+			}
+			for (index, &expected) in loaded.indices.iter().zip(&mesh.indices)
+			{
+				assert_eq!(*index, ObjIndex { position: expected, texcoord: Some(expected), normal: Some(expected) });
+			}
+			assert_eq!(loaded.indices.len(), mesh.indices.len());
+			assert_eq!(loaded.material_libs, vec!["cube.mtl".to_string()]);
+
+			let groups: Vec<_> = loaded.groups.iter()
+				.map(|g| (g.name.as_str(), g.material.as_deref(), g.first as u32, g.count as u32))
+				.collect();
+			let submeshes: Vec<_> = mesh.submeshes.iter()
+				.map(|s| (s.name.as_str(), s.material.as_deref(), s.first, s.count))
+				.collect();
+			assert_eq!(groups, submeshes);
+		}
+
+		let mut mtl = Vec::new();
+		write_mtl_to(&mut mtl, &mesh).unwrap();
+		let mtl = String::from_utf8(mtl).unwrap();
+		assert_eq!(mtl.matches("newmtl").count(), 2);
+		assert!(mtl.contains("newmtl red\nKd 1 1 1\n"));
+		assert!(mtl.contains("newmtl blue\nKd 1 1 1\n"));
+	}
+
+	#[test]
+	fn write_resets_group_and_material()
+	{
+		// the face after blue has no group and no material, it must not end up in side/blue on reload
+		let source = format!("{}g default\nusemtl\nf 3 4 8\n", CUBE);
+		let mut mesh = MeshData::from_obj(parse(source.as_bytes(), 1).unwrap());
+		let submeshes: Vec<_> = mesh.submeshes.iter().map(|s| (s.name.as_str(), s.material.as_deref())).collect();
+		assert_eq!(submeshes, vec![("front", Some("red")), ("side", Some("red")), ("side", Some("blue")), ("", None)]);
+
+		// and a material on the unnamed group, after one without
+		mesh.submeshes.push(SubMesh { name: String::new(), material: Some("red".into()), first: mesh.indices.len() as u32, count: 3 });
+		mesh.indices.extend_from_slice(&[0, 1, 2]);
+
+		let mut bytes = Vec::new();
+		write_to(&mut bytes, &mesh, &math::matrix::Matrix4::new_identity(), &[], &WriteOptions::default()).unwrap();
+		let loaded = MeshData::from_obj(parse(&bytes, 1).unwrap());
+		assert_eq!(loaded.submeshes, mesh.submeshes);
+	}
+
+	#[test]
+	fn write_keeps_material_libs()
+	{
+		let root = std::env::temp_dir().join(format!("scop_obj_mtl_{}", std::process::id()));
+		let (source, export) = (root.join("source"), root.join("export"));
+		fs::create_dir_all(source.join("materials")).unwrap();
+		fs::create_dir_all(&export).unwrap();
+		fs::write(source.join("materials/cube.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+
+		let mut mesh = MeshData::from_obj(parse(CUBE.as_bytes(), 1).unwrap());
+		mesh.material_libs = vec!["materials/cube.mtl".into()];
+		let options = WriteOptions { material_dir: Some(source.clone()), ..Default::default() };
+		write(export.join("out.obj"), &mesh, &math::matrix::Matrix4::new_identity(), &options).unwrap();
+
+		let loaded = load(export.join("out.obj")).unwrap();
+		assert_eq!(loaded.material_libs, mesh.material_libs);
+		assert_eq!(fs::read_to_string(export.join("materials/cube.mtl")).unwrap(), "newmtl red\nKd 1 0 0\n");
+		assert!(!export.join("out.mtl").exists());
+
+		// without a library of its own one is made up
+		mesh.material_libs.clear();
+		write(export.join("bare.obj"), &mesh, &math::matrix::Matrix4::new_identity(), &WriteOptions::default()).unwrap();
+		assert_eq!(load(export.join("bare.obj")).unwrap().material_libs, vec!["bare.mtl".to_string()]);
+		assert!(fs::read_to_string(export.join("bare.mtl")).unwrap().contains("newmtl blue\nKd 1 1 1\n"));
+
+		fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn vertex_colors()
+	{
+		let source = "v 0 0 0 1 0 0\nv 1 0 0 0 0.5 1 0.25\nv 0 1 0 255 128 0\nv 1 1 0 1\nv 0 0 1\nf 1 2 3\nf 2 4 5\n";
+		for chunks in 1..4
+		{
+			let data = parse(source.as_bytes(), chunks).unwrap();
+			assert_eq!(data.colors, vec![
+				Some((1.0, 0.0, 0.0).into()),
+				Some((0.0, 0.5, 1.0).into()),
+				Some((1.0, 128.0 / 255.0, 0.0).into()),
+				// w, not a color
+				None,
+				None
+			]);
+		}
+
+		let mesh = MeshData::from_obj(parse(source.as_bytes(), 1).unwrap());
+		assert!(mesh.vertex_colors);
+		assert_eq!(mesh.vertices[1].color(), (0.0, 0.5, 1.0).into());
+		assert_eq!(mesh.vertices[4].color(), (1.0, 1.0, 1.0).into());
+		assert!(!MeshData::from_obj(parse(CUBE.as_bytes(), 1).unwrap()).vertex_colors);
+
+		// the colors survive being written out
+		let mut bytes = Vec::new();
+		write_to(&mut bytes, &mesh, &math::matrix::Matrix4::new_identity(), &[], &WriteOptions::default()).unwrap();
+		let loaded = parse(&bytes, 1).unwrap();
+		let colors: Vec<_> = mesh.vertices.iter().map(|v| Some(v.color())).collect();
+		assert_eq!(loaded.colors, colors);
+
+		for source in ["v 0 0 0\nv 0 0 0 1 1\n", "v 0 0 0\nv 0 0 0 1 1 1 1 1\n"]
+		{
+			match parse(source.as_bytes(), 1)
+			{
+				Err(Error::Parse { line, .. }) => assert_eq!(line, 2),
+				_ => panic!("expected a parse error")
+			}
+		}
+	}
+
+	#[test]
+	fn lines_and_points()
+	{
+		let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nl 1 2/1 3\np 4\nl -2 -1\np 1 -3\nf 1 2 3\n";
+		for chunks in 1..6
+		{
+			let data = parse(source.as_bytes(), chunks).unwrap();
+			assert_eq!(data.lines, vec![0, 1, 1, 2, 2, 3]);
+			assert_eq!(data.points, vec![3, 0, 1]);
+			assert_eq!(data.indices.len(), 3);
+		}
+
+		let mesh = MeshData::from_obj(parse(source.as_bytes(), 1).unwrap());
+		assert_eq!(mesh.lines, vec![0, 1, 1, 2, 2, 3]);
+		for relative_indices in [false, true]
+		{
+			let mut bytes = Vec::new();
+			let options = WriteOptions { relative_indices, ..Default::default() };
+			write_to(&mut bytes, &mesh, &math::matrix::Matrix4::new_identity(), &[], &options).unwrap();
+			let loaded = parse(&bytes, 2).unwrap();
+			assert_eq!(loaded.lines, mesh.lines);
+			assert_eq!(loaded.points, mesh.points);
+		}
+
+		for bad in ["v 0 0 0\nl 1\n", "v 0 0 0\np\n"]
+		{
+			assert!(matches!(parse(bad.as_bytes(), 1), Err(Error::Parse { line: 2, .. })));
+		}
+	}
+
+	#[test]
+	fn reports_line_of_error()
+	{
+		let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 0\n";
+		for chunks in 1..4
+		{
+			match parse(source.as_bytes(), chunks)
+			{
+				Err(Error::Parse { line, .. }) => assert_eq!(line, 4),
+				_ => panic!("expected a parse error")
+			}
+		}
+	}
+
+	// the same triangle and line written in all the ways the tokenizer has to cope with
+	const CORPUS: [(&str, &str); 10] = [
+		("plain", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nl 1 2\n"),
+		("tabs", "v\t0\t0\t0\nv 1\t 0 0\n\tv 0 1 0\nf\t1 2\t3\t\nl 1 2\n"),
+		("crlf", "v 0 0 0\r\nv 1 0 0\r\nv 0 1 0\r\nf 1 2 3\r\nl 1 2\r\n"),
+		("no final newline", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nl 1 2"),
+		("continuation", "v 0 0 0\nv 1 \\\n0 0\nv 0 1 0\nf 1 \\\n 2 \\\n\t3\nl 1 2\n"),
+		("continuation crlf", "v 0 0 0\r\nv 1 0 0\r\nv 0 1 0\r\nf 1 2\\\r\n3\r\nl 1 2\r\n"),
+		("comments", "# a triangle\nv 0 0 0 # origin\nv 1 0 0#x\n  # indented\nv 0 1 0\nf 1 2 3 # face\nl 1 2\n#"),
+		("blank lines", "\n\nv 0 0 0\n   \nv 1 0 0\n\t\nv 0 1 0\n\r\nf 1 2 3\n\nl 1 2\n\n"),
+		("1d texcoords", "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0\nvt 1\nvt 0.5\nf 1 2 3\nl 1 2\n"),
+		("3d texcoords", "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0 0\nvt 1 0 0.5\nvt 0 1\nf 1 2 3\nl 1 2\n")
+	];
+
+	#[test]
+	fn tokenizer_corpus()
+	{
+		let reference = parse(CORPUS[0].1.as_bytes(), 1).unwrap();
+		assert_eq!(reference.indices.len(), 3);
+		assert_eq!(reference.lines, vec![0, 1]);
+
+		for (name, source) in CORPUS
+		{
+			for chunks in 1..6
+			{
+				let data = parse(source.as_bytes(), chunks).unwrap_or_else(|e| panic!("{}: {:?}", name, e));
+				assert_eq!(data.positions, reference.positions, "{}", name);
+				assert_eq!(data.indices, reference.indices, "{}", name);
+				assert_eq!(data.lines, reference.lines, "{}", name);
+				assert!(data.warnings.is_empty(), "{}: {:?}", name, data.warnings);
+			}
+		}
+	}
+
+	#[test]
+	fn texcoords()
+	{
+		let data = parse(CORPUS[8].1.as_bytes(), 1).unwrap();
+		assert_eq!(data.texcoords, vec![(0.0, 0.0).into(), (1.0, 0.0).into(), (0.5, 0.0).into()]);
+		let data = parse(CORPUS[9].1.as_bytes(), 1).unwrap();
+		assert_eq!(data.texcoords, vec![(0.0, 0.0).into(), (1.0, 0.0).into(), (0.0, 1.0).into()]);
+
+		for source in ["vt\n", "vt 0 0 0 0\n", "vt 0 x\n"]
+		{
+			assert!(matches!(parse(source.as_bytes(), 1), Err(Error::Parse { line: 1, .. })), "{}", source);
+		}
+	}
+
+	// standard statements that aren't supported, next to ones that aren't obj at all
+	const UNSUPPORTED_CORPUS: [(&str, &str); 3] = [
+		("display attributes", "lod 2
+v 0 0 0
+v 1 0 0
+shadow_obj shadow.obj
+v 0 1 0
+bevel on
+c_interp off
+d_interp off
+trace_obj trace.obj
+f 1 2 3
+"),
+		("texture maps", "maplib tiles.mpc
+usemap tiles
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+usemap off
+"),
+		("free-form", "v 0 0 0
+v 1 0 0
+v 0 1 0
+vp 0.5
+mg 1 0.5
+ctech cparm 1
+stech cparma 1 1
+curv 0 1 1 2
+f 1 2 3
+")
+	];
+
+	#[test]
+	fn unsupported_statements_are_warnings()
+	{
+		for (name, source) in UNSUPPORTED_CORPUS
+		{
+			let data = parse(source.as_bytes(), 1).unwrap();
+			assert_eq!(data.indices.len(), 3, "{}", name);
+			assert!(!data.warnings.is_empty(), "{}", name);
+			assert!(data.warnings.iter().all(|w| w.message.starts_with("skipped unsupported statement '")), "{}: {:?}", name, data.warnings);
+		}
+
+		let data = parse(b"v 0 0 0
+lod 1
+lodd 1
+usemap off
+usemtl2 x
+", 1).unwrap();
+		assert_eq!(data.warnings, vec![
+			Warning { line: 2, message: "skipped unsupported statement 'lod'".into(), repeats: 0 },
+			Warning { line: 3, message: "skipped unknown statement 'lodd'".into(), repeats: 0 },
+			Warning { line: 4, message: "skipped unsupported statement 'usemap'".into(), repeats: 0 },
+			Warning { line: 5, message: "skipped unknown statement 'usemtl2'".into(), repeats: 0 }
+		]);
+	}
+
+	#[test]
+	fn unknown_statements_are_warnings()
+	{
+		let source = "v 0 0 0\nfoo 1\nv 1 0 0\ns 1\nv 0 1 0\nbar\nfoo 2\nf 1 2 3\nfoo 3 \\\n4\nv 1 1 0\nf 2 3 x\n";
+		for chunks in 1..8
+		{
+			// the continued foo still counts both lines it spans
+			match parse(source.as_bytes(), chunks)
+			{
+				Err(Error::Parse { line, .. }) => assert_eq!(line, 12),
+				_ => panic!("expected a parse error")
+			}
+
+			let data = parse(source.strip_suffix("f 2 3 x\n").unwrap().as_bytes(), chunks).unwrap();
+			assert_eq!(data.indices.len(), 3);
+			assert_eq!(data.positions.len(), 4);
+			assert_eq!(data.warnings, vec![
+				Warning { line: 2, message: "skipped unknown statement 'foo'".into(), repeats: 2 },
+				Warning { line: 6, message: "skipped unknown statement 'bar'".into(), repeats: 0 }
+			]);
+		}
+	}
+
+	#[test]
+	fn bezier_surfaces()
+	{
+		// a bilinear patch, and a surface of two spans in u sharing its middle column (relative indices)
+		let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 1\n\
+			cstype bezier\ndeg 1 1\nsurf 0 1 0 1 1 2 3 4\nend\n\
+			v 0 0 2\nv 1 0 2\nv 2 0 2\nv 0 1 2\nv 1 1 2\nv 2 1 2\n\
+			surf 0 1 0 1 -6 -5 -4 -3 -2 -1\nparm u 0 0.25 1\nparm v 0 1\nend\n\
+			cstype bspline\nsurf 0 1 0 1 1 2 3 4\nend\n";
+
+		for chunks in 1..4
+		{
+			let data = parse(source.as_bytes(), chunks).unwrap();
+			assert_eq!(data.surfaces.len(), 2);
+			assert_eq!(data.surfaces[0].control, vec![0, 1, 2, 3]);
+			assert_eq!(data.surfaces[1].control, vec![4, 5, 6, 7, 8, 9]);
+
+			let patches = data.surfaces[1].patches(&data.positions);
+			assert_eq!(patches.len(), 2);
+			assert_eq!(patches[1].points, vec![(1.0, 0.0, 2.0).into(), (2.0, 0.0, 2.0).into(), (1.0, 1.0, 2.0).into(), (2.0, 1.0, 2.0).into()]);
+			assert_eq!(patches[1].uv_range, [(0.25, 0.0).into(), (1.0, 1.0).into()]);
+		}
+
+		let mesh = MeshData::from_obj(parse(source.as_bytes(), 1).unwrap());
+		assert_eq!(mesh.patches.len(), 3);
+		assert_eq!(mesh.patches[0].evaluate(0.5, 0.5).0, (0.5, 0.5, 0.25).into());
+
+		// 3 control points for a bilinear patch
+		match parse(b"v 0 0 0\ncstype bezier\ndeg 1 1\nsurf 0 1 0 1 1 1 1\nend\n", 1)
+		{
+			Err(Error::Parse { line, .. }) => assert_eq!(line, 4),
+			other => panic!("expected a parse error, got {:?}", other)
+		}
+
+		// errors instead of overflowing when the control points are counted
+		for source in ["v 0 0 0\ncstype bezier\ndeg 18446744073709551615 1\nsurf 0 1 0 1 1 1 1\nend\n", "v 0 0 0\ncstype bezier\ndeg 33 1\nsurf 0 1 0 1 1 1 1\nend\n"]
+		{
+			match parse(source.as_bytes(), 1)
+			{
+				Err(Error::Parse { line, .. }) => assert_eq!(line, 4),
+				other => panic!("expected a parse error, got {:?}", other)
+			}
+		}
+		let surface = ObjSurface { degree: [usize::MAX, 2], parameters: [vec![0.0, 1.0, 2.0], vec![0.0, 1.0]], control: Vec::new() };
+		assert_eq!(surface.control_size(), None);
+		assert!(surface.patches(&[]).is_empty());
+	}
+
+	// The line-by-line loader this module replaced, kept to compare against
+	fn parse_lines(path: &Path) -> (Vec<math::vector::Vector3>, Vec<u32>)
+	{
+		let mut positions = Vec::new();
+		let mut indices = Vec::new();
+		let file = fs::File::open(path).unwrap();
+
+		for line in io::BufReader::new(file).lines()
+		{
+			let line = line.unwrap();
+			let arr: Vec<&str> = line.split(" ").filter(|s| !s.is_empty()).collect();
+			if arr.is_empty()
+			{
+				continue;
+			}
+			if arr[0] == "v"
+			{
+				positions.push(math::vector::Vector3::new(arr[1].parse().unwrap(), arr[2].parse().unwrap(), arr[3].parse().unwrap()));
+			}
+			else if arr[0] == "f"
+			{
+				let corners: Vec<u32> = arr[1..].iter().map(|s| s.split("/").next().unwrap().parse().unwrap()).collect();
+				indices.extend_from_slice(&[corners[0] - 1, corners[1] - 1, corners[2] - 1]);
+			}
+		}
+
+		(positions, indices)
+	}
+
+	// cargo test --release -- --ignored --nocapture bench_
+	#[test]
+	#[ignore]
+	fn bench_million_faces()
+	{
+		let path = std::env::temp_dir().join("scop_bench_1m.obj");
+		{
+			let mut file = io::BufWriter::new(fs::File::create(&path).unwrap());
+			let side = 708; // 707 * 707 * 2 ~= 1M triangles
+			for y in 0..side
+			{
+				for x in 0..side
+				{
+					writeln!(file, "v {} {} {}", x as f32 * 0.1, y as f32 * 0.1, ((x * y) % 17) as f32 * 0.01).unwrap();
+				}
+			}
+			for y in 0..side - 1
+			{
+				for x in 0..side - 1
+				{
+					let i = y * side + x + 1;
+					writeln!(file, "f {} {} {}", i, i + 1, i + side).unwrap();
+					writeln!(file, "f {} {} {}", i + 1, i + side + 1, i + side).unwrap();
+				}
+			}
+		}
+
+		let start = Instant::now();
+		let (positions, indices) = parse_lines(&path);
+		let lines_time = start.elapsed();
+
+		let start = Instant::now();
+		let data = load(&path).unwrap();
+		let chunked_time = start.elapsed();
+
+		assert_eq!(positions, data.positions);
+		assert_eq!(indices, data.indices.iter().map(|i| i.position).collect::<Vec<u32>>());
+
+		println!("{} faces: lines() {:?}, chunked {:?}", indices.len() / 3, lines_time, chunked_time);
+		fs::remove_file(&path).unwrap();
+	}
+}
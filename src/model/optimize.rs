@@ -0,0 +1,541 @@
+// Mesh processing passes that don't change how a mesh looks, only how fast it draws:
+// welding near-duplicate vertices, reordering triangles so the gpu's post-transform cache
+// gets more hits (Tom Forsyth's "Linear-speed vertex cache optimisation"), and reordering
+// vertices so they are fetched in the order they are used.
+//
+// Triangles only move within their submesh, so submesh ranges stay valid.
+
+use std::collections::HashMap;
+use crate::model::{Bounds, MeshData, Vertex};
+
+// size of the lru cache the triangle order is optimized for
+const CACHE_SIZE: usize = 32;
+// size of the fifo cache ACMR is measured with, about what real hardware has
+pub const MEASURE_CACHE_SIZE: usize = 16;
+
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+// how close normals and texcoords have to be for WeldMode::KeepSeams
+const ATTRIBUTE_TOLERANCE: f32 = 1e-4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WeldMode
+{
+	// merge everything within epsilon, hard edges and uv seams are lost
+	PositionOnly,
+	// only merge vertices whose normals and texcoords match too
+	KeepSeams,
+}
+
+// average cache miss ratio, before and after optimize()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stats
+{
+	pub acmr_before: f32,
+	pub acmr_after: f32
+}
+
+// Merges vertices that are less than `epsilon` apart (the first one is kept), drops the triangles that
+// collapse because of it and returns how many vertices were removed
+pub fn weld(data: &mut MeshData, epsilon: f32, mode: WeldMode) -> usize
+{
+	let epsilon = epsilon.max(0.0);
+	let cell_size = if epsilon > 0.0 { epsilon } else { 1.0 };
+	let cell = |v: &Vertex| -> [i64; 3] {
+		let p = v.position();
+		[(p.x() / cell_size).floor() as i64, (p.y() / cell_size).floor() as i64, (p.z() / cell_size).floor() as i64]
+	};
+
+	let mut grid = HashMap::<[i64; 3], Vec<u32>>::new();
+	let mut welded = Vec::<Vertex>::with_capacity(data.vertices.len());
+	let mut remap = Vec::<u32>::with_capacity(data.vertices.len());
+
+	for vertex in &data.vertices
+	{
+		let [x, y, z] = cell(vertex);
+		let mut found = None;
+
+		// anything within epsilon is in this cell or one of its neighbours
+		'search: for dx in -1..=1
+		{
+			for dy in -1..=1
+			{
+				for dz in -1..=1
+				{
+					for &candidate in grid.get(&[x + dx, y + dy, z + dz]).map(Vec::as_slice).unwrap_or(&[])
+					{
+						if can_weld(&welded[candidate as usize], vertex, epsilon, mode)
+						{
+							found = Some(candidate);
+							break 'search;
+						}
+					}
+				}
+			}
+		}
+
+		let index = match found
+		{
+			Some(index) => index,
+			None => {
+				welded.push(*vertex);
+				let index = (welded.len() - 1) as u32;
+				grid.entry([x, y, z]).or_default().push(index);
+				index
+			}
+		};
+		remap.push(index);
+	}
+
+	let removed = data.vertices.len() - welded.len();
+	for index in data.indices.iter_mut().chain(&mut data.lines).chain(&mut data.points)
+	{
+		// out of range indices stay out of range
+		*index = remap.get(*index as usize).copied().unwrap_or(u32::MAX);
+	}
+	data.vertices = welded;
+	data.bounds = Bounds::from_vertices(&data.vertices);
+	retain_triangles(data, |t| t[0] != t[1] && t[1] != t[2] && t[0] != t[2]);
+
+	removed
+}
+
+fn can_weld(a: &Vertex, b: &Vertex, epsilon: f32, mode: WeldMode) -> bool
+{
+	if (a.position() - b.position()).length() > epsilon
+	{
+		return false;
+	}
+
+	match mode
+	{
+		WeldMode::PositionOnly => true,
+		WeldMode::KeepSeams => {
+			(a.normal() - b.normal()).length() <= ATTRIBUTE_TOLERANCE
+				&& (a.texcoord() - b.texcoord()).length() <= ATTRIBUTE_TOLERANCE
+		}
+	}
+}
+
+// Removes triangles `keep` returns false for, and shrinks the submeshes to match
+fn retain_triangles(data: &mut MeshData, mut keep: impl FnMut(&[u32]) -> bool)
+{
+	let mut indices = Vec::with_capacity(data.indices.len());
+
+	for submesh in data.submeshes.iter_mut()
+	{
+		let (first, end) = submesh_range(submesh.first, submesh.count, data.indices.len());
+		let new_first = indices.len();
+		for triangle in data.indices[first..end].chunks_exact(3)
+		{
+			if keep(triangle)
+			{
+				indices.extend_from_slice(triangle);
+			}
+		}
+		submesh.first = new_first as u32;
+		submesh.count = (indices.len() - new_first) as u32;
+	}
+
+	data.indices = indices;
+}
+
+fn submesh_range(first: u32, count: u32, len: usize) -> (usize, usize)
+{
+	let first = (first as usize).min(len);
+	let end = (first + count as usize).min(len);
+
+	(first, first + (end - first) / 3 * 3)
+}
+
+// Reorders triangles for the vertex cache, then vertices for fetching, and reports the ACMR before and after
+pub fn optimize(data: &mut MeshData) -> Stats
+{
+	let acmr_before = acmr(&data.indices, data.vertices.len(), MEASURE_CACHE_SIZE);
+
+	for submesh in &data.submeshes
+	{
+		let (first, end) = submesh_range(submesh.first, submesh.count, data.indices.len());
+		optimize_vertex_cache(&mut data.indices[first..end]);
+	}
+	optimize_vertex_fetch(data);
+
+	Stats {
+		acmr_before,
+		acmr_after: acmr(&data.indices, data.vertices.len(), MEASURE_CACHE_SIZE)
+	}
+}
+
+// Average cache miss ratio: vertex shader runs per triangle, with a fifo cache of `cache_size` entries.
+// 3.0 is no reuse at all, 0.5 is the best a big regular grid can get
+pub fn acmr(indices: &[u32], vertex_count: usize, cache_size: usize) -> f32
+{
+	let triangles = indices.len() / 3;
+	if triangles == 0
+	{
+		return 0.0;
+	}
+
+	// a vertex is still in the fifo if fewer than cache_size misses happened since it was added
+	let mut added_at = vec![usize::MAX; vertex_count];
+	let mut misses = 0;
+
+	for &index in &indices[..triangles * 3]
+	{
+		let slot = match added_at.get_mut(index as usize)
+		{
+			Some(slot) => slot,
+			None => continue
+		};
+		if *slot == usize::MAX || misses - *slot >= cache_size
+		{
+			*slot = misses;
+			misses += 1;
+		}
+	}
+
+	misses as f32 / triangles as f32
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32
+{
+	if remaining == 0
+	{
+		// nothing left to draw with it
+		return -1.0;
+	}
+
+	let cache_score = match cache_position
+	{
+		None => 0.0,
+		// the last triangle's vertices get a fixed score, so the next one doesn't just reuse its edge forever
+		Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+		Some(position) => {
+			let scaler = 1.0 / (CACHE_SIZE - 3) as f32;
+			(1.0 - (position - 3) as f32 * scaler).powf(CACHE_DECAY_POWER)
+		}
+	};
+
+	// vertices with few triangles left are finished first, so they don't linger as lone triangles
+	cache_score + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+// Forsyth's greedy algorithm: always draw the triangle whose vertices score best,
+// only looking at triangles around the vertices currently in the simulated cache
+pub fn optimize_vertex_cache(indices: &mut [u32])
+{
+	let triangle_count = indices.len() / 3;
+	if triangle_count < 2
+	{
+		return;
+	}
+
+	// local vertex ids, so the work is proportional to this range and not the whole mesh
+	let mut local_ids = HashMap::<u32, usize>::new();
+	let local: Vec<usize> = indices[..triangle_count * 3]
+		.iter()
+		.map(|&index| {
+			let next = local_ids.len();
+			*local_ids.entry(index).or_insert(next)
+		})
+		.collect();
+	let vertex_count = local_ids.len();
+
+	// triangles around each vertex, flattened: vertex v owns adjacency[offsets[v]..offsets[v] + remaining[v]]
+	let mut remaining = vec![0usize; vertex_count];
+	for &v in &local
+	{
+		remaining[v] += 1;
+	}
+	let mut offsets = vec![0usize; vertex_count];
+	for v in 1..vertex_count
+	{
+		offsets[v] = offsets[v - 1] + remaining[v - 1];
+	}
+	let mut adjacency = vec![0usize; local.len()];
+	let mut filled = vec![0usize; vertex_count];
+	for (i, &v) in local.iter().enumerate()
+	{
+		adjacency[offsets[v] + filled[v]] = i / 3;
+		filled[v] += 1;
+	}
+
+	let mut cache_position = vec![None::<usize>; vertex_count];
+	let mut scores: Vec<f32> = (0..vertex_count).map(|v| vertex_score(None, remaining[v])).collect();
+	let mut triangle_scores: Vec<f32> = (0..triangle_count)
+		.map(|t| scores[local[t * 3]] + scores[local[t * 3 + 1]] + scores[local[t * 3 + 2]])
+		.collect();
+	let mut emitted = vec![false; triangle_count];
+
+	let mut order = Vec::with_capacity(triangle_count);
+	let mut cache = Vec::<usize>::with_capacity(CACHE_SIZE + 3);
+	let mut next_unemitted = 0;
+	let mut best = best_triangle(&triangle_scores, 0..triangle_count, &emitted);
+
+	while let Some(triangle) = best
+	{
+		order.push(triangle);
+		emitted[triangle] = true;
+		let corners = [local[triangle * 3], local[triangle * 3 + 1], local[triangle * 3 + 2]];
+
+		for &v in &corners
+		{
+			// remove the triangle from the vertex's list
+			let list = &mut adjacency[offsets[v]..offsets[v] + remaining[v]];
+			if let Some(pos) = list.iter().position(|&t| t == triangle)
+			{
+				let last = list.len() - 1;
+				list.swap(pos, last);
+			}
+			remaining[v] -= 1;
+		}
+
+		// the triangle's vertices move to the front of the lru cache
+		let mut new_cache = Vec::with_capacity(CACHE_SIZE + 3);
+		for &v in &corners
+		{
+			if !new_cache.contains(&v)
+			{
+				new_cache.push(v);
+			}
+		}
+		new_cache.extend(cache.iter().copied().filter(|v| !corners.contains(v)));
+
+		for (position, &v) in new_cache.iter().enumerate()
+		{
+			cache_position[v] = if position < CACHE_SIZE { Some(position) } else { None };
+			scores[v] = vertex_score(cache_position[v], remaining[v]);
+		}
+		new_cache.truncate(CACHE_SIZE);
+		cache = new_cache;
+
+		// only triangles around cached vertices changed score, the best next one is among them
+		best = None;
+		let mut best_score = -1.0;
+		for &v in &cache
+		{
+			for &t in &adjacency[offsets[v]..offsets[v] + remaining[v]]
+			{
+				let score = scores[local[t * 3]] + scores[local[t * 3 + 1]] + scores[local[t * 3 + 2]];
+				triangle_scores[t] = score;
+				if score > best_score
+				{
+					best_score = score;
+					best = Some(t);
+				}
+			}
+		}
+
+		if best.is_none()
+		{
+			// the cache ran dry (end of a disconnected piece), start over at the first triangle left.
+			// Scanning all of them for the best score would make a soup of unconnected triangles quadratic
+			while next_unemitted < triangle_count && emitted[next_unemitted]
+			{
+				next_unemitted += 1;
+			}
+			best = (next_unemitted < triangle_count).then_some(next_unemitted);
+		}
+	}
+
+	let source: Vec<u32> = indices[..triangle_count * 3].to_vec();
+	for (i, &triangle) in order.iter().enumerate()
+	{
+		indices[i * 3..i * 3 + 3].copy_from_slice(&source[triangle * 3..triangle * 3 + 3]);
+	}
+}
+
+fn best_triangle(scores: &[f32], range: std::ops::Range<usize>, emitted: &[bool]) -> Option<usize>
+{
+	range
+		.filter(|&t| !emitted[t])
+		.max_by(|&a, &b| scores[a].total_cmp(&scores[b]))
+}
+
+// Renumbers vertices in the order the index buffer first uses them, so fetching them walks memory forwards.
+// Vertices nothing uses are moved to the end
+pub fn optimize_vertex_fetch(data: &mut MeshData)
+{
+	let mut remap = vec![u32::MAX; data.vertices.len()];
+	let mut order = Vec::with_capacity(data.vertices.len());
+
+	for index in data.indices.iter_mut()
+	{
+		let slot = match remap.get_mut(*index as usize)
+		{
+			Some(slot) => slot,
+			None => continue
+		};
+		if *slot == u32::MAX
+		{
+			*slot = order.len() as u32;
+			order.push(*index as usize);
+		}
+		*index = *slot;
+	}
+
+	for (vertex, slot) in remap.iter_mut().enumerate()
+	{
+		if *slot == u32::MAX
+		{
+			*slot = order.len() as u32;
+			order.push(vertex);
+		}
+	}
+
+	// lines and points only use vertices, their order doesn't matter
+	for index in data.lines.iter_mut().chain(&mut data.points)
+	{
+		*index = remap.get(*index as usize).copied().unwrap_or(u32::MAX);
+	}
+	data.vertices = order.into_iter().map(|v| data.vertices[v]).collect();
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::model::SubMesh;
+	use rand::seq::SliceRandom;
+	use rand::SeedableRng;
+
+	// n x n quads, two triangles each, in shuffled order
+	fn grid(n: u32) -> MeshData
+	{
+		let mut vertices = Vec::new();
+		for y in 0..=n
+		{
+			for x in 0..=n
+			{
+				vertices.push(Vertex::new((x as f32, y as f32, 0.0).into(), (1.0, 1.0, 1.0).into(), (0.0, 0.0).into()));
+			}
+		}
+
+		let mut triangles = Vec::new();
+		for y in 0..n
+		{
+			for x in 0..n
+			{
+				let i = y * (n + 1) + x;
+				triangles.push([i, i + 1, i + n + 2]);
+				triangles.push([i, i + n + 2, i + n + 1]);
+			}
+		}
+		triangles.shuffle(&mut rand::rngs::StdRng::seed_from_u64(42));
+
+		MeshData::new(vertices, triangles.concat())
+	}
+
+	fn sorted_triangles(data: &MeshData) -> Vec<[[u32; 3]; 3]>
+	{
+		// triangles by their corner positions, so they can be compared after vertices were renumbered
+		let key = |i: u32| {
+			let p = data.vertices[i as usize].position();
+			[p.x().to_bits(), p.y().to_bits(), p.z().to_bits()]
+		};
+		let mut triangles: Vec<_> = data.indices.chunks_exact(3).map(|t| [key(t[0]), key(t[1]), key(t[2])]).collect();
+		triangles.sort();
+		triangles
+	}
+
+	#[test]
+	fn acmr_of_known_orders()
+	{
+		// one triangle, every vertex missed once
+		assert_eq!(acmr(&[0, 1, 2], 3, 16), 3.0);
+		// second triangle shares an edge, only one new vertex
+		assert_eq!(acmr(&[0, 1, 2, 2, 1, 3], 4, 16), 2.0);
+		// cache of 3 forgets 0 by the time it comes back
+		assert_eq!(acmr(&[0, 1, 2, 3, 4, 5, 0, 1, 2], 6, 3), 3.0);
+	}
+
+	#[test]
+	fn optimize_improves_acmr_and_keeps_triangles()
+	{
+		let mut data = grid(32);
+		let before = sorted_triangles(&data);
+
+		let stats = optimize(&mut data);
+		assert!(stats.acmr_before > 2.0, "{:?}", stats);
+		assert!(stats.acmr_after < 0.9, "{:?}", stats);
+		assert_eq!(stats.acmr_after, acmr(&data.indices, data.vertices.len(), MEASURE_CACHE_SIZE));
+		assert_eq!(sorted_triangles(&data), before);
+
+		// vertices come in the order they are first used
+		let mut next = 0;
+		for &index in &data.indices
+		{
+			assert!(index <= next);
+			if index == next
+			{
+				next += 1;
+			}
+		}
+	}
+
+	#[test]
+	fn triangle_soup_is_linear()
+	{
+		// what stl files turn into, no triangle shares a vertex with another
+		let count = 100_000;
+		let mut indices: Vec<u32> = (0..count * 3).collect();
+
+		let start = std::time::Instant::now();
+		optimize_vertex_cache(&mut indices);
+		// a quadratic restart takes minutes here, even in release
+		assert!(start.elapsed() < std::time::Duration::from_secs(10), "{:?}", start.elapsed());
+
+		let mut sorted = indices.clone();
+		sorted.sort_unstable();
+		assert_eq!(sorted, (0..count * 3).collect::<Vec<u32>>());
+	}
+
+	#[test]
+	fn triangles_stay_in_their_submesh()
+	{
+		let mut data = grid(8);
+		let half = (data.indices.len() / 2) as u32;
+		data.submeshes = vec![
+			SubMesh { name: "a".into(), material: None, first: 0, count: half },
+			SubMesh { name: "b".into(), material: None, first: half, count: half }
+		];
+		let mut first_half = data.clone();
+		first_half.indices.truncate(half as usize);
+		let before = sorted_triangles(&first_half);
+
+		optimize(&mut data);
+		data.indices.truncate(half as usize);
+		assert_eq!(sorted_triangles(&data), before);
+	}
+
+	#[test]
+	fn weld_closes_cracks_and_keeps_seams()
+	{
+		let vertex = |p: (f32, f32, f32), uv: (f32, f32)| Vertex::new(p.into(), (1.0, 1.0, 1.0).into(), uv.into());
+		// two triangles sharing the 1-2 edge, but the second has its own slightly moved copies
+		let vertices = vec![
+			vertex((0.0, 0.0, 0.0), (0.0, 0.0)),
+			vertex((1.0, 0.0, 0.0), (1.0, 0.0)),
+			vertex((0.0, 1.0, 0.0), (0.0, 1.0)),
+			vertex((1.00001, 0.0, 0.0), (1.0, 0.0)),
+			vertex((1.0, 1.0, 0.0), (1.0, 1.0)),
+			vertex((0.0, 0.99999, 0.0), (0.5, 1.0)),
+			// collapses onto vertex 0
+			vertex((0.00001, 0.0, 0.0), (0.0, 0.0))
+		];
+		let indices = vec![0, 1, 2, 3, 4, 5, 0, 6, 1];
+
+		let mut data = MeshData::new(vertices.clone(), indices.clone());
+		assert_eq!(weld(&mut data, 1e-3, WeldMode::PositionOnly), 3);
+		assert_eq!(data.indices, vec![0, 1, 2, 1, 3, 2]);
+		assert_eq!(data.submeshes[0].count, 6);
+
+		// vertex 5 has a different uv, so it stays a separate vertex
+		let mut data = MeshData::new(vertices, indices);
+		assert_eq!(weld(&mut data, 1e-3, WeldMode::KeepSeams), 2);
+		assert_eq!(data.indices, vec![0, 1, 2, 1, 3, 4]);
+	}
+}
@@ -0,0 +1,656 @@
+use math;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+use crate::model::{self, MeshData, Vertex};
+
+#[derive(Debug)]
+pub enum Error
+{
+	Io(io::Error),
+	Header {
+		line: usize,
+		message: String
+	},
+	Data {
+		element: String,
+		index: usize,
+		message: String
+	}
+}
+
+impl From<io::Error> for Error
+{
+	fn from(other: io::Error) -> Self
+	{
+		Error::Io(other)
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format
+{
+	Ascii,
+	BinaryLittleEndian,
+	BinaryBigEndian
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ScalarType
+{
+	I8,
+	U8,
+	I16,
+	U16,
+	I32,
+	U32,
+	F32,
+	F64
+}
+
+impl ScalarType
+{
+	fn from_name(name: &str) -> Option<Self>
+	{
+		match name
+		{
+			"char" | "int8" => Some(ScalarType::I8),
+			"uchar" | "uint8" => Some(ScalarType::U8),
+			"short" | "int16" => Some(ScalarType::I16),
+			"ushort" | "uint16" => Some(ScalarType::U16),
+			"int" | "int32" => Some(ScalarType::I32),
+			"uint" | "uint32" => Some(ScalarType::U32),
+			"float" | "float32" => Some(ScalarType::F32),
+			"double" | "float64" => Some(ScalarType::F64),
+			_ => None
+		}
+	}
+
+	fn size(&self) -> usize
+	{
+		match self
+		{
+			ScalarType::I8 | ScalarType::U8 => 1,
+			ScalarType::I16 | ScalarType::U16 => 2,
+			ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+			ScalarType::F64 => 8
+		}
+	}
+
+	// integer colors go from 0 to the type's max, float colors from 0 to 1
+	fn color_scale(&self) -> f64
+	{
+		match self
+		{
+			ScalarType::I8 => i8::MAX as f64,
+			ScalarType::U8 => u8::MAX as f64,
+			ScalarType::I16 => i16::MAX as f64,
+			ScalarType::U16 => u16::MAX as f64,
+			ScalarType::I32 => i32::MAX as f64,
+			ScalarType::U32 => u32::MAX as f64,
+			ScalarType::F32 | ScalarType::F64 => 1.0
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+enum PropertyKind
+{
+	Scalar(ScalarType),
+	List {
+		count: ScalarType,
+		item: ScalarType
+	}
+}
+
+#[derive(Clone, Debug)]
+struct Property
+{
+	name: String,
+	kind: PropertyKind
+}
+
+#[derive(Clone, Debug)]
+struct Element
+{
+	name: String,
+	count: usize,
+	properties: Vec<Property>
+}
+
+impl Element
+{
+	// the fewest bytes a record can take: lists can be empty, an ascii value is at least a digit and a space
+	fn min_size(&self, format: Format) -> usize
+	{
+		let size: usize = self.properties
+			.iter()
+			.map(|property| match (format, property.kind)
+			{
+				(Format::Ascii, _) => 2,
+				(_, PropertyKind::Scalar(ty)) => ty.size(),
+				(_, PropertyKind::List { count, .. }) => count.size()
+			})
+			.sum();
+		size.max(1)
+	}
+
+	// the count comes from the header, this is how many records the body can actually hold
+	fn capacity(&self, format: Format, body_size: usize) -> usize
+	{
+		self.count.min(body_size / self.min_size(format))
+	}
+}
+
+struct Header
+{
+	format: Format,
+	elements: Vec<Element>,
+	// where the element data starts
+	body_offset: usize
+}
+
+pub fn load<T>(filename: T) -> Result<MeshData, Error>
+where T: AsRef<Path>
+{
+	from_bytes(&fs::read(filename)?)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<MeshData, Error>
+{
+	let header = parse_header(bytes)?;
+	let data = &bytes[header.body_offset..];
+
+	let mut body = match header.format
+	{
+		Format::Ascii => {
+			let text = str::from_utf8(data).map_err(|_| Error::Data {
+				element: String::new(),
+				index: 0,
+				message: "ascii body is not valid utf-8".into()
+			})?;
+			Body::Ascii(text.split_ascii_whitespace())
+		},
+		Format::BinaryLittleEndian => Body::Binary { bytes: data, pos: 0, big_endian: false },
+		Format::BinaryBigEndian => Body::Binary { bytes: data, pos: 0, big_endian: true }
+	};
+
+	let mut vertices = Vec::<Vertex>::new();
+	let mut indices = Vec::<u32>::new();
+	let mut has_normals = false;
+	let mut has_colors = false;
+	let mut has_texcoords = false;
+
+	for element in &header.elements
+	{
+		let error = |index: usize, message: String| Error::Data { element: element.name.clone(), index, message };
+
+		match element.name.as_str()
+		{
+			"vertex" => {
+				let slots: Vec<Option<Slot>> = element.properties.iter().map(Slot::for_property).collect();
+				has_normals = slots.iter().any(|s| matches!(s, Some(Slot::Normal(_))));
+				has_colors = slots.iter().any(|s| matches!(s, Some(Slot::Color(_))));
+				has_texcoords = slots.iter().any(|s| matches!(s, Some(Slot::Texcoord(_))));
+
+				vertices.reserve(element.capacity(header.format, data.len()));
+				for i in 0..element.count
+				{
+					let vertex = read_vertex(&mut body, element, &slots).map_err(|e| error(i, e))?;
+					vertices.push(vertex);
+				}
+			},
+			"face" => {
+				// at least one triangle each
+				indices.reserve(element.capacity(header.format, data.len()) * 3);
+				let mut corners = Vec::<u32>::new();
+				for i in 0..element.count
+				{
+					read_face(&mut body, element, &mut corners).map_err(|e| error(i, e))?;
+					if corners.len() < 3
+					{
+						return Err(error(i, "face needs at least 3 vertices".into()));
+					}
+
+					// fan triangulation, same as the obj loader
+					for j in 1..corners.len() - 1
+					{
+						indices.extend_from_slice(&[corners[0], corners[j], corners[j + 1]]);
+					}
+				}
+			},
+			// everything else (edges, materials, ...) still has to be read to get to the next element
+			_ => {
+				for i in 0..element.count
+				{
+					for property in &element.properties
+					{
+						skip_property(&mut body, property).map_err(|e| error(i, e))?;
+					}
+				}
+			}
+		}
+	}
+
+	if !has_colors
+	{
+		let mut rng = model::color::vertex_rng();
+		for vertex in vertices.iter_mut()
+		{
+			vertex.color = model::random_color(&mut rng);
+		}
+	}
+	if !has_texcoords
+	{
+		for vertex in vertices.iter_mut()
+		{
+			vertex.texcoord = (vertex.position.x(), vertex.position.y()).into();
+		}
+	}
+
+	let mut mesh = MeshData::new(vertices, indices);
+	mesh.vertex_colors = has_colors;
+	if !has_normals
+	{
+		mesh.generate_normals();
+	}
+
+	Ok(mesh)
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header, Error>
+{
+	let mut format = None;
+	let mut elements = Vec::<Element>::new();
+	let mut pos = 0;
+	let mut line_number = 0;
+
+	loop
+	{
+		let end = match bytes[pos..].iter().position(|&b| b == b'\n')
+		{
+			Some(end) => pos + end,
+			None => return Err(Error::Header { line: line_number + 1, message: "missing end_header".into() })
+		};
+		let line = String::from_utf8_lossy(&bytes[pos..end]);
+		pos = end + 1;
+		line_number += 1;
+
+		let error = |message: &str| Error::Header { line: line_number, message: message.into() };
+		let tokens: Vec<&str> = line.split_whitespace().collect();
+
+		if line_number == 1
+		{
+			if tokens != ["ply"]
+			{
+				return Err(error("not a ply file"));
+			}
+			continue;
+		}
+
+		match tokens.as_slice()
+		{
+			[] | ["comment", ..] | ["obj_info", ..] => {},
+			["format", name, _version] => {
+				format = Some(match *name
+				{
+					"ascii" => Format::Ascii,
+					"binary_little_endian" => Format::BinaryLittleEndian,
+					"binary_big_endian" => Format::BinaryBigEndian,
+					_ => return Err(error("unknown format"))
+				});
+			},
+			["element", name, count] => {
+				let count = count.parse().map_err(|_| error("invalid element count"))?;
+				elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+			},
+			["property", "list", count, item, name] => {
+				let count = ScalarType::from_name(count).ok_or_else(|| error("unknown property type"))?;
+				let item = ScalarType::from_name(item).ok_or_else(|| error("unknown property type"))?;
+				let element = elements.last_mut().ok_or_else(|| error("property before any element"))?;
+				element.properties.push(Property { name: name.to_string(), kind: PropertyKind::List { count, item } });
+			},
+			["property", ty, name] => {
+				let ty = ScalarType::from_name(ty).ok_or_else(|| error("unknown property type"))?;
+				let element = elements.last_mut().ok_or_else(|| error("property before any element"))?;
+				element.properties.push(Property { name: name.to_string(), kind: PropertyKind::Scalar(ty) });
+			},
+			["end_header"] => break,
+			_ => return Err(error("unexpected header line"))
+		}
+	}
+
+	let format = format.ok_or(Error::Header { line: line_number, message: "missing format".into() })?;
+
+	Ok(Header { format, elements, body_offset: pos })
+}
+
+// Where a vertex property ends up in Vertex
+#[derive(Copy, Clone, Debug)]
+enum Slot
+{
+	Position(usize),
+	Normal(usize),
+	Color(usize),
+	Texcoord(usize)
+}
+
+impl Slot
+{
+	fn for_property(property: &Property) -> Option<Slot>
+	{
+		match property.name.as_str()
+		{
+			"x" => Some(Slot::Position(0)),
+			"y" => Some(Slot::Position(1)),
+			"z" => Some(Slot::Position(2)),
+			"nx" => Some(Slot::Normal(0)),
+			"ny" => Some(Slot::Normal(1)),
+			"nz" => Some(Slot::Normal(2)),
+			"red" | "r" | "diffuse_red" => Some(Slot::Color(0)),
+			"green" | "g" | "diffuse_green" => Some(Slot::Color(1)),
+			"blue" | "b" | "diffuse_blue" => Some(Slot::Color(2)),
+			"u" | "s" | "texture_u" | "texture_s" => Some(Slot::Texcoord(0)),
+			"v" | "t" | "texture_v" | "texture_t" => Some(Slot::Texcoord(1)),
+			_ => None
+		}
+	}
+}
+
+fn read_vertex(body: &mut Body, element: &Element, slots: &[Option<Slot>]) -> Result<Vertex, String>
+{
+	let mut position = [0.0f32; 3];
+	let mut normal = [0.0f32; 3];
+	let mut color = [1.0f32; 3];
+	let mut texcoord = [0.0f32; 2];
+
+	for (property, slot) in element.properties.iter().zip(slots)
+	{
+		let ty = match (property.kind, slot)
+		{
+			(PropertyKind::Scalar(ty), Some(_)) => ty,
+			_ => {
+				skip_property(body, property)?;
+				continue;
+			}
+		};
+
+		let value = body.read(ty)?;
+		match *slot
+		{
+			Some(Slot::Position(i)) => position[i] = value as f32,
+			Some(Slot::Normal(i)) => normal[i] = value as f32,
+			Some(Slot::Color(i)) => color[i] = (value / ty.color_scale()) as f32,
+			Some(Slot::Texcoord(i)) => texcoord[i] = value as f32,
+			None => {}
+		}
+	}
+
+	let mut vertex = Vertex::new(
+		(position[0], position[1], position[2]).into(),
+		(color[0], color[1], color[2]).into(),
+		(texcoord[0], texcoord[1]).into()
+	);
+	vertex.normal = math::vector::Vector3::new(normal[0], normal[1], normal[2]);
+
+	Ok(vertex)
+}
+
+fn read_face(body: &mut Body, element: &Element, corners: &mut Vec<u32>) -> Result<(), String>
+{
+	corners.clear();
+
+	for property in &element.properties
+	{
+		match (property.name.as_str(), property.kind)
+		{
+			("vertex_indices" | "vertex_index", PropertyKind::List { count, item }) => {
+				let count = body.read(count)? as usize;
+				if !body.can_hold(item, count)
+				{
+					return Err("unexpected end of file".into());
+				}
+				for _ in 0..count
+				{
+					let index = body.read(item)?;
+					if index < 0.0
+					{
+						return Err(format!("negative vertex index {}", index));
+					}
+					corners.push(index as u32);
+				}
+			},
+			_ => skip_property(body, property)?
+		}
+	}
+
+	Ok(())
+}
+
+fn skip_property(body: &mut Body, property: &Property) -> Result<(), String>
+{
+	match property.kind
+	{
+		PropertyKind::Scalar(ty) => body.skip(ty, 1),
+		PropertyKind::List { count, item } => {
+			let count = body.read(count)? as usize;
+			body.skip(item, count)
+		}
+	}
+}
+
+enum Body<'a>
+{
+	Ascii(str::SplitAsciiWhitespace<'a>),
+	Binary {
+		bytes: &'a [u8],
+		pos: usize,
+		big_endian: bool
+	}
+}
+
+impl<'a> Body<'a>
+{
+	// every ply scalar type fits in an f64 without losing precision
+	fn read(&mut self, ty: ScalarType) -> Result<f64, String>
+	{
+		match self
+		{
+			Body::Ascii(tokens) => {
+				let token = tokens.next().ok_or("unexpected end of file")?;
+				token.parse().map_err(|_| format!("invalid number '{}'", token))
+			},
+			Body::Binary { bytes, pos, big_endian } => {
+				let size = ty.size();
+				let raw = bytes.get(*pos..*pos + size).ok_or("unexpected end of file")?;
+				*pos += size;
+
+				let mut buffer = [0u8; 8];
+				buffer[..size].copy_from_slice(raw);
+				if *big_endian
+				{
+					buffer[..size].reverse();
+				}
+
+				Ok(match ty
+				{
+					ScalarType::I8 => buffer[0] as i8 as f64,
+					ScalarType::U8 => buffer[0] as f64,
+					ScalarType::I16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+					ScalarType::U16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+					ScalarType::I32 => i32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+					ScalarType::U32 => u32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+					ScalarType::F32 => f32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+					ScalarType::F64 => f64::from_le_bytes(buffer)
+				})
+			}
+		}
+	}
+
+	// whether count values of ty are left, list counts are read from the file and can be anything.
+	// Ascii values are read one token at a time, so running out there is noticed without reading ahead
+	fn can_hold(&self, ty: ScalarType, count: usize) -> bool
+	{
+		match self
+		{
+			Body::Ascii(_) => true,
+			Body::Binary { bytes, pos, .. } => ty.size()
+				.checked_mul(count)
+				.and_then(|size| pos.checked_add(size))
+				.is_some_and(|end| end <= bytes.len())
+		}
+	}
+
+	fn skip(&mut self, ty: ScalarType, count: usize) -> Result<(), String>
+	{
+		if !self.can_hold(ty, count)
+		{
+			return Err("unexpected end of file".into());
+		}
+
+		match self
+		{
+			Body::Ascii(_) => {
+				for _ in 0..count
+				{
+					self.read(ty)?;
+				}
+			},
+			Body::Binary { pos, .. } => *pos += ty.size() * count
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property uchar intensity
+property list uchar int vertex_indices
+end_header
+";
+
+	fn check_quad(mesh: &MeshData)
+	{
+		assert_eq!(mesh.vertices.len(), 4);
+		assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+		assert_eq!(mesh.vertices[2].position(), (1.0, 1.0, 0.0).into());
+		assert_eq!(mesh.vertices[0].color(), (1.0, 0.0, 0.0).into());
+		assert_eq!(mesh.vertices[3].color(), (0.0, 0.0, 1.0).into());
+		// no normals in the file, so they are generated
+		assert_eq!(mesh.vertices[1].normal(), (0.0, 0.0, 1.0).into());
+	}
+
+	fn binary_quad(format: &str, big_endian: bool) -> Vec<u8>
+	{
+		let mut bytes = format!("ply\nformat {} 1.0\ncomment made by hand\n{}", format, HEADER).into_bytes();
+		let positions = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+		let colors = [[255u8, 0, 0], [0, 255, 0], [0, 0, 255], [0, 0, 255]];
+
+		for (position, color) in positions.iter().zip(colors)
+		{
+			for value in position
+			{
+				bytes.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+			}
+			bytes.extend_from_slice(&color);
+		}
+
+		bytes.extend_from_slice(&[7, 4]);
+		for index in [0i32, 1, 2, 3]
+		{
+			bytes.extend_from_slice(&if big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+		}
+
+		bytes
+	}
+
+	#[test]
+	fn ascii()
+	{
+		let source = format!("ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 0 0 255\n7 4 0 1 2 3\n", HEADER);
+		check_quad(&from_bytes(source.as_bytes()).unwrap());
+	}
+
+	#[test]
+	fn binary_little_endian()
+	{
+		check_quad(&from_bytes(&binary_quad("binary_little_endian", false)).unwrap());
+	}
+
+	#[test]
+	fn binary_big_endian()
+	{
+		check_quad(&from_bytes(&binary_quad("binary_big_endian", true)).unwrap());
+	}
+
+	#[test]
+	fn normals_and_texcoords()
+	{
+		let source = "ply
+format ascii 1.0
+element vertex 3
+property double x
+property double y
+property double z
+property float nx
+property float ny
+property float nz
+property float s
+property float t
+element face 1
+property list uchar uint vertex_index
+end_header
+0 0 0 0 1 0 0 0
+1 0 0 0 1 0 1 0
+0 0 1 0 1 0 0 1
+3 0 1 2
+";
+		let mesh = from_bytes(source.as_bytes()).unwrap();
+		assert_eq!(mesh.vertices[1].texcoord(), (1.0, 0.0).into());
+		assert_eq!(mesh.vertices[2].normal(), (0.0, 1.0, 0.0).into());
+	}
+
+	#[test]
+	fn counts_beyond_the_body()
+	{
+		let huge_vertices: &[u8] = b"ply\nformat ascii 1.0\nelement vertex 4000000000\nproperty float x\nend_header\n0\n";
+		let huge_faces: &[u8] = b"ply\nformat binary_little_endian 1.0\nelement face 4000000000\nproperty list uchar int vertex_indices\nend_header\n\x03";
+		let huge_list: &[u8] = b"ply\nformat binary_little_endian 1.0\nelement face 1\nproperty list uint uchar vertex_indices\nend_header\n\xff\xff\xff\xff";
+		let huge_skipped: &[u8] = b"ply\nformat binary_little_endian 1.0\nelement edge 1\nproperty list uint double vertex_indices\nend_header\n\xff\xff\xff\xff";
+
+		for (source, expected) in [(huge_vertices, "vertex"), (huge_faces, "face"), (huge_list, "face"), (huge_skipped, "edge")]
+		{
+			// an error, instead of aborting on the allocation
+			match from_bytes(source)
+			{
+				Err(Error::Data { element, .. }) => assert_eq!(element, expected),
+				other => panic!("expected a data error for {}, got {:?}", expected, other.map(|m| m.vertices.len()))
+			}
+		}
+	}
+
+	#[test]
+	fn truncated_body()
+	{
+		let mut bytes = binary_quad("binary_little_endian", false);
+		bytes.truncate(bytes.len() - 2);
+
+		match from_bytes(&bytes)
+		{
+			Err(Error::Data { element, index: 0, .. }) => assert_eq!(element, "face"),
+			_ => panic!("expected a data error")
+		}
+	}
+}
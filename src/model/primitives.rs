@@ -0,0 +1,499 @@
+// Generated meshes, for lighting tests and as a stand-in when a model can't be loaded.
+// All of them are centered on the origin with y up, wound counter-clockwise seen from outside,
+// and have unit normals, texture coordinates and tangents (along u, with w = 1 so the bitangent points along v).
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use math::vector::{Vector2, Vector3, Vector4};
+use crate::model::{MeshData, Vertex};
+
+const WHITE: (f32, f32, f32) = (1.0, 1.0, 1.0);
+
+// names accepted by by_name, with the default parameters
+pub const NAMES: [&str; 9] = ["cube", "sphere", "icosphere", "cylinder", "cone", "torus", "plane", "grid", "capsule"];
+
+pub fn by_name(name: &str) -> Option<MeshData>
+{
+	let data = match name
+	{
+		"cube" => cube(1.0, 1),
+		"sphere" => uv_sphere(0.5, 32, 16),
+		"icosphere" => icosphere(0.5, 3),
+		"cylinder" => cylinder(0.5, 1.0, 32),
+		"cone" => cone(0.5, 1.0, 32),
+		"torus" => torus(0.5, 0.2, 32, 16),
+		"plane" => plane(1.0, 1.0, 1, 1),
+		"grid" => plane(1.0, 1.0, 10, 10),
+		"capsule" => capsule(0.25, 0.5, 32, 8),
+		_ => return None
+	};
+
+	Some(data)
+}
+
+fn vertex(position: Vector3, normal: Vector3, texcoord: Vector2, tangent: Vector3) -> Vertex
+{
+	let mut vertex = Vertex::new(position, WHITE.into(), texcoord);
+	vertex.normal = normal;
+	vertex.tangent = Vector4::from((tangent, 1.0));
+	vertex
+}
+
+// quads between rows of columns + 1 vertices starting at first, split along the same diagonal
+fn grid_indices(indices: &mut Vec<u32>, first: u32, columns: usize, rows: usize)
+{
+	let stride = columns as u32 + 1;
+	for j in 0..rows as u32
+	{
+		for i in 0..columns as u32
+		{
+			let a = first + j * stride + i;
+			let b = a + 1;
+			let c = b + stride;
+			let d = a + stride;
+			indices.extend_from_slice(&[a, b, c, a, c, d]);
+		}
+	}
+}
+
+// Flat grid of size.0 by size.1 quads, spanned by the unit vectors tangent and bitangent around center
+fn face(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, center: Vector3, tangent: Vector3, bitangent: Vector3, size: (f32, f32), segments: (usize, usize))
+{
+	let normal = crate::model::cross(tangent, bitangent);
+	let first = vertices.len() as u32;
+	for j in 0..=segments.1
+	{
+		let v = j as f32 / segments.1 as f32;
+		for i in 0..=segments.0
+		{
+			let u = i as f32 / segments.0 as f32;
+			let position = center + tangent * ((u - 0.5) * size.0) + bitangent * ((v - 0.5) * size.1);
+			vertices.push(vertex(position, normal, (u, v).into(), tangent));
+		}
+	}
+	grid_indices(indices, first, segments.0, segments.1);
+}
+
+// point of a profile that is spun around the y axis
+struct Ring
+{
+	radius: f32,
+	y: f32,
+	// outward normal in the (radius, y) plane
+	normal: (f32, f32),
+	v: f32
+}
+
+// sin and cos of the angle of step i out of steps around a full circle. The last step is exactly the
+// first, so vertices on either side of a seam end up at the same position
+fn around(i: usize, steps: usize) -> (f32, f32)
+{
+	if i == 0 || i == steps { (0.0, 1.0) } else { (i as f32 / steps as f32 * 2.0 * PI).sin_cos() }
+}
+
+// Surface of revolution, rings go from bottom to top. A ring with radius 0 is a pole, which gets
+// one vertex per segment in the middle of it instead of one per column, and only one triangle per segment
+fn revolve(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, rings: &[Ring], segments: usize)
+{
+	let mut starts = Vec::with_capacity(rings.len());
+	for ring in rings
+	{
+		starts.push(vertices.len() as u32);
+		if ring.radius == 0.0
+		{
+			for i in 0..segments
+			{
+				let u = (i as f32 + 0.5) / segments as f32;
+				let (sin, cos) = (u * 2.0 * PI).sin_cos();
+				let normal = Vector3::new(ring.normal.0 * cos, ring.normal.1, -ring.normal.0 * sin);
+				vertices.push(vertex((0.0, ring.y, 0.0).into(), normal, (u, ring.v).into(), (-sin, 0.0, -cos).into()));
+			}
+			continue;
+		}
+
+		for i in 0..=segments
+		{
+			let u = i as f32 / segments as f32;
+			let (sin, cos) = around(i, segments);
+			let position = Vector3::new(ring.radius * cos, ring.y, -ring.radius * sin);
+			let normal = Vector3::new(ring.normal.0 * cos, ring.normal.1, -ring.normal.0 * sin);
+			vertices.push(vertex(position, normal, (u, ring.v).into(), (-sin, 0.0, -cos).into()));
+		}
+	}
+
+	for j in 0..rings.len() - 1
+	{
+		let (low, high) = (starts[j], starts[j + 1]);
+		for i in 0..segments as u32
+		{
+			if rings[j].radius == 0.0
+			{
+				indices.extend_from_slice(&[low + i, high + i + 1, high + i]);
+			}
+			else if rings[j + 1].radius == 0.0
+			{
+				indices.extend_from_slice(&[low + i, low + i + 1, high + i]);
+			}
+			else
+			{
+				indices.extend_from_slice(&[low + i, low + i + 1, high + i + 1, low + i, high + i + 1, high + i]);
+			}
+		}
+	}
+}
+
+// flat disc facing up or down, with the texture mapped straight on from outside
+fn cap(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, radius: f32, y: f32, up: bool, segments: usize)
+{
+	let side = if up { 1.0 } else { -1.0 };
+	let normal = Vector3::new(0.0, side, 0.0);
+	let tangent = Vector3::new(1.0, 0.0, 0.0);
+	let center = vertices.len() as u32;
+	vertices.push(vertex((0.0, y, 0.0).into(), normal, (0.5, 0.5).into(), tangent));
+	// the last vertex of the rim would be at the same position as the first, with the same texture coordinate
+	for i in 0..segments
+	{
+		let (sin, cos) = around(i, segments);
+		let texcoord = (0.5 + 0.5 * cos, 0.5 + 0.5 * sin * side).into();
+		vertices.push(vertex((radius * cos, y, -radius * sin).into(), normal, texcoord, tangent));
+	}
+	for i in 0..segments as u32
+	{
+		let (a, b) = (center + 1 + i, center + 1 + (i + 1) % segments as u32);
+		if up
+		{
+			indices.extend_from_slice(&[center, a, b]);
+		}
+		else
+		{
+			indices.extend_from_slice(&[center, b, a]);
+		}
+	}
+}
+
+// Cube with sides of length size, each side split into segments * segments quads
+pub fn cube(size: f32, segments: usize) -> MeshData
+{
+	let segments = segments.max(1);
+	let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+	// normal and tangent of each side
+	let sides = [
+		((1.0, 0.0, 0.0), (0.0, 0.0, -1.0)),
+		((-1.0, 0.0, 0.0), (0.0, 0.0, 1.0)),
+		((0.0, 1.0, 0.0), (1.0, 0.0, 0.0)),
+		((0.0, -1.0, 0.0), (1.0, 0.0, 0.0)),
+		((0.0, 0.0, 1.0), (1.0, 0.0, 0.0)),
+		((0.0, 0.0, -1.0), (-1.0, 0.0, 0.0))
+	];
+	for (normal, tangent) in sides
+	{
+		let (normal, tangent) = (Vector3::from(normal), Vector3::from(tangent));
+		let bitangent = crate::model::cross(normal, tangent);
+		face(&mut vertices, &mut indices, normal * (size / 2.0), tangent, bitangent, (size, size), (segments, segments));
+	}
+
+	MeshData::new(vertices, indices)
+}
+
+// Flat grid in the xz plane facing up, width along x and depth along z
+pub fn plane(width: f32, depth: f32, x_segments: usize, z_segments: usize) -> MeshData
+{
+	let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+	face(
+		&mut vertices,
+		&mut indices,
+		(0.0, 0.0, 0.0).into(),
+		(1.0, 0.0, 0.0).into(),
+		(0.0, 0.0, -1.0).into(),
+		(width, depth),
+		(x_segments.max(1), z_segments.max(1))
+	);
+
+	MeshData::new(vertices, indices)
+}
+
+// Sphere with segments around the y axis and rings from pole to pole
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> MeshData
+{
+	let (segments, rings) = (segments.max(3), rings.max(2));
+	let profile: Vec<Ring> = (0..=rings)
+		.map(|j| {
+			let (sin, cos) = (j as f32 / rings as f32 * PI).sin_cos();
+			// exactly 0 at the poles
+			let sin = if j == 0 || j == rings { 0.0 } else { sin };
+			Ring { radius: radius * sin, y: -radius * cos, normal: (sin, -cos), v: j as f32 / rings as f32 }
+		})
+		.collect();
+
+	let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+	revolve(&mut vertices, &mut indices, &profile, segments);
+
+	MeshData::new(vertices, indices)
+}
+
+// Sphere made by splitting the triangles of an icosahedron subdivisions times, evenly spread triangles without poles.
+// Vertices on the texture seam are duplicated so every triangle has continuous texture coordinates
+pub fn icosphere(radius: f32, subdivisions: usize) -> MeshData
+{
+	let t = (1.0 + 5f32.sqrt()) / 2.0;
+	let mut positions: Vec<Vector3> = [
+		(-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+		(0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+		(t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0)
+	]
+		.iter()
+		.map(|&p| Vector3::from(p).normalized())
+		.collect();
+	let mut triangles: Vec<[u32; 3]> = vec![
+		[0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+		[1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+		[3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+		[4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1]
+	];
+
+	for _ in 0..subdivisions
+	{
+		let mut midpoints = HashMap::new();
+		let mut midpoint = |a: u32, b: u32, positions: &mut Vec<Vector3>| {
+			*midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+				positions.push(((positions[a as usize] + positions[b as usize]) / 2.0).normalized());
+				positions.len() as u32 - 1
+			})
+		};
+		triangles = triangles
+			.iter()
+			.flat_map(|&[a, b, c]| {
+				let ab = midpoint(a, b, &mut positions);
+				let bc = midpoint(b, c, &mut positions);
+				let ca = midpoint(c, a, &mut positions);
+				[[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+			})
+			.collect();
+	}
+
+	// same mapping as uv_sphere: u goes around from +x towards -z, v from the bottom up
+	let texcoord = |p: Vector3| -> Vector2 {
+		let u = (-p.z()).atan2(p.x()) / (2.0 * PI);
+		let u = if u < 0.0 { u + 1.0 } else { u };
+		(u, (-p.y()).clamp(-1.0, 1.0).acos() / PI).into()
+	};
+
+	// u is undefined at the poles, so every triangle gets its own pole vertex in the middle of its other two corners
+	let at_pole = |p: Vector3| p.x().abs() < 1e-6 && p.z().abs() < 1e-6;
+	let mut vertices = Vec::new();
+	let mut indices = Vec::new();
+	// (position, wrapped past u = 1) -> vertex
+	let mut corners = HashMap::new();
+	let push = |vertices: &mut Vec<Vertex>, p: Vector3, uv: Vector2| {
+		let (sin, cos) = (uv.x() * 2.0 * PI).sin_cos();
+		vertices.push(vertex(p * radius, p, uv, (-sin, 0.0, -cos).into()));
+		vertices.len() as u32 - 1
+	};
+	for triangle in &triangles
+	{
+		let u: Vec<Option<f32>> = triangle
+			.iter()
+			.map(|&i| Some(positions[i as usize]).filter(|&p| !at_pole(p)).map(|p| texcoord(p).x()))
+			.collect();
+		let known: Vec<f32> = u.iter().flatten().cloned().collect();
+		let wraps = known.iter().cloned().fold(f32::MIN, f32::max) - known.iter().cloned().fold(f32::MAX, f32::min) > 0.5;
+		let unwrapped = |u: f32| if wraps && u < 0.5 { u + 1.0 } else { u };
+
+		for (corner, &i) in triangle.iter().enumerate()
+		{
+			let p = positions[i as usize];
+			let index = match u[corner]
+			{
+				None => {
+					let u = known.iter().map(|&u| unwrapped(u)).sum::<f32>() / known.len() as f32;
+					push(&mut vertices, p, (u, texcoord(p).y()).into())
+				},
+				Some(u) => {
+					let wrapped = unwrapped(u) != u;
+					*corners.entry((i, wrapped)).or_insert_with(|| push(&mut vertices, p, (unwrapped(u), texcoord(p).y()).into()))
+				}
+			};
+			indices.push(index);
+		}
+	}
+
+	MeshData::new(vertices, indices)
+}
+
+// Cylinder standing on the y axis, closed at both ends
+pub fn cylinder(radius: f32, height: f32, segments: usize) -> MeshData
+{
+	let segments = segments.max(3);
+	let (bottom, top) = (-height / 2.0, height / 2.0);
+	let profile = [
+		Ring { radius, y: bottom, normal: (1.0, 0.0), v: 0.0 },
+		Ring { radius, y: top, normal: (1.0, 0.0), v: 1.0 }
+	];
+
+	let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+	revolve(&mut vertices, &mut indices, &profile, segments);
+	cap(&mut vertices, &mut indices, radius, bottom, false, segments);
+	cap(&mut vertices, &mut indices, radius, top, true, segments);
+
+	MeshData::new(vertices, indices)
+}
+
+// Cone with its base at the bottom and the tip on the y axis. Each column gets its own tip vertex,
+// so the sides are shaded smoothly all the way up
+pub fn cone(radius: f32, height: f32, segments: usize) -> MeshData
+{
+	let segments = segments.max(3);
+	let slant = (height * height + radius * radius).sqrt();
+	let normal = (height / slant, radius / slant);
+	let profile = [
+		Ring { radius, y: -height / 2.0, normal, v: 0.0 },
+		Ring { radius: 0.0, y: height / 2.0, normal, v: 1.0 }
+	];
+
+	let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+	revolve(&mut vertices, &mut indices, &profile, segments);
+	cap(&mut vertices, &mut indices, radius, -height / 2.0, false, segments);
+
+	MeshData::new(vertices, indices)
+}
+
+// Torus lying in the xz plane, major is the distance from the center to the middle of the tube
+pub fn torus(major: f32, minor: f32, segments: usize, sides: usize) -> MeshData
+{
+	let (segments, sides) = (segments.max(3), sides.max(3));
+	let profile: Vec<Ring> = (0..=sides)
+		.map(|j| {
+			let v = j as f32 / sides as f32;
+			// starts and ends on the outside, so the seam is where it is least visible from above
+			let (sin, cos) = around(j, sides);
+			Ring { radius: major + minor * cos, y: minor * sin, normal: (cos, sin), v }
+		})
+		.collect();
+
+	let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+	revolve(&mut vertices, &mut indices, &profile, segments);
+
+	MeshData::new(vertices, indices)
+}
+
+// Cylinder of the given height with a half sphere on each end, rings is the number of rings per half sphere.
+// v follows the length of the profile, so the texture isn't stretched on the ends
+pub fn capsule(radius: f32, height: f32, segments: usize, rings: usize) -> MeshData
+{
+	let (segments, rings) = (segments.max(3), rings.max(1));
+	let length = PI * radius + height;
+	let mut profile = Vec::new();
+	for (offset, range) in [(-height / 2.0, 0..=rings), (height / 2.0, rings..=2 * rings)]
+	{
+		for j in range
+		{
+			let angle = j as f32 / (2 * rings) as f32 * PI;
+			let (sin, cos) = angle.sin_cos();
+			let sin = if j == 0 || j == 2 * rings { 0.0 } else { sin };
+			let arc = angle * radius + if offset > 0.0 { height } else { 0.0 };
+			profile.push(Ring { radius: radius * sin, y: offset - radius * cos, normal: (sin, -cos), v: arc / length });
+		}
+	}
+
+	let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+	revolve(&mut vertices, &mut indices, &profile, segments);
+
+	MeshData::new(vertices, indices)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::model::validate;
+
+	// what every primitive has to get right: normals point out of the triangles they are on and
+	// are unit length, tangents are perpendicular to them and follow u
+	fn check(name: &str, data: &MeshData)
+	{
+		assert!(!data.indices.is_empty(), "{}", name);
+		for vertex in &data.vertices
+		{
+			let (normal, tangent) = (vertex.normal(), vertex.tangent());
+			let tangent3 = Vector3::new(tangent.x(), tangent.y(), tangent.z());
+			assert!((normal.length() - 1.0).abs() < 1e-4, "{}: normal {:?}", name, normal);
+			assert!((tangent3.length() - 1.0).abs() < 1e-4, "{}: tangent {:?}", name, tangent);
+			assert!(normal.dot(&tangent3).abs() < 1e-4, "{}: tangent {:?} not perpendicular to {:?}", name, tangent, normal);
+			assert_eq!(tangent.w(), 1.0);
+		}
+
+		for triangle in data.indices.chunks(3)
+		{
+			let [a, b, c] = [0, 1, 2].map(|i| data.vertices[triangle[i] as usize]);
+			let (e1, e2) = (b.position() - a.position(), c.position() - a.position());
+			let face_normal = crate::model::cross(e1, e2);
+			assert!(face_normal.length() > 1e-8, "{}: degenerate triangle {:?}", name, triangle);
+			for vertex in [a, b, c]
+			{
+				assert!(face_normal.dot(&vertex.normal()) > 0.0, "{}: triangle {:?} faces away from its normals", name, triangle);
+			}
+
+			// direction of increasing u on the triangle
+			let (d1, d2) = (b.texcoord() - a.texcoord(), c.texcoord() - a.texcoord());
+			let det = d1.x() * d2.y() - d2.x() * d1.y();
+			assert!(det > 0.0, "{}: texture mirrored on triangle {:?}", name, triangle);
+			let along_u = (e1 * d2.y() - e2 * d1.y()) / det;
+			let t = a.tangent();
+			assert!(along_u.dot(&Vector3::new(t.x(), t.y(), t.z())) > 0.0, "{}: tangent against u on {:?}", name, triangle);
+		}
+	}
+
+	fn assert_closed(name: &str, data: &MeshData)
+	{
+		let report = validate::validate(data);
+		assert!(report.is_clean(), "{}: {:?}", name, report);
+	}
+
+	#[test]
+	fn solids_are_closed_and_consistent()
+	{
+		for name in NAMES.iter().filter(|name| !["plane", "grid"].contains(name))
+		{
+			let data = by_name(name).unwrap();
+			check(name, &data);
+			assert_closed(name, &data);
+		}
+
+		for name in ["plane", "grid"]
+		{
+			check(name, &by_name(name).unwrap());
+		}
+		assert!(by_name("teapot").is_none());
+	}
+
+	#[test]
+	fn resolution()
+	{
+		let cube = cube(2.0, 3);
+		assert_eq!(cube.vertices.len(), 6 * 16);
+		assert_eq!(cube.indices.len(), 6 * 9 * 6);
+		assert_eq!(cube.bounds.max(), Vector3::new(1.0, 1.0, 1.0));
+
+		// one vertex per segment at the poles, and a single triangle per segment around them
+		let sphere = uv_sphere(1.0, 8, 4);
+		assert_eq!(sphere.vertices.len(), 9 * 3 + 8 * 2);
+		assert_eq!(sphere.indices.len(), (8 * 4 * 2 - 2 * 8) * 3);
+
+		// 20 * 4^n triangles
+		assert_eq!(icosphere(1.0, 2).indices.len(), 20 * 16 * 3);
+		for vertex in &icosphere(2.0, 2).vertices
+		{
+			assert!((vertex.position().length() - 2.0).abs() < 1e-4);
+		}
+
+		let plane = plane(2.0, 1.0, 4, 2);
+		assert_eq!(plane.vertices.len(), 15);
+		assert_eq!(plane.indices.len(), 8 * 6);
+		assert_eq!(plane.bounds.min(), Vector3::new(-1.0, 0.0, -0.5));
+		assert!(plane.vertices.iter().all(|v| v.normal() == Vector3::new(0.0, 1.0, 0.0)));
+
+		let capsule = capsule(0.5, 1.0, 16, 4);
+		assert!((capsule.bounds.max().y() - 1.0).abs() < 1e-5);
+		assert!((capsule.bounds.min().y() + 1.0).abs() < 1e-5);
+	}
+}
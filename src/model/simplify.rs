@@ -0,0 +1,571 @@
+// Edge collapse simplification with quadric error metrics (Garland & Heckbert).
+//
+// Collapses always move a vertex onto one of its neighbours, so a simplified mesh is only a new
+// index buffer over the same vertices, and every level of detail can share one vertex buffer.
+// Collapses work on positions rather than vertices: copies of a vertex that only differ in their
+// normal (hard edges) move together. Positions on a boundary, on a uv seam (copies with different
+// texcoords) or shared by two submeshes are never moved, so outlines, seams and material borders stay put.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use crate::model::{self, MeshData, SubMesh, Vertex};
+use crate::model::optimize;
+
+// each level aims for this fraction of the previous level's triangles
+const LOD_REDUCTION: f32 = 0.5;
+// no more levels once a level has fewer triangles than this
+const MIN_LOD_TRIANGLES: usize = 256;
+pub const MAX_LODS: usize = 4;
+// a level that doesn't get at least this much smaller than the previous one isn't worth keeping
+const MIN_LOD_GAIN: f32 = 0.8;
+// largest error a level may have, as a fraction of the model's size; past that it stops looking like the model
+const MAX_LOD_ERROR: f32 = 0.02;
+
+// A simplified version of a mesh, drawn with the same vertices
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lod
+{
+	pub indices: Vec<u32>,
+	// same number and order as the full mesh's submeshes, ranges are in `indices`
+	pub submeshes: Vec<SubMesh>,
+	// largest error of a collapse that was made, roughly a distance in model units
+	pub error: f32
+}
+
+impl Lod
+{
+	pub fn triangle_count(&self) -> usize
+	{
+		self.indices.len() / 3
+	}
+}
+
+// Symmetric 4x4 matrix, the sum of squared distances to a set of planes: p^T A p + 2 b.p + c
+#[derive(Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric
+{
+	fn from_plane(n: [f64; 3], d: f64) -> Self
+	{
+		let [a, b, c] = n;
+		Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d])
+	}
+
+	fn add(&mut self, other: &Quadric)
+	{
+		for (a, b) in self.0.iter_mut().zip(other.0.iter())
+		{
+			*a += b;
+		}
+	}
+
+	fn error(&self, p: [f64; 3]) -> f64
+	{
+		let [x, y, z] = p;
+		let q = &self.0;
+		let e = q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+			+ q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+			+ q[7] * z * z + 2.0 * q[8] * z
+			+ q[9];
+
+		// rounding can make it slightly negative
+		e.max(0.0)
+	}
+}
+
+#[derive(Copy, Clone, PartialEq)]
+struct Collapse
+{
+	cost: f64,
+	from: u32,
+	to: u32,
+	// versions of both positions when the cost was computed, anything else is out of date
+	from_version: u32,
+	to_version: u32
+}
+
+impl Eq for Collapse {}
+
+impl Ord for Collapse
+{
+	// reversed, so the heap pops the cheapest collapse first
+	fn cmp(&self, other: &Self) -> Ordering
+	{
+		other.cost.total_cmp(&self.cost)
+	}
+}
+
+impl PartialOrd for Collapse
+{
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering>
+	{
+		Some(self.cmp(other))
+	}
+}
+
+fn to_f64(v: math::vector::Vector3) -> [f64; 3]
+{
+	[v.x() as f64, v.y() as f64, v.z() as f64]
+}
+
+// Collapses edges until at most `target_triangles` are left, or the next collapse would cost more than `max_error`
+pub fn simplify(vertices: &[Vertex], indices: &[u32], submeshes: &[SubMesh], target_triangles: usize, max_error: f32) -> Lod
+{
+	// positions, and which vertices sit on each one
+	let mut position_ids = HashMap::<[u32; 3], u32>::new();
+	let mut positions = Vec::<math::vector::Vector3>::new();
+	let mut copies = Vec::<Vec<u32>>::new();
+	let position_of: Vec<u32> = vertices
+		.iter()
+		.enumerate()
+		.map(|(i, v)| {
+			let p = v.position();
+			let id = *position_ids.entry([p.x().to_bits(), p.y().to_bits(), p.z().to_bits()]).or_insert_with(|| {
+				positions.push(p);
+				copies.push(Vec::new());
+				(positions.len() - 1) as u32
+			});
+			copies[id as usize].push(i as u32);
+			id
+		})
+		.collect();
+	let position_count = positions.len();
+
+	// triangles with the submesh they belong to, out of range ones are left out
+	let mut corners = Vec::<[u32; 3]>::new();
+	let mut triangle_submesh = Vec::<usize>::new();
+	for (s, submesh) in submeshes.iter().enumerate()
+	{
+		let first = (submesh.first as usize).min(indices.len());
+		let end = (first + submesh.count as usize).min(indices.len());
+		for t in indices[first..end].chunks_exact(3)
+		{
+			if t.iter().all(|&i| (i as usize) < vertices.len())
+			{
+				corners.push([t[0], t[1], t[2]]);
+				triangle_submesh.push(s);
+			}
+		}
+	}
+	let mut triangles: Vec<[u32; 3]> = corners
+		.iter()
+		.map(|c| [position_of[c[0] as usize], position_of[c[1] as usize], position_of[c[2] as usize]])
+		.collect();
+	let mut alive: Vec<bool> = triangles.iter().map(|t| t[0] != t[1] && t[1] != t[2] && t[0] != t[2]).collect();
+	let mut live_count = alive.iter().filter(|&&a| a).count();
+
+	let mut quadrics = vec![Quadric::default(); position_count];
+	let mut around = vec![Vec::<usize>::new(); position_count];
+	let mut edges = HashMap::<(u32, u32), usize>::new();
+	let mut position_submesh = vec![usize::MAX; position_count];
+	let mut locked = vec![false; position_count];
+
+	for (t, triangle) in triangles.iter().enumerate()
+	{
+		if !alive[t]
+		{
+			continue;
+		}
+
+		let [a, b, c] = triangle.map(|p| positions[p as usize]);
+		let normal = model::cross(b - a, c - a);
+		let length = normal.length();
+		if length > 0.0
+		{
+			let n = to_f64(normal / length);
+			let d = -(n[0] * a.x() as f64 + n[1] * a.y() as f64 + n[2] * a.z() as f64);
+			let quadric = Quadric::from_plane(n, d);
+			for &p in triangle
+			{
+				quadrics[p as usize].add(&quadric);
+			}
+		}
+
+		for (i, &p) in triangle.iter().enumerate()
+		{
+			around[p as usize].push(t);
+			let next = triangle[(i + 1) % 3];
+			*edges.entry((p.min(next), p.max(next))).or_default() += 1;
+
+			let submesh = &mut position_submesh[p as usize];
+			if *submesh == usize::MAX
+			{
+				*submesh = triangle_submesh[t];
+			}
+			else if *submesh != triangle_submesh[t]
+			{
+				locked[p as usize] = true;
+			}
+		}
+	}
+
+	for (&(a, b), &faces) in &edges
+	{
+		// boundary or non-manifold
+		if faces != 2
+		{
+			locked[a as usize] = true;
+			locked[b as usize] = true;
+		}
+	}
+	for (p, vertices_here) in copies.iter().enumerate()
+	{
+		let texcoord = vertices[vertices_here[0] as usize].texcoord();
+		if vertices_here.iter().any(|&v| vertices[v as usize].texcoord() != texcoord)
+		{
+			locked[p] = true;
+		}
+	}
+
+	let mut version = vec![0u32; position_count];
+	let mut collapsed_into: Vec<u32> = (0..position_count as u32).collect();
+	let mut heap = BinaryHeap::new();
+
+	let push_collapses = |heap: &mut BinaryHeap<Collapse>, p: u32, q: u32, quadrics: &[Quadric], version: &[u32]| {
+		for (from, to) in [(p, q), (q, p)]
+		{
+			if locked[from as usize]
+			{
+				continue;
+			}
+			let mut quadric = quadrics[from as usize];
+			quadric.add(&quadrics[to as usize]);
+			heap.push(Collapse {
+				cost: quadric.error(to_f64(positions[to as usize])),
+				from,
+				to,
+				from_version: version[from as usize],
+				to_version: version[to as usize]
+			});
+		}
+	};
+
+	for &(a, b) in edges.keys()
+	{
+		push_collapses(&mut heap, a, b, &quadrics, &version);
+	}
+
+	let max_cost = (max_error as f64) * (max_error as f64);
+	let mut error = 0.0f64;
+
+	while live_count > target_triangles
+	{
+		let collapse = match heap.pop()
+		{
+			Some(collapse) => collapse,
+			None => break
+		};
+		let (from, to) = (collapse.from as usize, collapse.to as usize);
+		if collapsed_into[from] != from as u32 || collapsed_into[to] != to as u32
+			|| version[from] != collapse.from_version || version[to] != collapse.to_version
+		{
+			continue;
+		}
+		if collapse.cost > max_cost
+		{
+			break;
+		}
+		if flips(&triangles, &alive, &around[from], collapse.from, collapse.to, &positions)
+		{
+			// tried again if the neighbourhood changes
+			continue;
+		}
+
+		error = error.max(collapse.cost);
+		collapsed_into[from] = collapse.to;
+		let from_quadric = quadrics[from];
+		quadrics[to].add(&from_quadric);
+		version[to] += 1;
+
+		let moved = std::mem::take(&mut around[from]);
+		for t in moved
+		{
+			if !alive[t]
+			{
+				continue;
+			}
+			if triangles[t].contains(&collapse.to)
+			{
+				alive[t] = false;
+				live_count -= 1;
+			}
+			else
+			{
+				for p in triangles[t].iter_mut()
+				{
+					if *p == collapse.from
+					{
+						*p = collapse.to;
+					}
+				}
+				around[to].push(t);
+			}
+		}
+		around[to].retain(|&t| alive[t]);
+
+		let mut neighbours: Vec<u32> = around[to].iter().flat_map(|&t| triangles[t]).filter(|&p| p != collapse.to).collect();
+		neighbours.sort_unstable();
+		neighbours.dedup();
+		for neighbour in neighbours
+		{
+			push_collapses(&mut heap, collapse.to, neighbour, &quadrics, &version);
+		}
+	}
+
+	// where each position ended up
+	let resolve = |mut p: u32| -> u32 {
+		while collapsed_into[p as usize] != p
+		{
+			p = collapsed_into[p as usize];
+		}
+		p
+	};
+
+	let mut lod = Lod { indices: Vec::with_capacity(live_count * 3), submeshes: Vec::with_capacity(submeshes.len()), error: error.sqrt() as f32 };
+	for (s, submesh) in submeshes.iter().enumerate()
+	{
+		let first = lod.indices.len();
+		for t in (0..triangles.len()).filter(|&t| alive[t] && triangle_submesh[t] == s)
+		{
+			for corner in corners[t]
+			{
+				let target = resolve(position_of[corner as usize]);
+				let vertex = if position_of[corner as usize] == target { corner } else { closest_copy(vertices, &copies[target as usize], corner) };
+				lod.indices.push(vertex);
+			}
+		}
+		lod.submeshes.push(SubMesh {
+			name: submesh.name.clone(),
+			material: submesh.material.clone(),
+			first: first as u32,
+			count: (lod.indices.len() - first) as u32
+		});
+	}
+
+	lod
+}
+
+// Would moving `from` onto `to` turn any of the remaining triangles around it over?
+fn flips(triangles: &[[u32; 3]], alive: &[bool], around: &[usize], from: u32, to: u32, positions: &[math::vector::Vector3]) -> bool
+{
+	for &t in around
+	{
+		let triangle = triangles[t];
+		if !alive[t] || triangle.contains(&to)
+		{
+			continue;
+		}
+
+		let before = triangle.map(|p| positions[p as usize]);
+		let after = triangle.map(|p| positions[if p == from { to } else { p } as usize]);
+		let normal_before = model::cross(before[1] - before[0], before[2] - before[0]);
+		let normal_after = model::cross(after[1] - after[0], after[2] - after[0]);
+		if normal_before.dot(&normal_after) <= 0.0
+		{
+			return true;
+		}
+	}
+
+	false
+}
+
+// copy of a vertex at the new position that looks the most like the one that was moved there
+fn closest_copy(vertices: &[Vertex], copies: &[u32], original: u32) -> u32
+{
+	let original = &vertices[original as usize];
+	let distance = |v: &u32| {
+		let v = &vertices[*v as usize];
+		(v.texcoord() - original.texcoord()).length() + (v.normal() - original.normal()).length()
+	};
+
+	*copies.iter().min_by(|a, b| distance(a).total_cmp(&distance(b))).unwrap()
+}
+
+// Simplifies level after level, each with about half the triangles of the one before
+pub fn build_lods(data: &MeshData) -> Vec<Lod>
+{
+	let mut lods = Vec::<Lod>::new();
+	let max_error = data.bounds.largest_extent() * MAX_LOD_ERROR;
+
+	while lods.len() < MAX_LODS
+	{
+		let (indices, submeshes) = match lods.last()
+		{
+			Some(lod) => (&lod.indices, &lod.submeshes),
+			None => (&data.indices, &data.submeshes)
+		};
+		let triangles = indices.len() / 3;
+		if triangles < MIN_LOD_TRIANGLES
+		{
+			break;
+		}
+
+		let target = (triangles as f32 * LOD_REDUCTION) as usize;
+		let mut lod = simplify(&data.vertices, indices, submeshes, target, max_error);
+		if lod.triangle_count() as f32 > triangles as f32 * MIN_LOD_GAIN
+		{
+			break;
+		}
+
+		for submesh in &lod.submeshes
+		{
+			let first = submesh.first as usize;
+			optimize::optimize_vertex_cache(&mut lod.indices[first..first + submesh.count as usize]);
+		}
+		lods.push(lod);
+	}
+
+	lods
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::model::validate::{self, Kind};
+
+	// n x n grid on a gentle hill, uv from position
+	fn grid(n: u32, height: f32) -> MeshData
+	{
+		let mut vertices = Vec::new();
+		for y in 0..=n
+		{
+			for x in 0..=n
+			{
+				let (u, v) = (x as f32 / n as f32, y as f32 / n as f32);
+				let z = height * (u * std::f32::consts::PI).sin() * (v * std::f32::consts::PI).sin();
+				vertices.push(Vertex::new((u, v, z).into(), (1.0, 1.0, 1.0).into(), (u, v).into()));
+			}
+		}
+
+		let mut indices = Vec::new();
+		for y in 0..n
+		{
+			for x in 0..n
+			{
+				let i = y * (n + 1) + x;
+				indices.extend_from_slice(&[i, i + 1, i + n + 2, i, i + n + 2, i + n + 1]);
+			}
+		}
+
+		let mut data = MeshData::new(vertices, indices);
+		data.generate_normals();
+		data
+	}
+
+	#[test]
+	fn flat_grid_collapses_to_its_outline()
+	{
+		let data = grid(16, 0.0);
+		let lod = simplify(&data.vertices, &data.indices, &data.submeshes, 0, 1e-4);
+
+		assert!(lod.triangle_count() < data.indices.len() / 3 / 4, "{} triangles left", lod.triangle_count());
+		assert!(lod.error < 1e-4);
+
+		// the boundary vertices are never moved, so the outline is intact
+		let report = validate::validate(&MeshData { indices: lod.indices.clone(), submeshes: lod.submeshes.clone(), ..data.clone() });
+		assert_eq!(report.count(Kind::BoundaryEdge), 16 * 4);
+		assert_eq!(report.count(Kind::InconsistentWinding), 0);
+		assert_eq!(report.count(Kind::NonManifoldEdge), 0);
+	}
+
+	#[test]
+	fn target_and_error_bound()
+	{
+		let data = grid(32, 0.3);
+
+		let lod = simplify(&data.vertices, &data.indices, &data.submeshes, 500, f32::MAX);
+		assert!(lod.triangle_count() <= 500);
+
+		let bounded = simplify(&data.vertices, &data.indices, &data.submeshes, 0, 1e-3);
+		assert!(bounded.error <= 1e-3);
+		assert!(bounded.triangle_count() > lod.triangle_count());
+	}
+
+	#[test]
+	fn uv_seams_stay()
+	{
+		let mut data = grid(16, 0.0);
+		// the right half gets its own copies of the x = 0.5 column, with different texcoords,
+		// and the rest of its vertices are moved along in uv so they stay next to those copies
+		for vertex in data.vertices.iter_mut().filter(|v| v.position().x() > 0.5)
+		{
+			vertex.texcoord = (vertex.texcoord().x() + 1.0, vertex.texcoord().y()).into();
+		}
+		let mut copy_of = HashMap::new();
+		for t in 0..data.indices.len() / 3
+		{
+			let triangle = &data.indices[t * 3..t * 3 + 3];
+			let centre: f32 = triangle.iter().map(|&i| data.vertices[i as usize].position().x()).sum::<f32>() / 3.0;
+			if centre < 0.5
+			{
+				continue;
+			}
+			for corner in t * 3..t * 3 + 3
+			{
+				let index = data.indices[corner];
+				let vertex = data.vertices[index as usize];
+				if vertex.position().x() == 0.5
+				{
+					let copy = *copy_of.entry(index).or_insert_with(|| {
+						let mut copy = vertex;
+						copy.texcoord = (vertex.texcoord().x() + 1.0, vertex.texcoord().y()).into();
+						data.vertices.push(copy);
+						(data.vertices.len() - 1) as u32
+					});
+					data.indices[corner] = copy;
+				}
+			}
+		}
+
+		let lod = simplify(&data.vertices, &data.indices, &data.submeshes, 0, 1e-4);
+		assert!(lod.triangle_count() < data.indices.len() / 3 / 2);
+		// both copies of every seam vertex are still there
+		for (&original, &copy) in &copy_of
+		{
+			assert!(lod.indices.contains(&original) && lod.indices.contains(&copy));
+		}
+	}
+
+	#[test]
+	fn submesh_borders_stay()
+	{
+		let mut data = grid(16, 0.0);
+		// split the grid in two submeshes along y = 0.5
+		let half = (data.indices.len() / 2) as u32;
+		data.submeshes = vec![
+			SubMesh { name: "bottom".into(), material: Some("a".into()), first: 0, count: half },
+			SubMesh { name: "top".into(), material: Some("b".into()), first: half, count: half }
+		];
+		let border = |v: &Vertex| v.position().y() == 0.5;
+
+		let lod = simplify(&data.vertices, &data.indices, &data.submeshes, 0, 1e-4);
+		assert_eq!(lod.submeshes.len(), 2);
+		assert_eq!(lod.submeshes[1].first, lod.submeshes[0].count);
+
+		// every vertex of the border line is still used by both halves
+		for (s, submesh) in lod.submeshes.iter().enumerate()
+		{
+			let used: Vec<u32> = lod.indices[submesh.first as usize..(submesh.first + submesh.count) as usize].to_vec();
+			let on_border = data.vertices.iter().enumerate().filter(|(i, v)| border(v) && used.contains(&(*i as u32))).count();
+			assert_eq!(on_border, 17, "submesh {}", s);
+		}
+	}
+
+	#[test]
+	fn lod_chain_halves()
+	{
+		let data = grid(32, 0.3);
+		let lods = build_lods(&data);
+
+		assert!(!lods.is_empty());
+		let mut previous = data.indices.len() / 3;
+		for lod in &lods
+		{
+			assert!(lod.triangle_count() as f32 <= previous as f32 * MIN_LOD_GAIN);
+			assert!(lod.indices.iter().all(|&i| (i as usize) < data.vertices.len()));
+			previous = lod.triangle_count();
+		}
+	}
+}
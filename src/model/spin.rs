@@ -0,0 +1,155 @@
+// Continuous rotation of a model about an axis through a point (its bounding box center),
+// advanced by frame time so it turns at the same speed at any frame rate
+
+use std::f32::consts::PI;
+use math::matrix::Matrix4;
+use math::vector::Vector3;
+
+// degrees per second
+pub const DEFAULT_SPEED: f32 = 45.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Axis
+{
+	X,
+	Y,
+	Z,
+}
+
+impl Axis
+{
+	// X -> Y -> Z -> X
+	pub fn next(self) -> Self
+	{
+		match self
+		{
+			Axis::X => Axis::Y,
+			Axis::Y => Axis::Z,
+			Axis::Z => Axis::X
+		}
+	}
+
+	pub fn direction(self) -> Vector3
+	{
+		match self
+		{
+			Axis::X => Vector3::new(1.0, 0.0, 0.0),
+			Axis::Y => Vector3::new(0.0, 1.0, 0.0),
+			Axis::Z => Vector3::new(0.0, 0.0, 1.0)
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Spin
+{
+	pub axis: Axis,
+	// degrees per second, negative turns the other way
+	pub speed: f32,
+	pub paused: bool,
+	// radians, kept in 0..2π
+	angle: f32
+}
+
+impl Spin
+{
+	pub fn new(axis: Axis, speed: f32) -> Self
+	{
+		Spin { axis, speed, paused: false, angle: 0.0 }
+	}
+
+	pub fn angle(&self) -> f32
+	{
+		self.angle
+	}
+
+	// Switches to another axis, starting again from the unrotated model so it doesn't jump
+	// to where it would have been had it been spinning about the new axis all along
+	pub fn set_axis(&mut self, axis: Axis)
+	{
+		if axis != self.axis
+		{
+			self.axis = axis;
+			self.angle = 0.0;
+		}
+	}
+
+	pub fn update(&mut self, dt: f32)
+	{
+		if !self.paused
+		{
+			self.angle = (self.angle + self.speed.to_radians() * dt).rem_euclid(2.0 * PI);
+		}
+	}
+
+	// rotation about the axis through center
+	pub fn matrix(&self, center: Vector3) -> Matrix4
+	{
+		let identity = Matrix4::new_identity();
+		let rotation = math::rotate(&math::translate(&identity, &center), self.angle, &self.axis.direction());
+
+		math::translate(&rotation, &-center)
+	}
+}
+
+impl Default for Spin
+{
+	fn default() -> Self
+	{
+		Spin::new(Axis::Y, DEFAULT_SPEED)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use math::vector::Vector4;
+
+	fn apply(m: &Matrix4, p: Vector3) -> Vector3
+	{
+		let p: Vector4 = *m * Vector4::from((p, 1.0));
+		Vector3::new(p.x(), p.y(), p.z())
+	}
+
+	#[test]
+	fn speed_does_not_depend_on_frame_rate()
+	{
+		let (mut slow, mut fast) = (Spin::new(Axis::Y, 90.0), Spin::new(Axis::Y, 90.0));
+		slow.update(0.5);
+		for _ in 0..50
+		{
+			fast.update(0.01);
+		}
+		assert!((slow.angle() - PI / 4.0).abs() < 1e-5);
+		assert!((fast.angle() - PI / 4.0).abs() < 1e-4);
+
+		// stays in one turn
+		slow.update(4.0);
+		assert!((slow.angle() - PI / 4.0).abs() < 1e-4);
+
+		slow.paused = true;
+		slow.update(1.0);
+		assert!((slow.angle() - PI / 4.0).abs() < 1e-4);
+
+		slow.set_axis(Axis::X);
+		assert_eq!(slow.angle(), 0.0);
+		assert_eq!(Axis::X.next().next().next(), Axis::X);
+	}
+
+	#[test]
+	fn turns_about_the_center()
+	{
+		let center = Vector3::new(1.0, 2.0, 3.0);
+		let mut spin = Spin::new(Axis::Z, 90.0);
+		spin.update(1.0);
+		let m = spin.matrix(center);
+
+		let moved = apply(&m, center);
+		assert!((moved - center).length() < 1e-5);
+
+		// a quarter turn counter-clockwise seen from +z
+		let moved = apply(&m, center + Vector3::new(1.0, 0.0, 0.0));
+		assert!((moved - (center + Vector3::new(0.0, 1.0, 0.0))).length() < 1e-5, "{:?}", moved);
+	}
+}
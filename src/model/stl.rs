@@ -0,0 +1,326 @@
+use math;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str;
+use crate::model::{self, MeshData, Vertex};
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug)]
+pub enum Error
+{
+	Io(io::Error),
+	Parse(String),
+}
+
+impl From<io::Error> for Error
+{
+	fn from(other: io::Error) -> Self
+	{
+		Error::Io(other)
+	}
+}
+
+struct Facet
+{
+	normal: math::vector::Vector3,
+	corners: [math::vector::Vector3; 3]
+}
+
+pub fn load<T>(filename: T) -> Result<MeshData, Error>
+where T: AsRef<Path>
+{
+	from_bytes(&fs::read(filename)?)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<MeshData, Error>
+{
+	// ascii files start with "solid", but so do some binary ones (it's just the header text),
+	// so the size has to match too before a file is treated as binary
+	let facets = if is_binary(bytes) { parse_binary(bytes)? } else { parse_ascii(bytes)? };
+
+	Ok(weld(&facets))
+}
+
+fn is_binary(bytes: &[u8]) -> bool
+{
+	if bytes.len() < HEADER_SIZE + 4
+	{
+		return false;
+	}
+
+	let count = u32::from_le_bytes(bytes[HEADER_SIZE..HEADER_SIZE + 4].try_into().unwrap()) as usize;
+	let expected = count.checked_mul(TRIANGLE_SIZE).and_then(|n| n.checked_add(HEADER_SIZE + 4));
+
+	expected == Some(bytes.len()) || !bytes.starts_with(b"solid")
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Facet>, Error>
+{
+	if bytes.len() < HEADER_SIZE + 4
+	{
+		return Err(Error::Parse("file too short for a binary stl header".into()));
+	}
+
+	let count = u32::from_le_bytes(bytes[HEADER_SIZE..HEADER_SIZE + 4].try_into().unwrap()) as usize;
+	let data = &bytes[HEADER_SIZE + 4..];
+	if data.len() / TRIANGLE_SIZE < count
+	{
+		return Err(Error::Parse(format!("header says {} triangles, file only has room for {}", count, data.len() / TRIANGLE_SIZE)));
+	}
+
+	let read_vector = |bytes: &[u8]| {
+		let value = |i: usize| f32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+		math::vector::Vector3::new(value(0), value(1), value(2))
+	};
+
+	Ok(data
+		.chunks_exact(TRIANGLE_SIZE)
+		.take(count)
+		.map(|triangle| Facet {
+			normal: read_vector(&triangle[0..12]),
+			corners: [read_vector(&triangle[12..24]), read_vector(&triangle[24..36]), read_vector(&triangle[36..48])]
+			// last 2 bytes are the "attribute byte count", which nobody agrees on, so it's ignored
+		})
+		.collect())
+}
+
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<Facet>, Error>
+{
+	let text = str::from_utf8(bytes).map_err(|_| Error::Parse("ascii stl is not valid utf-8".into()))?;
+	let mut tokens = text.split_ascii_whitespace();
+	let mut facets = Vec::new();
+	let mut normal = math::vector::Vector3::new(0.0, 0.0, 0.0);
+	let mut corners = Vec::<math::vector::Vector3>::with_capacity(3);
+
+	let read_vector = |tokens: &mut str::SplitAsciiWhitespace| -> Result<math::vector::Vector3, Error> {
+		let mut values = [0.0f32; 3];
+		for value in values.iter_mut()
+		{
+			let token = tokens.next().ok_or_else(|| Error::Parse("unexpected end of file".into()))?;
+			*value = token.parse().map_err(|_| Error::Parse(format!("invalid number '{}'", token)))?;
+		}
+		Ok((values[0], values[1], values[2]).into())
+	};
+
+	while let Some(token) = tokens.next()
+	{
+		match token
+		{
+			"facet" => {
+				if tokens.next() != Some("normal")
+				{
+					return Err(Error::Parse("expected 'normal' after 'facet'".into()));
+				}
+				normal = read_vector(&mut tokens)?;
+				corners.clear();
+			},
+			"vertex" => corners.push(read_vector(&mut tokens)?),
+			"endfacet" => {
+				if corners.len() != 3
+				{
+					return Err(Error::Parse(format!("facet {} has {} vertices", facets.len(), corners.len())));
+				}
+				facets.push(Facet { normal, corners: [corners[0], corners[1], corners[2]] });
+			},
+			// solid name, outer loop, endloop, endsolid name
+			_ => {}
+		}
+	}
+
+	Ok(facets)
+}
+
+// Merges corners that have the same position and the same facet normal.
+// Keying on the normal too keeps the facet normals exact: vertices are shared across
+// a flat area, but not across an edge between two facets facing different ways
+fn weld(facets: &[Facet]) -> MeshData
+{
+	let mut vertices = Vec::<Vertex>::new();
+	let mut indices = Vec::<u32>::with_capacity(facets.len() * 3);
+	let mut lookup = HashMap::<[u32; 6], u32>::new();
+	let mut rng = model::color::vertex_rng();
+
+	for facet in facets
+	{
+		let mut normal = facet.normal;
+		// plenty of exporters write 0 0 0, work it out from the winding instead
+		if normal.length() == 0.0 || !normal.length().is_finite()
+		{
+			let [a, b, c] = facet.corners;
+			normal = model::cross(b - a, c - a);
+		}
+		let length = normal.length();
+		if length > 0.0
+		{
+			normal = normal / length;
+		}
+
+		for corner in facet.corners
+		{
+			let key = [
+				corner.x().to_bits(), corner.y().to_bits(), corner.z().to_bits(),
+				normal.x().to_bits(), normal.y().to_bits(), normal.z().to_bits()
+			];
+			let index = *lookup.entry(key).or_insert_with(|| {
+				let mut vertex = Vertex::new(corner, model::random_color(&mut rng), (corner.x(), corner.y()).into());
+				vertex.normal = normal;
+				vertices.push(vertex);
+				(vertices.len() - 1) as u32
+			});
+			indices.push(index);
+		}
+	}
+
+	MeshData::new(vertices, indices)
+}
+
+pub fn write_binary<T>(filename: T, data: &MeshData, transform: &math::matrix::Matrix4) -> Result<(), Error>
+where T: AsRef<Path>
+{
+	let mut out = BufWriter::new(fs::File::create(filename)?);
+	write_binary_to(&mut out, data, transform)?;
+	out.flush()?;
+
+	Ok(())
+}
+
+// Writes every triangle with `transform` applied to its corners, facet normals are recomputed from the result.
+// A mirroring transform writes the corners in the other order, so the facets still face outwards
+pub fn write_binary_to(out: &mut impl Write, data: &MeshData, transform: &math::matrix::Matrix4) -> Result<(), Error>
+{
+	let mut header = [0u8; HEADER_SIZE];
+	let text = b"binary stl exported by scop";
+	header[..text.len()].copy_from_slice(text);
+	out.write_all(&header)?;
+
+	let triangles = data.indices.len() / 3;
+	out.write_all(&(triangles as u32).to_le_bytes())?;
+
+	let apply = |index: u32| -> Result<math::vector::Vector3, Error> {
+		let vertex = data.vertices.get(index as usize)
+			.ok_or_else(|| Error::Parse(format!("index {} out of range", index)))?;
+		let p: math::vector::Vector4 = *transform * math::vector::Vector4::from((vertex.position, 1.0));
+		Ok(math::vector::Vector3::new(p.x(), p.y(), p.z()))
+	};
+
+	let mirrored = model::coords::mirrors(transform);
+	for triangle in data.indices.chunks_exact(3)
+	{
+		let mut corners = [apply(triangle[0])?, apply(triangle[1])?, apply(triangle[2])?];
+		if mirrored
+		{
+			corners.swap(1, 2);
+		}
+		let mut normal = model::cross(corners[1] - corners[0], corners[2] - corners[0]);
+		let length = normal.length();
+		if length > 0.0
+		{
+			normal = normal / length;
+		}
+
+		for v in [normal, corners[0], corners[1], corners[2]]
+		{
+			for value in [v.x(), v.y(), v.z()]
+			{
+				out.write_all(&value.to_le_bytes())?;
+			}
+		}
+		out.write_all(&0u16.to_le_bytes())?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const ASCII_PYRAMID: &str = "solid pyramid
+  facet normal 0 0 -1
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 1 0 0
+    endloop
+  endfacet
+  facet normal 0 0 -1
+    outer loop
+      vertex 1 0 0
+      vertex 0 1 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0.5 0.5 1
+    endloop
+  endfacet
+endsolid pyramid
+";
+
+	#[test]
+	fn ascii_welds_flat_areas()
+	{
+		let mesh = from_bytes(ASCII_PYRAMID.as_bytes()).unwrap();
+
+		assert_eq!(mesh.indices.len(), 9);
+		// 4 corners of the bottom, plus 3 for the side (different normal, so not shared with the bottom)
+		assert_eq!(mesh.vertices.len(), 7);
+		assert_eq!(mesh.vertices[0].normal(), (0.0, 0.0, -1.0).into());
+		// the zero normal was replaced with one from the winding
+		let side = mesh.vertices[mesh.indices[6] as usize].normal();
+		assert!(side.y() < 0.0 && side.z() > 0.0);
+	}
+
+	#[test]
+	fn binary_round_trip()
+	{
+		let source = from_bytes(ASCII_PYRAMID.as_bytes()).unwrap();
+		let offset = math::translate(&math::matrix::Matrix4::new_identity(), &(0.0, 0.0, 2.0).into());
+
+		let mut bytes = Vec::new();
+		write_binary_to(&mut bytes, &source, &offset).unwrap();
+		assert_eq!(bytes.len(), HEADER_SIZE + 4 + 3 * TRIANGLE_SIZE);
+
+		let loaded = from_bytes(&bytes).unwrap();
+		assert_eq!(loaded.indices, source.indices);
+		assert_eq!(loaded.vertices.len(), source.vertices.len());
+		for (a, b) in loaded.vertices.iter().zip(&source.vertices)
+		{
+			assert_eq!(a.position(), b.position() + math::vector::Vector3::new(0.0, 0.0, 2.0));
+			// normals are recomputed on export, so they can be off by a rounding error
+			assert!((a.normal() - b.normal()).length() < 1e-6);
+		}
+	}
+
+	#[test]
+	fn mirrored_export_faces_outwards()
+	{
+		let source = from_bytes(ASCII_PYRAMID.as_bytes()).unwrap();
+		let conversion = model::coords::Conversion { flip_handedness: true, ..Default::default() };
+
+		let mut bytes = Vec::new();
+		write_binary_to(&mut bytes, &source, &conversion.matrix()).unwrap();
+		let loaded = from_bytes(&bytes).unwrap();
+		// the bottom was facing -z, mirrored in z it faces +z
+		assert_eq!(loaded.vertices[0].normal(), (0.0, 0.0, 1.0).into());
+	}
+
+	#[test]
+	fn binary_header_starting_with_solid()
+	{
+		let source = from_bytes(ASCII_PYRAMID.as_bytes()).unwrap();
+		let mut bytes = Vec::new();
+		write_binary_to(&mut bytes, &source, &math::matrix::Matrix4::new_identity()).unwrap();
+		bytes[..5].copy_from_slice(b"solid");
+
+		assert_eq!(from_bytes(&bytes).unwrap().indices.len(), 9);
+	}
+}
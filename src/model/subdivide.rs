@@ -0,0 +1,539 @@
+// Subdivision surfaces: Loop for triangle meshes, Catmull-Clark for quad-dominant ones.
+//
+// Every level splits each face into 4 (Loop) or one quad per corner (Catmull-Clark) and moves the
+// vertices towards the smooth limit surface. Texcoords and colors are smoothed with the same weights.
+// Boundaries (including uv seams, which are boundaries in the index topology) follow the cubic
+// B-spline boundary rules, so both sides of a seam end up on the same curve. Vertices where the
+// surface isn't manifold are left where they are.
+//
+// Obj faces are fan triangulated when loaded ((a, b, c), (a, c, d), ...), which is how the quads
+// Catmull-Clark needs are found again.
+
+use std::collections::HashMap;
+use crate::model::{self, optimize, Bounds, MeshData, SubMesh, Vertex};
+use crate::model::halfedge::{HalfEdgeMesh, NONE};
+
+// two triangles are only put back together as a quad if their normals are this close (about 25 degrees)
+const QUAD_FLATNESS: f32 = 0.9;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Scheme
+{
+	Loop,
+	CatmullClark
+}
+
+impl Scheme
+{
+	// Catmull-Clark if most of the surface is quads, Loop otherwise
+	pub fn for_mesh(data: &MeshData) -> Self
+	{
+		let mesh = HalfEdgeMesh::from_mesh_data(data);
+		let (faces, _) = polygons(&mesh);
+		let quad_triangles = faces.iter().filter(|face| face.len() == 4).count() * 2;
+
+		if quad_triangles * 2 > mesh.face_count() { Scheme::CatmullClark } else { Scheme::Loop }
+	}
+}
+
+// Returns a new mesh, `levels` times subdivided, with smooth normals and without lods.
+// Lines and points are carried over unchanged
+pub fn subdivide(data: &MeshData, scheme: Scheme, levels: usize) -> MeshData
+{
+	let cage = weld_cage(data);
+
+	let mut result = match scheme
+	{
+		Scheme::Loop => {
+			let mut current = cage;
+			for _ in 0..levels
+			{
+				current = loop_level(&current);
+			}
+			current
+		},
+		Scheme::CatmullClark => {
+			let mesh = HalfEdgeMesh::from_mesh_data(&cage);
+			let (mut faces, mut groups) = polygons(&mesh);
+			let mut vertices = mesh.vertices;
+			for _ in 0..levels
+			{
+				(vertices, faces, groups) = catmull_clark_level(&vertices, &faces, &groups);
+			}
+			assemble(vertices, &faces, &groups, &cage)
+		}
+	};
+
+	result.generate_normals();
+	optimize::optimize(&mut result);
+	keep_lines_and_points(data, &mut result);
+	result
+}
+
+fn keep_lines_and_points(data: &MeshData, result: &mut MeshData)
+{
+	let mut copied = HashMap::new();
+	let mut copy = |index: u32, vertices: &mut Vec<Vertex>| -> u32 {
+		*copied.entry(index).or_insert_with(|| {
+			vertices.push(data.vertices[index as usize]);
+			vertices.len() as u32 - 1
+		})
+	};
+	result.lines = data.lines.iter().map(|&i| copy(i, &mut result.vertices)).collect();
+	result.points = data.points.iter().map(|&i| copy(i, &mut result.vertices)).collect();
+	result.bounds = Bounds::from_vertices(&result.vertices);
+}
+
+// Merges the copies that only exist because of their normals (flat shaded files have one per face),
+// the normals are generated again afterwards anyway
+fn weld_cage(data: &MeshData) -> MeshData
+{
+	let mut cage = MeshData {
+		vertices: data.vertices.iter().map(|v| Vertex { normal: (0.0, 0.0, 0.0).into(), ..*v }).collect(),
+		lods: Vec::new(),
+		..data.clone()
+	};
+	optimize::weld(&mut cage, 0.0, optimize::WeldMode::KeepSeams);
+	cage
+}
+
+fn loop_level(data: &MeshData) -> MeshData
+{
+	let mesh = HalfEdgeMesh::from_mesh_data(data);
+	let vertices = &mesh.vertices;
+	let manifold = manifold_vertices(&mesh);
+
+	let mut out = Vec::with_capacity(vertices.len() + mesh.half_edge_count() / 2);
+	for v in 0..vertices.len() as u32
+	{
+		let vertex = &vertices[v as usize];
+		if mesh.outgoing(v) == NONE || !manifold[v as usize]
+		{
+			out.push(*vertex);
+			continue;
+		}
+
+		let ring: Vec<u32> = mesh.one_ring(v).collect();
+		if mesh.is_boundary_vertex(v)
+		{
+			let (first, last) = (ring[0], ring[ring.len() - 1]);
+			out.push(blend([(vertex, 0.75), (&vertices[first as usize], 0.125), (&vertices[last as usize], 0.125)]));
+		}
+		else
+		{
+			let n = ring.len() as f32;
+			let c = 0.375 + 0.25 * (2.0 * std::f32::consts::PI / n).cos();
+			let beta = (0.625 - c * c) / n;
+			out.push(blend(std::iter::once((vertex, 1.0 - n * beta)).chain(ring.iter().map(|&r| (&vertices[r as usize], beta)))));
+		}
+	}
+
+	// one new vertex per edge, shared by both of its half-edges
+	let mut edge_vertex = vec![NONE; mesh.half_edge_count()];
+	for h in 0..mesh.half_edge_count() as u32
+	{
+		if edge_vertex[h as usize] != NONE
+		{
+			continue;
+		}
+
+		let (a, b) = (&vertices[mesh.origin(h) as usize], &vertices[mesh.target(h) as usize]);
+		let t = mesh.twin(h);
+		let point = if t == NONE
+		{
+			blend([(a, 0.5), (b, 0.5)])
+		}
+		else
+		{
+			let c = &vertices[mesh.origin(mesh.prev(h)) as usize];
+			let d = &vertices[mesh.origin(mesh.prev(t)) as usize];
+			blend([(a, 0.375), (b, 0.375), (c, 0.125), (d, 0.125)])
+		};
+
+		edge_vertex[h as usize] = out.len() as u32;
+		if t != NONE
+		{
+			edge_vertex[t as usize] = out.len() as u32;
+		}
+		out.push(point);
+	}
+
+	let mut faces = Vec::with_capacity(mesh.face_count() * 4);
+	let mut groups = Vec::with_capacity(mesh.face_count() * 4);
+	for f in 0..mesh.face_count()
+	{
+		let [a, b, c] = mesh.face_vertices(f);
+		let [ab, bc, ca] = [edge_vertex[f * 3], edge_vertex[f * 3 + 1], edge_vertex[f * 3 + 2]];
+		faces.extend([vec![a, ab, ca], vec![ab, b, bc], vec![ca, bc, c], vec![ab, bc, ca]]);
+		groups.extend([mesh.face_group(f); 4]);
+	}
+
+	assemble(out, &faces, &groups, data)
+}
+
+fn catmull_clark_level(vertices: &[Vertex], faces: &[Vec<u32>], groups: &[u32]) -> (Vec<Vertex>, Vec<Vec<u32>>, Vec<u32>)
+{
+	let mut out: Vec<Vertex> = vertices.to_vec();
+
+	let face_points: Vec<u32> = faces
+		.iter()
+		.map(|face| {
+			let weight = 1.0 / face.len() as f32;
+			out.push(blend(face.iter().map(|&v| (&vertices[v as usize], weight))));
+			(out.len() - 1) as u32
+		})
+		.collect();
+
+	// faces around each edge, in the order they were first seen
+	let mut edges = HashMap::<(u32, u32), usize>::new();
+	let mut edge_faces = Vec::<Vec<usize>>::new();
+	let mut edge_ends = Vec::<(u32, u32)>::new();
+	for (f, face) in faces.iter().enumerate()
+	{
+		for i in 0..face.len()
+		{
+			let (a, b) = (face[i], face[(i + 1) % face.len()]);
+			let e = *edges.entry((a.min(b), a.max(b))).or_insert_with(|| {
+				edge_faces.push(Vec::new());
+				edge_ends.push((a, b));
+				edge_faces.len() - 1
+			});
+			edge_faces[e].push(f);
+		}
+	}
+
+	let edge_points: Vec<u32> = edge_faces
+		.iter()
+		.zip(&edge_ends)
+		.map(|(around, &(a, b))| {
+			let (a, b) = (&vertices[a as usize], &vertices[b as usize]);
+			out.push(match around.as_slice()
+			{
+				&[f1, f2] => blend([(a, 0.25), (b, 0.25), (&out[face_points[f1] as usize], 0.25), (&out[face_points[f2] as usize], 0.25)]),
+				_ => blend([(a, 0.5), (b, 0.5)])
+			});
+			(out.len() - 1) as u32
+		})
+		.collect();
+
+	let mut vertex_faces = vec![Vec::<usize>::new(); vertices.len()];
+	for (f, face) in faces.iter().enumerate()
+	{
+		for &v in face
+		{
+			vertex_faces[v as usize].push(f);
+		}
+	}
+	let mut vertex_edges = vec![Vec::<usize>::new(); vertices.len()];
+	for (e, &(a, b)) in edge_ends.iter().enumerate()
+	{
+		vertex_edges[a as usize].push(e);
+		vertex_edges[b as usize].push(e);
+	}
+
+	for v in 0..vertices.len()
+	{
+		let vertex = &vertices[v];
+		let other_end = |e: usize| {
+			let (a, b) = edge_ends[e];
+			&vertices[if a as usize == v { b } else { a } as usize]
+		};
+
+		let boundary: Vec<usize> = vertex_edges[v].iter().copied().filter(|&e| edge_faces[e].len() == 1).collect();
+		let non_manifold = vertex_edges[v].iter().any(|&e| edge_faces[e].len() > 2);
+		let n = vertex_faces[v].len();
+
+		out[v] = if non_manifold || n == 0
+		{
+			*vertex
+		}
+		else if boundary.len() == 2
+		{
+			blend([(vertex, 0.75), (other_end(boundary[0]), 0.125), (other_end(boundary[1]), 0.125)])
+		}
+		else if boundary.is_empty() && vertex_edges[v].len() == n
+		{
+			// (Q + 2R + (n - 3)P) / n, with Q the average face point and R the average edge midpoint
+			let n = n as f32;
+			let faces_weight = 1.0 / (n * n);
+			let edges_weight = 1.0 / (n * n);
+			blend(
+				std::iter::once((vertex, (n - 3.0) / n + 1.0 / n))
+					.chain(vertex_faces[v].iter().map(|&f| (&out[face_points[f] as usize], faces_weight)))
+					.chain(vertex_edges[v].iter().map(|&e| (other_end(e), edges_weight)))
+			)
+		}
+		else
+		{
+			// corners where boundaries meet, or several fans touching
+			*vertex
+		};
+	}
+
+	let mut new_faces = Vec::with_capacity(faces.len() * 4);
+	let mut new_groups = Vec::with_capacity(faces.len() * 4);
+	for (f, face) in faces.iter().enumerate()
+	{
+		let edge_point = |a: u32, b: u32| edge_points[edges[&(a.min(b), a.max(b))]];
+		for i in 0..face.len()
+		{
+			let previous = face[(i + face.len() - 1) % face.len()];
+			let next = face[(i + 1) % face.len()];
+			new_faces.push(vec![face[i], edge_point(face[i], next), face_points[f], edge_point(previous, face[i])]);
+			new_groups.push(groups[f]);
+		}
+	}
+
+	(out, new_faces, new_groups)
+}
+
+// Faces of the mesh as quads and triangles, and the submesh of each.
+// A fan triangulated quad is a pair (a, b, c), (a, c, d): the first triangle's closing edge is the
+// second one's first edge. Such links also happen between neighbouring quads, so each chain of them
+// is paired up every other link, starting wherever that gives the flattest quads
+fn polygons(mesh: &HalfEdgeMesh) -> (Vec<Vec<u32>>, Vec<u32>)
+{
+	let count = mesh.face_count();
+	let link = |f: usize| -> Option<usize> {
+		let t = mesh.twin(f as u32 * 3 + 2);
+		(t != NONE && t.is_multiple_of(3) && mesh.face_group(mesh.face(t)) == mesh.face_group(f)).then(|| mesh.face(t))
+	};
+
+	let mut has_previous = vec![false; count];
+	for f in 0..count
+	{
+		if let Some(next) = link(f)
+		{
+			has_previous[next] = true;
+		}
+	}
+
+	let mut visited = vec![false; count];
+	let mut partner = vec![None; count];
+	let mut is_second = vec![false; count];
+
+	// chain starts first, then whatever is left (chains that loop around)
+	for start in (0..count).filter(|&f| !has_previous[f]).chain(0..count)
+	{
+		if visited[start]
+		{
+			continue;
+		}
+
+		let mut chain = vec![start];
+		visited[start] = true;
+		while let Some(next) = link(chain[chain.len() - 1]).filter(|&next| !visited[next])
+		{
+			visited[next] = true;
+			chain.push(next);
+		}
+
+		let flatness: Vec<f32> = chain.windows(2).map(|pair| flatness(mesh, pair[0], pair[1])).collect();
+		let score = |parity: usize| flatness.iter().skip(parity).step_by(2).filter(|&&f| f >= QUAD_FLATNESS).sum::<f32>();
+		let parity = if score(1) > score(0) { 1 } else { 0 };
+
+		for i in (parity..flatness.len()).step_by(2).filter(|&i| flatness[i] >= QUAD_FLATNESS)
+		{
+			partner[chain[i]] = Some(chain[i + 1]);
+			is_second[chain[i + 1]] = true;
+		}
+	}
+
+	let mut faces = Vec::with_capacity(count);
+	let mut groups = Vec::with_capacity(count);
+	for f in (0..count).filter(|&f| !is_second[f])
+	{
+		let mut polygon = mesh.face_vertices(f).to_vec();
+		if let Some(second) = partner[f]
+		{
+			polygon.push(mesh.face_vertices(second)[2]);
+		}
+		faces.push(polygon);
+		groups.push(mesh.face_group(f));
+	}
+
+	(faces, groups)
+}
+
+// cosine of the angle between two faces' normals
+fn flatness(mesh: &HalfEdgeMesh, a: usize, b: usize) -> f32
+{
+	let normal = |f: usize| {
+		let [p0, p1, p2] = mesh.face_vertices(f).map(|v| mesh.vertices[v as usize].position());
+		let normal = model::cross(p1 - p0, p2 - p0);
+		let length = normal.length();
+		if length > 0.0 { normal / length } else { normal }
+	};
+
+	normal(a).dot(&normal(b))
+}
+
+// every vertex whose faces form a single fan
+fn manifold_vertices(mesh: &HalfEdgeMesh) -> Vec<bool>
+{
+	let mut around = vec![0usize; mesh.vertices.len()];
+	for h in 0..mesh.half_edge_count() as u32
+	{
+		around[mesh.origin(h) as usize] += 1;
+	}
+
+	(0..mesh.vertices.len() as u32).map(|v| mesh.outgoing_half_edges(v).count() == around[v as usize]).collect()
+}
+
+fn blend<'a>(weighted: impl IntoIterator<Item = (&'a Vertex, f32)>) -> Vertex
+{
+	let mut vertex = Vertex::new((0.0, 0.0, 0.0).into(), (0.0, 0.0, 0.0).into(), (0.0, 0.0).into());
+	for (v, weight) in weighted
+	{
+		vertex.position = vertex.position + v.position() * weight;
+		vertex.texcoord = vertex.texcoord + v.texcoord() * weight;
+		vertex.color = vertex.color + v.color() * weight;
+	}
+	vertex
+}
+
+// Triangulates the polygons as fans, grouped by submesh in the order of `source`'s submeshes
+fn assemble(vertices: Vec<Vertex>, faces: &[Vec<u32>], groups: &[u32], source: &MeshData) -> MeshData
+{
+	let mut indices = Vec::with_capacity(faces.len() * 6);
+	let mut submeshes = Vec::with_capacity(source.submeshes.len());
+
+	for (group, submesh) in source.submeshes.iter().enumerate()
+	{
+		let first = indices.len();
+		for face in faces.iter().zip(groups).filter(|(_, &g)| g == group as u32).map(|(face, _)| face)
+		{
+			for i in 1..face.len() - 1
+			{
+				indices.extend([face[0], face[i], face[i + 1]]);
+			}
+		}
+		submeshes.push(SubMesh { first: first as u32, count: (indices.len() - first) as u32, ..submesh.clone() });
+	}
+
+	let mut data = MeshData::new(vertices, indices);
+	data.submeshes = submeshes;
+	data.material_libs = source.material_libs.clone();
+	data
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::model::test_meshes::{cube, octahedron};
+	use crate::model::validate::{self, Kind};
+
+	fn assert_closed(data: &MeshData)
+	{
+		let report = validate::validate(data);
+		assert_eq!(report.count(Kind::BoundaryEdge), 0);
+		assert_eq!(report.count(Kind::NonManifoldEdge), 0);
+		assert_eq!(report.count(Kind::InconsistentWinding), 0);
+	}
+
+	#[test]
+	fn keeps_lines_and_points()
+	{
+		let mut data = octahedron();
+		data.vertices.push(Vertex::new((3.0, 0.0, 0.0).into(), (1.0, 0.0, 0.0).into(), (0.0, 0.0).into()));
+		data.lines = vec![0, 6];
+		data.points = vec![6, 2];
+
+		let result = subdivide(&data, Scheme::Loop, 2);
+		let position = |i: u32| result.vertices[i as usize].position();
+		assert_eq!(position(result.lines[0]), (1.0, 0.0, 0.0).into());
+		assert_eq!(position(result.lines[1]), (3.0, 0.0, 0.0).into());
+		assert_eq!(result.points[0], result.lines[1]);
+		assert_eq!(position(result.points[1]), (0.0, 1.0, 0.0).into());
+		assert_eq!(result.bounds.max().x(), 3.0);
+		assert!(!validate::validate(&result).has_errors());
+	}
+
+	#[test]
+	fn picks_scheme()
+	{
+		assert_eq!(Scheme::for_mesh(&cube()), Scheme::CatmullClark);
+		assert_eq!(Scheme::for_mesh(&octahedron()), Scheme::Loop);
+	}
+
+	#[test]
+	fn loop_subdivision()
+	{
+		let data = octahedron();
+		let once = subdivide(&data, Scheme::Loop, 1);
+		assert_eq!(once.indices.len() / 3, 8 * 4);
+		assert_eq!(once.vertices.len(), 6 + 12);
+		assert_closed(&once);
+
+		// the limit surface of the octahedron is inside it and gets rounder with every level
+		let twice = subdivide(&data, Scheme::Loop, 2);
+		assert_eq!(twice.indices.len() / 3, 8 * 16);
+		assert_closed(&twice);
+		let radius = |v: &Vertex| v.position().length();
+		let spread = |d: &MeshData| {
+			let lengths: Vec<f32> = d.vertices.iter().map(radius).collect();
+			lengths.iter().cloned().fold(f32::MIN, f32::max) - lengths.iter().cloned().fold(f32::MAX, f32::min)
+		};
+		assert!(twice.vertices.iter().all(|v| radius(v) < 1.0));
+		assert!(spread(&twice) < spread(&once));
+	}
+
+	#[test]
+	fn catmull_clark_cube()
+	{
+		let data = subdivide(&cube(), Scheme::CatmullClark, 1);
+		// 6 faces, 12 edges and 8 corners, 24 quads
+		assert_eq!(data.vertices.len(), 6 + 12 + 8);
+		assert_eq!(data.indices.len() / 3, 24 * 2);
+		assert_closed(&data);
+
+		// corners move to (Q + 2R) / 3 = ((1/3, 1/3, 1/3) + 2 (1/6, 1/6, 1/6)) / 3
+		let corner = data.vertices.iter().find(|v| v.position().x() < 0.3 && v.position().y() < 0.3 && v.position().z() < 0.3).unwrap();
+		let expected = 2.0 / 9.0;
+		assert!((corner.position().x() - expected).abs() < 1e-5, "{:?}", corner.position());
+
+		let twice = subdivide(&cube(), Scheme::CatmullClark, 2);
+		assert_eq!(twice.indices.len() / 3, 96 * 2);
+		assert_closed(&twice);
+	}
+
+	#[test]
+	fn boundaries_and_groups()
+	{
+		// open box (no top) in two submeshes
+		let mut data = cube();
+		data.indices.truncate(30);
+		data.submeshes = vec![
+			SubMesh { name: "bottom".into(), material: Some("a".into()), first: 0, count: 6 },
+			SubMesh { name: "sides".into(), material: None, first: 6, count: 24 }
+		];
+
+		for scheme in [Scheme::Loop, Scheme::CatmullClark]
+		{
+			let result = subdivide(&data, scheme, 2);
+			let report = validate::validate(&result);
+			assert_eq!(report.count(Kind::NonManifoldEdge), 0);
+			assert_eq!(report.count(Kind::InconsistentWinding), 0);
+			// the rim is the only boundary, and it stays in the z = 1 plane
+			assert!(report.count(Kind::BoundaryEdge) > 0);
+			assert_eq!(result.submeshes.len(), 2);
+			assert_eq!(result.submeshes[0].name, "bottom");
+			assert_eq!(result.submeshes[1].first, result.submeshes[0].count);
+			assert_eq!(result.indices.len() as u32, result.submeshes[1].first + result.submeshes[1].count);
+		}
+	}
+
+	#[test]
+	fn flat_shaded_copies_are_welded()
+	{
+		// every triangle with its own vertices, like stl files
+		let data = octahedron();
+		let vertices = data.indices.iter().map(|&i| data.vertices[i as usize]).collect();
+		let soup = MeshData::new(vertices, (0..data.indices.len() as u32).collect());
+
+		let result = subdivide(&soup, Scheme::Loop, 1);
+		assert_eq!(result.vertices.len(), 6 + 12);
+		assert_closed(&result);
+	}
+}
@@ -0,0 +1,41 @@
+// Small meshes the tests of the model modules share
+use crate::model::{MeshData, Vertex};
+
+// white, without texture coordinates
+pub fn vertex(position: (f32, f32, f32)) -> Vertex
+{
+	Vertex::new(position.into(), (1.0, 1.0, 1.0).into(), (0.0, 0.0).into())
+}
+
+pub fn mesh(positions: &[(f32, f32, f32)], indices: &[u32]) -> MeshData
+{
+	MeshData::new(positions.iter().map(|&p| vertex(p)).collect(), indices.to_vec())
+}
+
+// closed, consistently wound
+pub const TETRAHEDRON: [u32; 12] = [0, 2, 1, 0, 1, 3, 1, 2, 3, 2, 0, 3];
+
+// the tetrahedron's corners, with other indices to break it on purpose
+pub fn tetrahedron(indices: &[u32]) -> MeshData
+{
+	mesh(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)], indices)
+}
+
+// unit cube as 6 quads, fan triangulated the way the obj loader does it
+pub fn cube() -> MeshData
+{
+	let positions = [
+		(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0),
+		(0.0, 0.0, 1.0), (1.0, 0.0, 1.0), (1.0, 1.0, 1.0), (0.0, 1.0, 1.0)
+	];
+	let quads: [[u32; 4]; 6] = [[0, 3, 2, 1], [4, 5, 6, 7], [0, 1, 5, 4], [2, 3, 7, 6], [1, 2, 6, 5], [0, 4, 7, 3]];
+
+	let indices: Vec<u32> = quads.iter().flat_map(|q| [q[0], q[1], q[2], q[0], q[2], q[3]]).collect();
+	mesh(&positions, &indices)
+}
+
+pub fn octahedron() -> MeshData
+{
+	let positions = [(1.0, 0.0, 0.0), (-1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, -1.0, 0.0), (0.0, 0.0, 1.0), (0.0, 0.0, -1.0)];
+	mesh(&positions, &[0, 2, 4, 2, 1, 4, 1, 3, 4, 3, 0, 4, 2, 0, 5, 1, 2, 5, 3, 1, 5, 0, 3, 5])
+}
@@ -0,0 +1,412 @@
+// Checks a mesh for problems that either break rendering (indices past the end of the vertex buffer,
+// NaN positions) or only show up as artifacts (holes, flipped faces, z-fighting duplicates).
+// A 0 in an obj face is rejected by the obj parser already, with the line it is on.
+
+use std::collections::HashMap;
+use std::fmt;
+use crate::model::{self, MeshData};
+
+// how many examples of each kind of problem the summary lists
+const SUMMARY_EXAMPLES: usize = 5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind
+{
+	IndexOutOfRange,
+	TrailingIndices,
+	NanCoordinate,
+	DegenerateTriangle,
+	DuplicateTriangle,
+	NonManifoldEdge,
+	BoundaryEdge,
+	InconsistentWinding,
+	UnreferencedVertex,
+}
+
+impl Kind
+{
+	// problems that make drawing the mesh read garbage, instead of just looking wrong
+	pub fn is_error(&self) -> bool
+	{
+		matches!(self, Kind::IndexOutOfRange | Kind::TrailingIndices | Kind::NanCoordinate)
+	}
+
+	fn description(&self) -> &'static str
+	{
+		match self
+		{
+			Kind::IndexOutOfRange => "indices out of range",
+			Kind::TrailingIndices => "trailing indices that don't make a whole triangle",
+			Kind::NanCoordinate => "vertices with NaN or infinite coordinates",
+			Kind::DegenerateTriangle => "degenerate triangles",
+			Kind::DuplicateTriangle => "duplicate triangles",
+			Kind::NonManifoldEdge => "non-manifold edges",
+			Kind::BoundaryEdge => "boundary edges",
+			Kind::InconsistentWinding => "edges with inconsistent winding",
+			Kind::UnreferencedVertex => "unreferenced vertices"
+		}
+	}
+}
+
+// Triangles are numbered by their position in the index buffer (index / 3).
+// Edges use the first vertex at each position, so seams (same position, different uv/normal) don't count as boundaries
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Issue
+{
+	IndexOutOfRange { triangle: usize, index: u32 },
+	// in MeshData::lines or MeshData::points, `element` is the position in that list
+	LineIndexOutOfRange { element: usize, index: u32 },
+	PointIndexOutOfRange { element: usize, index: u32 },
+	TrailingIndices { count: usize },
+	NanCoordinate { vertex: usize },
+	// repeated corner or zero area
+	DegenerateTriangle { triangle: usize },
+	// same corners as an earlier triangle, in either winding
+	DuplicateTriangle { triangle: usize, first: usize },
+	NonManifoldEdge { a: u32, b: u32, faces: usize },
+	BoundaryEdge { a: u32, b: u32 },
+	// both triangles on the edge go along it the same way, so one of them faces the wrong side
+	InconsistentWinding { a: u32, b: u32 },
+	UnreferencedVertex { vertex: usize },
+}
+
+impl Issue
+{
+	pub fn kind(&self) -> Kind
+	{
+		match self
+		{
+			Issue::IndexOutOfRange { .. } | Issue::LineIndexOutOfRange { .. } | Issue::PointIndexOutOfRange { .. } => Kind::IndexOutOfRange,
+			Issue::TrailingIndices { .. } => Kind::TrailingIndices,
+			Issue::NanCoordinate { .. } => Kind::NanCoordinate,
+			Issue::DegenerateTriangle { .. } => Kind::DegenerateTriangle,
+			Issue::DuplicateTriangle { .. } => Kind::DuplicateTriangle,
+			Issue::NonManifoldEdge { .. } => Kind::NonManifoldEdge,
+			Issue::BoundaryEdge { .. } => Kind::BoundaryEdge,
+			Issue::InconsistentWinding { .. } => Kind::InconsistentWinding,
+			Issue::UnreferencedVertex { .. } => Kind::UnreferencedVertex
+		}
+	}
+}
+
+impl fmt::Display for Issue
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Issue::IndexOutOfRange { triangle, index } => write!(f, "triangle {} uses {}", triangle, index),
+			Issue::LineIndexOutOfRange { element, index } => write!(f, "line segment {} uses {}", element / 2, index),
+			Issue::PointIndexOutOfRange { element, index } => write!(f, "point {} uses {}", element, index),
+			Issue::TrailingIndices { count } => write!(f, "{} left over", count),
+			Issue::NanCoordinate { vertex } => write!(f, "vertex {}", vertex),
+			Issue::DegenerateTriangle { triangle } => write!(f, "triangle {}", triangle),
+			Issue::DuplicateTriangle { triangle, first } => write!(f, "triangle {} (same as {})", triangle, first),
+			Issue::NonManifoldEdge { a, b, faces } => write!(f, "{}-{} ({} faces)", a, b, faces),
+			Issue::BoundaryEdge { a, b } | Issue::InconsistentWinding { a, b } => write!(f, "{}-{}", a, b),
+			Issue::UnreferencedVertex { vertex } => write!(f, "vertex {}", vertex)
+		}
+	}
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report
+{
+	pub vertex_count: usize,
+	pub triangle_count: usize,
+	// sorted by kind, then by where they are in the mesh
+	pub issues: Vec<Issue>
+}
+
+impl Report
+{
+	pub fn is_clean(&self) -> bool
+	{
+		self.issues.is_empty()
+	}
+
+	pub fn has_errors(&self) -> bool
+	{
+		self.issues.iter().any(|issue| issue.kind().is_error())
+	}
+
+	pub fn count(&self, kind: Kind) -> usize
+	{
+		self.issues.iter().filter(|issue| issue.kind() == kind).count()
+	}
+
+	pub fn of_kind(&self, kind: Kind) -> impl Iterator<Item = &Issue>
+	{
+		self.issues.iter().filter(move |issue| issue.kind() == kind)
+	}
+}
+
+impl fmt::Display for Report
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		write!(f, "{} vertices, {} triangles", self.vertex_count, self.triangle_count)?;
+		if self.is_clean()
+		{
+			return write!(f, ", no problems found");
+		}
+		write!(f, ", {} problems:", self.issues.len())?;
+
+		let mut issues = self.issues.as_slice();
+		while let Some(first) = issues.first()
+		{
+			let kind = first.kind();
+			let count = issues.iter().take_while(|issue| issue.kind() == kind).count();
+			let examples: Vec<String> = issues[..count].iter().take(SUMMARY_EXAMPLES).map(|issue| issue.to_string()).collect();
+
+			write!(f, "\n  {}{} {}: {}", if kind.is_error() { "error: " } else { "" }, count, kind.description(), examples.join(", "))?;
+			if count > SUMMARY_EXAMPLES
+			{
+				write!(f, ", ...")?;
+			}
+
+			issues = &issues[count..];
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Default)]
+struct EdgeUse
+{
+	faces: usize,
+	// how many of them go from the lower vertex to the higher one
+	forward: usize
+}
+
+pub fn validate(data: &MeshData) -> Report
+{
+	let vertex_count = data.vertices.len();
+	let mut issues = Vec::new();
+
+	for (vertex, v) in data.vertices.iter().enumerate()
+	{
+		let p = v.position();
+		if !(p.x().is_finite() && p.y().is_finite() && p.z().is_finite())
+		{
+			issues.push(Issue::NanCoordinate { vertex });
+		}
+	}
+
+	let trailing = data.indices.len() % 3;
+	if trailing != 0
+	{
+		issues.push(Issue::TrailingIndices { count: trailing });
+	}
+
+	// first vertex at each position, for the topology checks
+	let mut by_position = HashMap::<[u32; 3], u32>::new();
+	let canonical: Vec<u32> = data.vertices
+		.iter()
+		.enumerate()
+		.map(|(i, v)| {
+			let p = v.position();
+			*by_position.entry([p.x().to_bits(), p.y().to_bits(), p.z().to_bits()]).or_insert(i as u32)
+		})
+		.collect();
+
+	let mut referenced = vec![false; vertex_count];
+	let mut triangles = HashMap::<[u32; 3], usize>::new();
+	let mut edges = HashMap::<(u32, u32), EdgeUse>::new();
+
+	for (triangle, corners) in data.indices.chunks_exact(3).enumerate()
+	{
+		let mut in_range = true;
+		for &index in corners
+		{
+			match referenced.get_mut(index as usize)
+			{
+				Some(referenced) => *referenced = true,
+				None => {
+					issues.push(Issue::IndexOutOfRange { triangle, index });
+					in_range = false;
+				}
+			}
+		}
+		if !in_range
+		{
+			continue;
+		}
+
+		let [a, b, c] = [canonical[corners[0] as usize], canonical[corners[1] as usize], canonical[corners[2] as usize]];
+		let p0 = data.vertices[a as usize].position();
+		let area = model::cross(data.vertices[b as usize].position() - p0, data.vertices[c as usize].position() - p0).length();
+		if a == b || b == c || a == c || area == 0.0
+		{
+			issues.push(Issue::DegenerateTriangle { triangle });
+			continue;
+		}
+
+		let mut key = [a, b, c];
+		key.sort_unstable();
+		if let Some(&first) = triangles.get(&key)
+		{
+			// already counted by the first one, it would only make its edges look non-manifold
+			issues.push(Issue::DuplicateTriangle { triangle, first });
+			continue;
+		}
+		triangles.insert(key, triangle);
+
+		for (from, to) in [(a, b), (b, c), (c, a)]
+		{
+			let edge = edges.entry((from.min(to), from.max(to))).or_default();
+			edge.faces += 1;
+			if from < to
+			{
+				edge.forward += 1;
+			}
+		}
+	}
+
+	let mut edges: Vec<_> = edges.into_iter().collect();
+	edges.sort_unstable_by_key(|&(key, _)| key);
+	for ((a, b), edge) in edges
+	{
+		match edge.faces
+		{
+			1 => issues.push(Issue::BoundaryEdge { a, b }),
+			2 if edge.forward != 1 => issues.push(Issue::InconsistentWinding { a, b }),
+			2 => {},
+			faces => issues.push(Issue::NonManifoldEdge { a, b, faces })
+		}
+	}
+
+	for (element, &index) in data.lines.iter().enumerate()
+	{
+		match referenced.get_mut(index as usize)
+		{
+			Some(referenced) => *referenced = true,
+			None => issues.push(Issue::LineIndexOutOfRange { element, index })
+		}
+	}
+	for (element, &index) in data.points.iter().enumerate()
+	{
+		match referenced.get_mut(index as usize)
+		{
+			Some(referenced) => *referenced = true,
+			None => issues.push(Issue::PointIndexOutOfRange { element, index })
+		}
+	}
+
+	for (vertex, referenced) in referenced.into_iter().enumerate()
+	{
+		if !referenced
+		{
+			issues.push(Issue::UnreferencedVertex { vertex });
+		}
+	}
+
+	// stable, so each kind keeps the order it was found in
+	issues.sort_by_key(|issue| issue.kind());
+
+	Report {
+		vertex_count,
+		triangle_count: data.indices.len() / 3,
+		issues
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::model::test_meshes::{mesh, tetrahedron, vertex, TETRAHEDRON};
+
+	#[test]
+	fn closed_mesh_is_clean()
+	{
+		let report = validate(&tetrahedron(&TETRAHEDRON));
+		assert!(report.is_clean(), "{}", report);
+		assert_eq!(report.triangle_count, 4);
+		assert!(report.to_string().ends_with("no problems found"));
+	}
+
+	#[test]
+	fn lines_and_points()
+	{
+		// vertices only used by a line or a point are referenced
+		let mut data = mesh(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0), (5.0, 0.0, 0.0), (6.0, 0.0, 0.0)], &TETRAHEDRON);
+		data.lines = vec![2, 4];
+		data.points = vec![5];
+		assert!(validate(&data).is_clean());
+
+		data.lines = vec![2, 4, 4, 7];
+		data.points = vec![5, 9];
+		let report = validate(&data);
+		assert!(report.has_errors());
+		assert_eq!(report.of_kind(Kind::IndexOutOfRange).collect::<Vec<_>>(), vec![
+			&Issue::LineIndexOutOfRange { element: 3, index: 7 },
+			&Issue::PointIndexOutOfRange { element: 1, index: 9 }
+		]);
+	}
+
+	#[test]
+	fn index_errors()
+	{
+		let mut indices = TETRAHEDRON.to_vec();
+		indices.extend_from_slice(&[0, 1, 9, 2]);
+		let report = validate(&tetrahedron(&indices));
+
+		assert!(report.has_errors());
+		assert_eq!(report.of_kind(Kind::IndexOutOfRange).collect::<Vec<_>>(), vec![&Issue::IndexOutOfRange { triangle: 4, index: 9 }]);
+		assert_eq!(report.count(Kind::TrailingIndices), 1);
+		// the bad triangle is left out of the topology checks
+		assert_eq!(report.count(Kind::BoundaryEdge), 0);
+	}
+
+	#[test]
+	fn open_and_flipped()
+	{
+		// last face missing, and the third one flipped
+		let report = validate(&tetrahedron(&[0, 2, 1, 0, 1, 3, 1, 3, 2]));
+
+		assert!(!report.has_errors());
+		assert_eq!(report.count(Kind::BoundaryEdge), 3);
+		assert_eq!(report.of_kind(Kind::InconsistentWinding).collect::<Vec<_>>(), vec![
+			&Issue::InconsistentWinding { a: 1, b: 2 },
+			&Issue::InconsistentWinding { a: 1, b: 3 }
+		]);
+	}
+
+	#[test]
+	fn degenerate_duplicate_and_non_manifold()
+	{
+		let mut indices = TETRAHEDRON.to_vec();
+		// repeated corner, then the first face again wound the other way, then a fin on edge 0-1
+		indices.extend_from_slice(&[0, 0, 1, 0, 1, 2, 0, 1, 4]);
+		let mut data = mesh(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0), (0.0, -1.0, 0.0), (5.0, 5.0, 5.0)], &indices);
+		data.vertices[5] = vertex((f32::NAN, 0.0, 0.0));
+
+		let report = validate(&data);
+		assert_eq!(report.of_kind(Kind::DegenerateTriangle).collect::<Vec<_>>(), vec![&Issue::DegenerateTriangle { triangle: 4 }]);
+		assert_eq!(report.of_kind(Kind::DuplicateTriangle).collect::<Vec<_>>(), vec![&Issue::DuplicateTriangle { triangle: 5, first: 0 }]);
+		assert_eq!(report.of_kind(Kind::NonManifoldEdge).collect::<Vec<_>>(), vec![&Issue::NonManifoldEdge { a: 0, b: 1, faces: 3 }]);
+		assert_eq!(report.of_kind(Kind::NanCoordinate).collect::<Vec<_>>(), vec![&Issue::NanCoordinate { vertex: 5 }]);
+		assert_eq!(report.of_kind(Kind::UnreferencedVertex).collect::<Vec<_>>(), vec![&Issue::UnreferencedVertex { vertex: 5 }]);
+
+		let summary = report.to_string();
+		assert!(summary.contains("error: 1 vertices with NaN"), "{}", summary);
+		assert!(summary.contains("1 non-manifold edges: 0-1 (3 faces)"), "{}", summary);
+	}
+
+	#[test]
+	fn seams_are_not_boundaries()
+	{
+		// square made of two triangles that don't share vertices, only positions
+		let data = mesh(
+			&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0)],
+			&[0, 1, 2, 3, 4, 5]
+		);
+		let report = validate(&data);
+
+		// only the outline of the square, not the diagonal
+		assert_eq!(report.count(Kind::BoundaryEdge), 4);
+		assert_eq!(report.count(Kind::InconsistentWinding), 0);
+	}
+}
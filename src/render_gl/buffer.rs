@@ -52,6 +52,24 @@ impl<const BUFFER_TYPE: gl::types::GLenum> Buffer<BUFFER_TYPE>
 			);
 		}
 	}
+
+	// same as static_draw_data, with the parts one after another
+	pub fn static_draw_parts<T>(&self, parts: &[&[T]])
+	{
+		let size = parts.iter().map(|part| std::mem::size_of_val(*part)).sum::<usize>();
+		unsafe
+		{
+			gl::BufferData(BUFFER_TYPE, size as gl::types::GLsizeiptr, std::ptr::null(), gl::STATIC_DRAW);
+
+			let mut offset = 0;
+			for part in parts
+			{
+				let bytes = std::mem::size_of_val(*part);
+				gl::BufferSubData(BUFFER_TYPE, offset as gl::types::GLintptr, bytes as gl::types::GLsizeiptr, part.as_ptr() as *const gl::types::GLvoid);
+				offset += bytes;
+			}
+		}
+	}
 }
 
 impl<const BUFFER_TYPE: gl::types::GLenum> Drop for Buffer<BUFFER_TYPE>
@@ -42,19 +42,6 @@ impl f32_f32
 	{
 		f32_f32 { d0, d1 }
 	}
-
-	pub unsafe fn vertex_attrib_pointer(stride: usize, location: usize, offset: usize)
-	{
-		gl::VertexAttribPointer(
-			location as gl::types::GLuint,
-			2,
-			gl::FLOAT,
-			gl::FALSE,
-			stride as gl::types::GLint,
-			offset as *const gl::types::GLvoid
-		);
-		gl::EnableVertexAttribArray(location as gl::types::GLuint);
-	}
 }
 
 impl From<(f32, f32)> for f32_f32
@@ -82,20 +69,6 @@ impl f32_f32_f32
 	{
 		f32_f32_f32 { d0, d1, d2 }
 	}
-
-	pub unsafe fn vertex_attrib_pointer(stride: usize, location: usize, offset: usize)
-	{
-		gl::EnableVertexAttribArray(location as gl::types::GLuint);
-		gl::VertexAttribPointer(
-			location as gl::types::GLuint, // index of the generic vertex attribute
-			3, // Number of components per generic vertex attribute
-			gl::FLOAT,
-			gl::FALSE,
-			stride as gl::types::GLint, // offset between consecutive attributes
-			offset as *const gl::types::GLvoid // offset of the first component
-		);
-	}
-
 }
 
 
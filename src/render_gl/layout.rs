@@ -0,0 +1,142 @@
+// Vertex formats described as data, so setting up a vertex array needs no pointer math.
+// Derive VertexLayout on a #[repr(C)] (or packed) struct, every field type has to implement AttributeType.
+
+use super::buffer::{ArrayBuffer, VertexArray};
+use super::data;
+
+// One attribute of a vertex format
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VertexAttribute
+{
+	pub location: u32,
+	pub components: i32,
+	// type of each component, gl::FLOAT, gl::UNSIGNED_BYTE, ...
+	pub kind: gl::types::GLenum,
+	// integers read as 0..1 floats, instead of as integers
+	pub normalized: bool,
+	// bytes from the start of the vertex
+	pub offset: usize
+}
+
+// Types that can be a field of a vertex
+pub trait AttributeType
+{
+	const COMPONENTS: i32;
+	const KIND: gl::types::GLenum;
+}
+
+pub trait VertexLayout: Sized
+{
+	const ATTRIBUTES: &'static [VertexAttribute];
+}
+
+pub use vertex_derive::VertexLayout;
+
+macro_rules! attribute_type
+{
+	($type:ty, $components:expr, $kind:expr) => {
+		impl AttributeType for $type
+		{
+			const COMPONENTS: i32 = $components;
+			const KIND: gl::types::GLenum = $kind;
+		}
+	};
+}
+
+attribute_type!(f32, 1, gl::FLOAT);
+attribute_type!(i32, 1, gl::INT);
+attribute_type!(u32, 1, gl::UNSIGNED_INT);
+attribute_type!(math::vector::Vector2, 2, gl::FLOAT);
+attribute_type!(math::vector::Vector3, 3, gl::FLOAT);
+attribute_type!(math::vector::Vector4, 4, gl::FLOAT);
+attribute_type!(data::f32_f32, 2, gl::FLOAT);
+attribute_type!(data::f32_f32_f32, 3, gl::FLOAT);
+attribute_type!(data::uint_uint_uint, 3, gl::UNSIGNED_INT);
+
+impl<const N: usize> AttributeType for [f32; N]
+{
+	const COMPONENTS: i32 = N as i32;
+	const KIND: gl::types::GLenum = gl::FLOAT;
+}
+
+// normalized, these are colors
+impl<const N: usize> AttributeType for [u8; N]
+{
+	const COMPONENTS: i32 = N as i32;
+	const KIND: gl::types::GLenum = gl::UNSIGNED_BYTE;
+}
+
+impl VertexArray
+{
+	// Points the attributes of T at vbo, which holds an array of T
+	pub fn set_layout<T: VertexLayout>(&self, vbo: &ArrayBuffer)
+	{
+		self.bind();
+		vbo.bind();
+
+		let stride = std::mem::size_of::<T>() as gl::types::GLsizei;
+		for attribute in T::ATTRIBUTES
+		{
+			let integer = matches!(attribute.kind, gl::BYTE | gl::UNSIGNED_BYTE | gl::SHORT | gl::UNSIGNED_SHORT | gl::INT | gl::UNSIGNED_INT);
+			let offset = attribute.offset as *const gl::types::GLvoid;
+			unsafe
+			{
+				gl::EnableVertexAttribArray(attribute.location);
+				if integer && !attribute.normalized
+				{
+					// the shader reads ints, VertexAttribPointer would convert them to floats
+					gl::VertexAttribIPointer(attribute.location, attribute.components, attribute.kind, stride, offset);
+				}
+				else
+				{
+					let normalized = if attribute.normalized { gl::TRUE } else { gl::FALSE };
+					gl::VertexAttribPointer(attribute.location, attribute.components, attribute.kind, normalized, stride, offset);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::model::Vertex;
+
+	#[derive(VertexLayout)]
+	#[repr(C, packed)]
+	#[allow(dead_code)]
+	struct Particle
+	{
+		position: data::f32_f32_f32,
+		#[vertex(skip)]
+		id: u32,
+		#[vertex(location = 4, normalized)]
+		color: [u8; 4],
+		size: f32
+	}
+
+	fn summary(attributes: &[VertexAttribute]) -> Vec<(u32, i32, gl::types::GLenum, bool, usize)>
+	{
+		attributes.iter().map(|a| (a.location, a.components, a.kind, a.normalized, a.offset)).collect()
+	}
+
+	#[test]
+	fn derived_layouts()
+	{
+		assert_eq!(summary(Particle::ATTRIBUTES), vec![
+			(0, 3, gl::FLOAT, false, 0),
+			(4, 4, gl::UNSIGNED_BYTE, true, 16),
+			(5, 1, gl::FLOAT, false, 20)
+		]);
+
+		// what triangle.vert reads
+		assert_eq!(summary(Vertex::ATTRIBUTES), vec![
+			(0, 3, gl::FLOAT, false, 0),
+			(1, 3, gl::FLOAT, false, 12),
+			(2, 2, gl::FLOAT, false, 24),
+			(3, 3, gl::FLOAT, false, 32),
+			(4, 4, gl::FLOAT, false, 44)
+		]);
+	}
+}
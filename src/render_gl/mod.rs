@@ -2,8 +2,14 @@ pub mod data;
 pub mod buffer;
 pub mod texture;
 
+mod layout;
+mod reflect;
 mod shader;
+mod uniform;
 mod viewport;
+mod watch;
 
+pub use self::layout::{VertexAttribute, AttributeType, VertexLayout};
+pub use self::reflect::{Variable, LayoutError};
 pub use self::shader::{Shader, Program, Error};
 pub use self::viewport::Viewport;
\ No newline at end of file
@@ -0,0 +1,176 @@
+use std::fmt;
+use super::layout::VertexAttribute;
+
+// An active attribute or uniform of a linked program, as gl reports it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable
+{
+	// arrays are reported with [0] after the name
+	pub name: String,
+	// gl::FLOAT_VEC3, gl::FLOAT_MAT4, gl::SAMPLER_2D, ...
+	pub kind: gl::types::GLenum,
+	// number of elements, 1 unless it is an array
+	pub size: i32,
+	// -1 for uniforms in a uniform block, and built-ins like gl_VertexID
+	pub location: i32
+}
+
+impl Variable
+{
+	// components of one element, for the scalar and vector types
+	pub fn components(&self) -> Option<i32>
+	{
+		components(self.kind)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayoutError
+{
+	// the shader reads an attribute no buffer provides, it would read a constant instead
+	Missing {
+		name: String,
+		location: i32
+	},
+	// the buffer has a different number of components than the shader reads
+	Components {
+		name: String,
+		location: i32,
+		expected: i32,
+		provided: i32
+	}
+}
+
+impl fmt::Display for LayoutError
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			LayoutError::Missing { name, location } =>
+				write!(f, "attribute {} (location {}) is not provided by the vertex layout", name, location),
+			LayoutError::Components { name, location, expected, provided } =>
+				write!(f, "attribute {} (location {}) has {} components, the vertex layout provides {}", name, location, expected, provided)
+		}
+	}
+}
+
+// Compares the attributes a program reads with what a vertex layout provides.
+// Attributes of types without a plain component count (matrices) are only checked for being there
+pub fn check_layout(attributes: &[Variable], layout: &[VertexAttribute]) -> Vec<LayoutError>
+{
+	attributes
+		.iter()
+		// built-ins have no location and aren't fed from buffers
+		.filter(|attribute| attribute.location >= 0)
+		.filter_map(|attribute| {
+			let provided = layout.iter().find(|a| a.location as i32 == attribute.location);
+			match (provided, attribute.components())
+			{
+				(None, _) => Some(LayoutError::Missing { name: attribute.name.clone(), location: attribute.location }),
+				(Some(provided), Some(expected)) if provided.components != expected => Some(LayoutError::Components {
+					name: attribute.name.clone(),
+					location: attribute.location,
+					expected,
+					provided: provided.components
+				}),
+				_ => None
+			}
+		})
+		.collect()
+}
+
+pub fn components(kind: gl::types::GLenum) -> Option<i32>
+{
+	match kind
+	{
+		gl::FLOAT | gl::INT | gl::UNSIGNED_INT | gl::DOUBLE | gl::BOOL => Some(1),
+		gl::FLOAT_VEC2 | gl::INT_VEC2 | gl::UNSIGNED_INT_VEC2 | gl::DOUBLE_VEC2 | gl::BOOL_VEC2 => Some(2),
+		gl::FLOAT_VEC3 | gl::INT_VEC3 | gl::UNSIGNED_INT_VEC3 | gl::DOUBLE_VEC3 | gl::BOOL_VEC3 => Some(3),
+		gl::FLOAT_VEC4 | gl::INT_VEC4 | gl::UNSIGNED_INT_VEC4 | gl::DOUBLE_VEC4 | gl::BOOL_VEC4 => Some(4),
+		_ => None
+	}
+}
+
+// ACTIVE_ATTRIBUTES with GetActiveAttrib/GetAttribLocation, or ACTIVE_UNIFORMS with GetActiveUniform/GetUniformLocation
+pub fn active_variables(program: gl::types::GLuint, uniforms: bool) -> Vec<Variable>
+{
+	let (count_param, length_param) = if uniforms
+	{
+		(gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH)
+	}
+	else
+	{
+		(gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH)
+	};
+
+	let mut count: gl::types::GLint = 0;
+	let mut max_length: gl::types::GLint = 0;
+	unsafe
+	{
+		gl::GetProgramiv(program, count_param, &mut count);
+		gl::GetProgramiv(program, length_param, &mut max_length);
+	}
+
+	(0..count as gl::types::GLuint)
+		.map(|index| {
+			let mut name = vec![0u8; max_length.max(1) as usize];
+			let mut length: gl::types::GLsizei = 0;
+			let mut size: gl::types::GLint = 0;
+			let mut kind: gl::types::GLenum = 0;
+			let location = unsafe
+			{
+				let get = if uniforms { gl::GetActiveUniform } else { gl::GetActiveAttrib };
+				get(program, index, name.len() as gl::types::GLsizei, &mut length, &mut size, &mut kind, name.as_mut_ptr() as *mut gl::types::GLchar);
+				if uniforms
+				{
+					gl::GetUniformLocation(program, name.as_ptr() as *const gl::types::GLchar)
+				}
+				else
+				{
+					gl::GetAttribLocation(program, name.as_ptr() as *const gl::types::GLchar)
+				}
+			};
+			name.truncate(length as usize);
+
+			Variable { name: String::from_utf8_lossy(&name).into_owned(), kind, size, location }
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn attribute(name: &str, kind: gl::types::GLenum, location: i32) -> Variable
+	{
+		Variable { name: name.to_string(), kind, size: 1, location }
+	}
+
+	fn provided(location: u32, components: i32) -> VertexAttribute
+	{
+		VertexAttribute { location, components, kind: gl::FLOAT, normalized: false, offset: 0 }
+	}
+
+	#[test]
+	fn layout_mismatches()
+	{
+		let attributes = [
+			attribute("Position", gl::FLOAT_VEC3, 0),
+			attribute("TexCoord", gl::FLOAT_VEC2, 2),
+			attribute("Tangent", gl::FLOAT_VEC4, 5),
+			attribute("Instance", gl::FLOAT_MAT4, 6),
+			attribute("gl_VertexID", gl::INT, -1)
+		];
+		let layout = [provided(0, 3), provided(1, 3), provided(2, 3), provided(6, 4)];
+
+		// unused buffer attributes (1) are fine, the shader just doesn't read them
+		assert_eq!(check_layout(&attributes, &layout), vec![
+			LayoutError::Components { name: "TexCoord".into(), location: 2, expected: 2, provided: 3 },
+			LayoutError::Missing { name: "Tangent".into(), location: 5 }
+		]);
+		assert!(check_layout(&attributes[..1], &layout).is_empty());
+		assert_eq!(components(gl::SAMPLER_2D), None);
+	}
+}
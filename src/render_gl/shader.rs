@@ -1,8 +1,13 @@
 use gl;
 use std;
+use std::fmt;
 use::std::ffi::{CStr, CString};
 
 use crate::resources::{Resources, self};
+use super::layout::VertexAttribute;
+use super::reflect::{self, Variable, LayoutError};
+use super::uniform::Locations;
+use super::watch::Watch;
 
 #[derive(Debug)]
 pub enum Error
@@ -24,10 +29,30 @@ pub enum Error
 	}
 }
 
+impl fmt::Display for Error
+{
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+	{
+		match self
+		{
+			Error::ResourceLoad { name, inner } => write!(f, "failed to load {}: {:?}", name, inner),
+			Error::CanNotDetermineShaderTypeForResource { name } => write!(f, "{} is not a .vert or .frag file", name),
+			Error::CompileError { name, message } => write!(f, "failed to compile {}:\n{}", name, message.trim_end()),
+			Error::LinkError { name, message } => write!(f, "failed to link {}:\n{}", name, message.trim_end())
+		}
+	}
+}
+
 #[derive(Clone)]
 pub struct Program
 {
 	id: gl::types::GLuint,
+	locations: Locations,
+	// what the linker kept, see attributes() and uniforms()
+	attributes: Vec<Variable>,
+	uniforms: Vec<Variable>,
+	// the files to rebuild it from when they change, for programs loaded with from_res
+	watch: Option<Watch>,
 }
 
 impl Program
@@ -41,16 +66,40 @@ impl Program
             .iter()
             .map(|file_extension| format!("{}{}", name, file_extension))
             .collect::<Vec<String>>();
+        let watch = Watch::new(res, name, &resource_names);
 
         let shaders = resource_names
             .iter()
             .map(|resource_name| Shader::from_res(res, resource_name))
             .collect::<Result<Vec<Shader>, Error>>()?;
 
-        Program::from_shaders(&shaders[..]).map_err(|message| Error::LinkError {
+        let mut program = Program::from_shaders(&shaders[..]).map_err(|message| Error::LinkError {
             name: name.into(),
             message,
-        })
+        })?;
+        program.watch = Some(watch);
+
+        Ok(program)
+	}
+
+	// Rebuilds the program if a file it was loaded from changed since it was loaded (or last checked).
+	// Ok(true) when it was replaced, if the new version doesn't compile or link this one is kept
+	pub fn reload_if_changed(&mut self) -> Result<bool, Error>
+	{
+		let watch = match &mut self.watch
+		{
+			Some(watch) => watch,
+			None => return Ok(false)
+		};
+		if !watch.changed()
+		{
+			return Ok(false);
+		}
+		let watch = watch.clone();
+
+		// the old gl program is deleted when it is dropped here
+		*self = Program::from_res(watch.resources(), watch.name())?;
+		Ok(true)
 	}
 
 	pub fn from_shaders(shaders: &[Shader]) -> Result<Program, String>
@@ -103,7 +152,13 @@ impl Program
 			unsafe { gl::DetachShader(program_id, shader.id()); }
 		}
 
-		Ok(Program { id: program_id })
+		Ok(Program {
+			id: program_id,
+			locations: Locations::default(),
+			attributes: reflect::active_variables(program_id, false),
+			uniforms: reflect::active_variables(program_id, true),
+			watch: None
+		})
 	}
 
 	pub fn id(&self) -> gl::types::GLuint
@@ -118,6 +173,112 @@ impl Program
 			gl::UseProgram(self.id);
 		}
 	}
+
+	// active vertex attributes, built-ins (gl_VertexID, ...) have location -1
+	pub fn attributes(&self) -> &[Variable]
+	{
+		&self.attributes
+	}
+
+	// active uniforms, arrays once with [0] after the name
+	pub fn uniforms(&self) -> &[Variable]
+	{
+		&self.uniforms
+	}
+
+	// Checks that `layout` feeds every attribute the program reads, with the number of components it expects
+	pub fn check_layout(&self, layout: &[VertexAttribute]) -> Result<(), Vec<LayoutError>>
+	{
+		let errors = reflect::check_layout(&self.attributes, layout);
+		if errors.is_empty() { Ok(()) } else { Err(errors) }
+	}
+
+	// Location of a uniform, cached after the first lookup. None (and a warning the first time)
+	// if the program doesn't have it
+	pub fn uniform_location(&self, name: &str) -> Option<gl::types::GLint>
+	{
+		self.locations.get(name, |c_name| unsafe { gl::GetUniformLocation(self.id, c_name.as_ptr()) })
+	}
+
+	// The setters write to the program that is in use, call set_used first
+
+	pub fn set_mat4(&self, name: &str, value: &math::matrix::Matrix4)
+	{
+		self.set_mat4_array(name, std::slice::from_ref(value));
+	}
+
+	pub fn set_vec3(&self, name: &str, value: math::vector::Vector3)
+	{
+		self.set_vec3_array(name, &[value]);
+	}
+
+	pub fn set_f32(&self, name: &str, value: f32)
+	{
+		self.set_f32_array(name, &[value]);
+	}
+
+	pub fn set_i32(&self, name: &str, value: i32)
+	{
+		self.set_i32_array(name, &[value]);
+	}
+
+	pub fn set_bool(&self, name: &str, value: bool)
+	{
+		self.set_i32(name, value as i32);
+	}
+
+	// texture unit the sampler reads from, 0 for gl::TEXTURE0
+	pub fn set_sampler(&self, name: &str, unit: u32)
+	{
+		self.set_i32(name, unit as i32);
+	}
+
+	pub fn set_mat4_array(&self, name: &str, values: &[math::matrix::Matrix4])
+	{
+		if let Some(location) = self.uniform_location(name)
+		{
+			// we store matrices in rows, opengl expects columns
+			let transposed: Vec<math::matrix::Matrix4> = values.iter().map(|m| m.transposed()).collect();
+			unsafe
+			{
+				gl::UniformMatrix4fv(location, values.len() as gl::types::GLsizei, gl::FALSE, transposed.as_ptr() as *const f32);
+			}
+		}
+	}
+
+	pub fn set_vec3_array(&self, name: &str, values: &[math::vector::Vector3])
+	{
+		if let Some(location) = self.uniform_location(name)
+		{
+			let floats: Vec<f32> = values.iter().flat_map(|v| [v.x(), v.y(), v.z()]).collect();
+			unsafe
+			{
+				gl::Uniform3fv(location, values.len() as gl::types::GLsizei, floats.as_ptr());
+			}
+		}
+	}
+
+	pub fn set_f32_array(&self, name: &str, values: &[f32])
+	{
+		if let Some(location) = self.uniform_location(name)
+		{
+			unsafe
+			{
+				gl::Uniform1fv(location, values.len() as gl::types::GLsizei, values.as_ptr());
+			}
+		}
+	}
+
+	pub fn set_i32_array(&self, name: &str, values: &[i32])
+	{
+		if let Some(location) = self.uniform_location(name)
+		{
+			unsafe
+			{
+				gl::Uniform1iv(location, values.len() as gl::types::GLsizei, values.as_ptr());
+			}
+		}
+	}
 }
 
 impl Drop for Program
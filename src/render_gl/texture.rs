@@ -23,9 +23,14 @@ impl Texture
 
 	pub fn load(&self, path: &str)
 	{
-		self.bind();
-
 		let img = image::open(Path::new(path)).unwrap().into_rgba8();
+		self.load_image(&img);
+	}
+
+	// uploads an image that is already decoded, e.g. one embedded in a model file
+	pub fn load_image(&self, img: &image::RgbaImage)
+	{
+		self.bind();
 
 		unsafe
 		{
@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+
+// Uniform locations of one program, looked up by name the first time they are used.
+// Names the program doesn't have are remembered too, so the warning about them is printed once
+#[derive(Clone, Debug, Default)]
+pub struct Locations
+{
+	// None for uniforms that aren't active in the program
+	cache: RefCell<HashMap<String, Option<gl::types::GLint>>>
+}
+
+impl Locations
+{
+	// `lookup` asks gl for the location, it is only called for names that aren't cached yet
+	pub fn get(&self, name: &str, lookup: impl FnOnce(&CStr) -> gl::types::GLint) -> Option<gl::types::GLint>
+	{
+		if let Some(&location) = self.cache.borrow().get(name)
+		{
+			return location;
+		}
+
+		let location = match CString::new(name)
+		{
+			Ok(c_name) => Some(lookup(&c_name)).filter(|&location| location >= 0),
+			Err(_) => None
+		};
+		if location.is_none()
+		{
+			// writing to -1 is silently ignored by gl, which hides typos and uniforms the compiler optimized out
+			println!("uniform {} is not active in the shader, ignoring it", name);
+		}
+		self.cache.borrow_mut().insert(name.to_string(), location);
+
+		location
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn looks_up_each_name_once()
+	{
+		let locations = Locations::default();
+		let mut lookups = Vec::new();
+		let mut lookup = |name: &str| {
+			locations.get(name, |c_name| {
+				lookups.push(c_name.to_str().unwrap().to_string());
+				if c_name.to_bytes() == b"model" { 3 } else { -1 }
+			})
+		};
+
+		assert_eq!(lookup("model"), Some(3));
+		assert_eq!(lookup("model"), Some(3));
+		assert_eq!(lookup("modle"), None);
+		assert_eq!(lookup("modle"), None);
+		assert_eq!(lookup("with\0nul"), None);
+		assert_eq!(lookups, vec!["model", "modle"]);
+	}
+}
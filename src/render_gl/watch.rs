@@ -0,0 +1,87 @@
+use std::time::SystemTime;
+use crate::resources::Resources;
+
+// The resources a program was built from, with when they were last written.
+// Polled instead of asking the os for notifications, it's a few files checked a couple of times a second
+#[derive(Clone, Debug)]
+pub struct Watch
+{
+	res: Resources,
+	// what the program was loaded as, "shaders/triangle"
+	name: String,
+	files: Vec<(String, Option<SystemTime>)>
+}
+
+impl Watch
+{
+	// call before reading the files, so a change made while they are read isn't missed
+	pub fn new(res: &Resources, name: &str, files: &[String]) -> Self
+	{
+		Watch {
+			res: res.clone(),
+			name: name.into(),
+			files: files.iter().map(|file| (file.clone(), res.modified(file))).collect()
+		}
+	}
+
+	pub fn resources(&self) -> &Resources
+	{
+		&self.res
+	}
+
+	pub fn name(&self) -> &str
+	{
+		&self.name
+	}
+
+	// true once for every time a file was written (or created or removed) since the last call
+	pub fn changed(&mut self) -> bool
+	{
+		let mut changed = false;
+		for (file, modified) in &mut self.files
+		{
+			let now = self.res.modified(file);
+			if now != *modified
+			{
+				*modified = now;
+				changed = true;
+			}
+		}
+
+		changed
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use std::fs;
+	use std::time::Duration;
+
+	#[test]
+	fn notices_written_files()
+	{
+		let root = std::env::temp_dir().join(format!("scop_watch_{}", std::process::id()));
+		fs::create_dir_all(root.join("shaders")).unwrap();
+		let vert = root.join("shaders/test.vert");
+		fs::write(&vert, "void main() {}").unwrap();
+		let _ = fs::remove_file(root.join("shaders/test.frag"));
+
+		let res = Resources::from_path(&root);
+		let files = ["shaders/test.vert".to_string(), "shaders/test.frag".to_string()];
+		let mut watch = Watch::new(&res, "shaders/test", &files);
+		assert!(!watch.changed());
+
+		// set explicitly, file systems with coarse timestamps could otherwise see the same time
+		let later = SystemTime::now() + Duration::from_secs(10);
+		fs::File::options().write(true).open(&vert).unwrap().set_modified(later).unwrap();
+		assert!(watch.changed());
+		assert!(!watch.changed());
+
+		fs::write(root.join("shaders/test.frag"), "void main() {}").unwrap();
+		assert!(watch.changed());
+
+		fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -2,6 +2,7 @@ use std::path::{Path, PathBuf};
 use std::fs;
 use std::io::{self, Read}; // shorthand for using both use std::io and use std::io::Read
 use std::ffi;
+use std::time::SystemTime;
 
 #[derive(Debug)]
 pub enum Error
@@ -19,6 +20,7 @@ impl From<io::Error> for Error
 	}
 }
 
+#[derive(Clone, Debug)]
 pub struct Resources
 {
 	root_path: PathBuf,
@@ -38,6 +40,20 @@ impl Resources
 		})
 	}
 
+	// resources straight from root_path, instead of next to the executable
+	pub fn from_path(root_path: &Path) -> Resources
+	{
+		Resources { root_path: root_path.into() }
+	}
+
+	// when the file was last written, None if it doesn't exist (or the platform doesn't know)
+	pub fn modified(&self, resource_name: &str) -> Option<SystemTime>
+	{
+		fs::metadata(resource_name_to_path(&self.root_path, resource_name))
+			.and_then(|metadata| metadata.modified())
+			.ok()
+	}
+
 	pub fn load_cstring(&self, resource_name: &str) -> Result<ffi::CString, Error>
 	{
 		let mut file = fs::File::open(
@@ -4,10 +4,17 @@ use crate::model;
 use crate::camera::Camera;
 use sdl2;
 
+// seconds between looking for edited shader files
+const SHADER_CHECK_INTERVAL: f32 = 0.5;
+
 pub struct Scene
 {
     models: Vec<model::Mesh>,
     projection: math::matrix::Matrix4,
+    // vertical field of view of the projection, in radians
+    fov: f32,
+    // time since the shader files were last checked
+    since_shader_check: f32,
     pub camera: Camera
 }
 
@@ -15,11 +22,14 @@ impl Scene
 {
     pub fn new(models: Vec<model::Mesh>, camera: Camera) -> Self
     {
-        let projection = math::matrix::Matrix4::new_perspective(45.0f32.to_radians(), 900.0/700.0, 0.1, 100.0);
+        let fov = 45.0f32.to_radians();
+        let projection = math::matrix::Matrix4::new_perspective(fov, 900.0/700.0, 0.1, 100.0);
 
         Self {
             models,
             projection,
+            fov,
+            since_shader_check: 0.0,
             camera
         }
     }
@@ -33,11 +43,32 @@ impl Scene
         }
     }
 
+    // dt is the time since the last frame, in seconds
+    pub fn update(&mut self, dt: f32)
+    {
+        for model in &mut self.models
+        {
+            model.update(dt);
+        }
+
+        self.since_shader_check += dt;
+        if self.since_shader_check >= SHADER_CHECK_INTERVAL
+        {
+            self.since_shader_check = 0.0;
+            for model in &mut self.models
+            {
+                model.reload_shader();
+            }
+        }
+    }
+
     pub fn draw(&self)
     {
         for model in &self.models
         {
-            model.render(self.camera.view(), &self.projection);
+            // fewer triangles the smaller the model is on screen
+            let lod = model.lod_for(self.camera.position(), self.fov);
+            model.render(self.camera.view(), &self.projection, lod);
         }
     }
 }
\ No newline at end of file